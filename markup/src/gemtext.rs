@@ -0,0 +1,206 @@
+use crate::inline::{is_linkable, replace_inline, strip_emphasis, InlineLink};
+
+/// Converts nonograph markup to gemtext (text/gemini). Headings, lists,
+/// quotes and fenced code map across directly; tables become preformatted
+/// blocks; inline links and media are collected into `=>` lines placed
+/// after the paragraph they appear in, since gemtext has no inline links.
+pub fn render_gemtext(content: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut links: Vec<(String, String)> = Vec::new();
+    let mut in_fence = false;
+    let mut in_table = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+
+        if in_fence {
+            if trimmed.starts_with("```") {
+                out.push("```".to_string());
+                in_fence = false;
+            } else {
+                out.push(line.to_string());
+            }
+            continue;
+        }
+
+        if trimmed.starts_with('|') {
+            if !in_table {
+                flush_links(&mut out, &mut links);
+                out.push("```".to_string());
+                in_table = true;
+            }
+            out.push(trimmed.trim_end().to_string());
+            continue;
+        } else if in_table {
+            out.push("```".to_string());
+            in_table = false;
+        }
+
+        if let Some(language) = trimmed.strip_prefix("```") {
+            flush_links(&mut out, &mut links);
+            out.push(format!("```{}", language.trim()));
+            in_fence = true;
+            continue;
+        }
+
+        // "// " lines are author comments and never published
+        if trimmed.starts_with("// ") {
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            flush_links(&mut out, &mut links);
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+            continue;
+        }
+
+        let (prefix, text) = line_prefix(trimmed);
+        let text = inline_text(text, &mut links);
+        out.push(format!("{}{}", prefix, text).trim_end().to_string());
+    }
+
+    if in_fence || in_table {
+        out.push("```".to_string());
+    }
+    flush_links(&mut out, &mut links);
+
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    let mut gemtext = out.join("\n");
+    gemtext.push('\n');
+    gemtext
+}
+
+/// Maps the block syntax at the start of a line to its gemtext equivalent.
+/// Gemtext only has three heading levels and one kind of list.
+fn line_prefix(line: &str) -> (&'static str, &str) {
+    for (marker, prefix) in [
+        ("#### ", "### "),
+        ("### ", "### "),
+        ("## ", "## "),
+        ("# ", "# "),
+    ] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return (prefix, rest.trim());
+        }
+    }
+
+    if let Some(rest) = line.strip_prefix('>') {
+        return ("> ", rest.trim_start());
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            if let Some(task) = rest.strip_prefix("[ ] ") {
+                return ("* ☐ ", task);
+            }
+            if let Some(task) = rest
+                .strip_prefix("[x] ")
+                .or_else(|| rest.strip_prefix("[X] "))
+            {
+                return ("* ☑ ", task);
+            }
+            return ("* ", rest);
+        }
+    }
+
+    // Plain lines that happen to start with gemtext syntax get a leading
+    // space so clients don't treat them as links or preformatting toggles
+    if line.starts_with("=>") {
+        return (" ", line);
+    }
+
+    ("", line)
+}
+
+/// Strips inline markup from a line, replacing links and media with their
+/// text and collecting their targets for `=>` lines.
+fn inline_text(line: &str, links: &mut Vec<(String, String)>) -> String {
+    replace_inline(line, |link| match link {
+        InlineLink::Link { label, url } => {
+            if is_linkable(url) {
+                links.push((url.to_string(), strip_emphasis(label.trim())));
+            }
+            label.to_string()
+        }
+        InlineLink::Media { alt, url } => {
+            if is_linkable(url) {
+                let label = if alt.is_empty() { "Image" } else { alt };
+                links.push((url.to_string(), strip_emphasis(label)));
+            }
+            alt.to_string()
+        }
+        InlineLink::Bare { url } => {
+            if is_linkable(url) {
+                links.push((url.to_string(), url.to_string()));
+            }
+            url.to_string()
+        }
+    })
+}
+
+fn flush_links(out: &mut Vec<String>, links: &mut Vec<(String, String)>) {
+    for (url, label) in links.drain(..) {
+        if label.is_empty() || label == url {
+            out.push(format!("=> {}", url));
+        } else {
+            out.push(format!("=> {} {}", url, label));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_structure() {
+        let source = "# Title\n#### Deep\n\n- one\n- [x] done\n* two\n\n> quoted\n\n// hidden note\n1. first";
+        assert_eq!(
+            render_gemtext(source),
+            "# Title\n### Deep\n\n* one\n* ☑ done\n* two\n\n> quoted\n\n1. first\n"
+        );
+    }
+
+    #[test]
+    fn test_links_follow_their_paragraph() {
+        let source = "Read [the docs](https://example.com/docs) and [https://example.org].\nSee ![a cat](/cat.png)\n\nNext paragraph";
+        assert_eq!(
+            render_gemtext(source),
+            "Read the docs and https://example.org.\nSee a cat\n=> https://example.com/docs the docs\n=> https://example.org\n=> /cat.png a cat\n\nNext paragraph\n"
+        );
+
+        // Unsafe targets keep their text but get no link line
+        assert_eq!(
+            render_gemtext("[click](javascript:void) [x](http://127.0.0.1/)"),
+            "click x\n"
+        );
+    }
+
+    #[test]
+    fn test_preformatted_blocks() {
+        let source = "```rust\nlet **x** = [a](b);\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\nafter";
+        assert_eq!(
+            render_gemtext(source),
+            "```rust\nlet **x** = [a](b);\n```\n\n```\n| a | b |\n|---|---|\n| 1 | 2 |\n```\nafter\n"
+        );
+        // Unterminated fences are closed
+        assert_eq!(render_gemtext("```\ncode"), "```\ncode\n```\n");
+    }
+
+    #[test]
+    fn test_inline_markup_is_stripped() {
+        assert_eq!(
+            render_gemtext("**bold** *it* _under_ ~del~ ^sup^ ==mark== `**code**`"),
+            "bold it under del sup mark `**code**`\n"
+        );
+        assert_eq!(
+            render_gemtext("snake_case_name 2*3*4 a note[^1]\n\n[^1]: The note"),
+            "snake_case_name 2*3*4 a note[1]\n\n[1]: The note\n"
+        );
+        assert_eq!(render_gemtext("=> not a link"), " => not a link\n");
+    }
+}
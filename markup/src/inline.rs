@@ -0,0 +1,146 @@
+use crate::parser::is_safe_url;
+
+/// Emphasis markers removed from inline text. `**` has to come before `*`.
+const EMPHASIS_MARKERS: &[&str] = &["**", "==", "*", "_", "~", "^"];
+
+/// A link or media reference found in a line of markup.
+pub(crate) enum InlineLink<'a> {
+    /// `[label](url)`
+    Link { label: &'a str, url: &'a str },
+    /// `![alt](url)`, with `alt` trimmed
+    Media { alt: &'a str, url: &'a str },
+    /// `[https://...]`
+    Bare { url: &'a str },
+}
+
+/// Flattens a line of markup to plain text for the text-based output
+/// formats. Each link or media reference is replaced by whatever `on_link`
+/// returns, footnote references become `[n]` and emphasis markers are removed.
+pub(crate) fn replace_inline(line: &str, mut on_link: impl FnMut(InlineLink) -> String) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(open) = rest.find('[') {
+        let is_media = open > 0 && rest[..open].ends_with('!');
+        let before = if is_media {
+            &rest[..open - 1]
+        } else {
+            &rest[..open]
+        };
+
+        let after_open = &rest[open + 1..];
+        let close = match after_open.find(']') {
+            Some(close) => close,
+            None => break,
+        };
+        let label = &after_open[..close];
+        let after_label = &after_open[close + 1..];
+
+        // [text](url) and ![alt](url)
+        if let Some(target) = after_label.strip_prefix('(') {
+            if let Some(end) = target.find(')') {
+                let url = target[..end].trim();
+                result.push_str(before);
+                if is_media {
+                    result.push_str(&on_link(InlineLink::Media {
+                        alt: label.trim(),
+                        url,
+                    }));
+                } else {
+                    result.push_str(&on_link(InlineLink::Link { label, url }));
+                }
+                rest = &target[end + 1..];
+                continue;
+            }
+        }
+
+        result.push_str(&rest[..open]);
+        if let Some(number) = label.strip_prefix('^') {
+            // Footnote reference
+            result.push('[');
+            result.push_str(number);
+            result.push(']');
+        } else if label.starts_with("http://") || label.starts_with("https://") {
+            result.push_str(&on_link(InlineLink::Bare { url: label }));
+        } else {
+            result.push('[');
+            result.push_str(label);
+            result.push(']');
+        }
+        rest = after_label;
+    }
+    result.push_str(rest);
+
+    strip_emphasis(&result)
+}
+
+/// Whether a link target may be shown as a link. `is_safe_url` lets every
+/// relative URL through, so "javascript:" and friends are rejected here: a
+/// colon before the first path separator is a scheme.
+pub(crate) fn is_linkable(url: &str) -> bool {
+    let scheme_end = url.find(['/', '?', '#']).unwrap_or(url.len());
+    let has_bare_scheme = !url.contains("://") && url[..scheme_end].contains(':');
+
+    !url.is_empty() && !url.contains(char::is_whitespace) && !has_bare_scheme && is_safe_url(url)
+}
+
+/// Removes paired emphasis markers outside of inline code spans.
+pub(crate) fn strip_emphasis(text: &str) -> String {
+    text.split('`')
+        .enumerate()
+        .map(|(i, segment)| {
+            if i % 2 == 1 {
+                return segment.to_string();
+            }
+            EMPHASIS_MARKERS
+                .iter()
+                .fold(segment.to_string(), |acc, marker| {
+                    strip_marker(&acc, marker)
+                })
+        })
+        .collect::<Vec<_>>()
+        .join("`")
+}
+
+fn strip_marker(text: &str, marker: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        if text[i..].starts_with(marker) && opens_emphasis(text, i, marker) {
+            if let Some(close) = find_closing_marker(text, i + marker.len(), marker) {
+                result.push_str(&text[i + marker.len()..close]);
+                i = close + marker.len();
+                continue;
+            }
+        }
+        let ch = text[i..].chars().next().unwrap_or_default();
+        result.push(ch);
+        i += ch.len_utf8();
+    }
+
+    result
+}
+
+fn opens_emphasis(text: &str, at: usize, marker: &str) -> bool {
+    let before = text[..at].chars().next_back();
+    let after = text[at + marker.len()..].chars().next();
+    before.is_none_or(|c| !c.is_alphanumeric()) && after.is_some_and(|c| !c.is_whitespace())
+}
+
+fn find_closing_marker(text: &str, from: usize, marker: &str) -> Option<usize> {
+    let mut j = from;
+    while j < text.len() {
+        if j > from && text[j..].starts_with(marker) {
+            let before = text[..j].chars().next_back();
+            let after = text[j + marker.len()..].chars().next();
+            if before.is_some_and(|c| !c.is_whitespace())
+                && after.is_none_or(|c| !c.is_alphanumeric())
+            {
+                return Some(j);
+            }
+        }
+        j += text[j..].chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
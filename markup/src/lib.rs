@@ -0,0 +1,32 @@
+//! Parser and HTML renderer for nonograph's markup dialect: Markdown-style
+//! emphasis plus `_underline_`, `~strike~`, `^super^`, `==highlight==`,
+//! `#secret#` spoilers, footnotes, pipe tables, task lists, media embeds and
+//! fenced code blocks with syntax highlighting.
+//!
+//! ```
+//! use nonograph_markup::render_markdown;
+//!
+//! let html = render_markdown("**bold** and ==marked==");
+//! assert!(html.contains("<strong>bold</strong>"));
+//! assert!(html.contains("<mark>marked</mark>"));
+//! ```
+//!
+//! All output goes through an ammonia allowlist before it is returned.
+//! Rendering is tuned with [`RenderOptions`]; syntax highlighting needs the
+//! `highlight` (default) or `highlight-pure` cargo feature.
+
+mod gemtext;
+mod inline;
+mod links;
+mod options;
+mod parser;
+mod plain;
+
+pub use gemtext::render_gemtext;
+pub use links::rewrite_url;
+pub use options::{Features, LinkOptions, MediaMode, MediaOptions, RenderOptions};
+pub use parser::{
+    html_attr_escape, html_escape, is_ip_blocked, is_safe_url, render_markdown,
+    render_markdown_with_options, sanitize_text,
+};
+pub use plain::render_plain_text;
@@ -0,0 +1,257 @@
+use url::Url;
+
+use crate::options::LinkOptions;
+
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "gclsrc", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "twclid",
+    "igshid", "mc_cid", "mc_eid", "mkt_tok", "_hsenc", "_hsmi", "ref_src", "ref_url",
+];
+
+const TRACKING_PARAM_PREFIXES: &[&str] = &["utm_"];
+
+/// (host, path, query parameter holding the real destination)
+const REDIRECTORS: &[(&str, &str, &str)] = &[
+    ("google.com", "/url", "q"),
+    ("google.com", "/url", "url"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("l.instagram.com", "/", "u"),
+    ("youtube.com", "/redirect", "q"),
+    ("t.umblr.com", "/redirect", "z"),
+    ("out.reddit.com", "/", "url"),
+    ("slack-redir.net", "/link", "url"),
+    ("steamcommunity.com", "/linkfilter/", "url"),
+    ("href.li", "/", ""),
+];
+
+const MAX_UNWRAP_DEPTH: usize = 5;
+
+/// Applies the configured rewriting to a link target. Only absolute http(s)
+/// URLs are touched, and a URL that needs no changes is returned exactly as
+/// written so rendering stays byte-for-byte stable.
+pub fn rewrite_url(url: &str, links: &LinkOptions) -> String {
+    let mut parsed = match Url::parse(url) {
+        Ok(u) if u.scheme() == "http" || u.scheme() == "https" => u,
+        _ => return url.to_string(),
+    };
+    let mut changed = false;
+
+    if links.unwrap_redirects {
+        for _ in 0..MAX_UNWRAP_DEPTH {
+            match unwrap_redirect(&parsed) {
+                Some(target) => {
+                    parsed = target;
+                    changed = true;
+                }
+                None => break,
+            }
+        }
+    }
+
+    if links.strip_tracking_params && strip_tracking_params(&mut parsed) {
+        changed = true;
+    }
+
+    if apply_domain_rules(&mut parsed, &links.domain_rewrites) {
+        changed = true;
+    }
+
+    if changed {
+        parsed.to_string()
+    } else {
+        url.to_string()
+    }
+}
+
+fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_prefix("www.") == Some(domain)
+}
+
+fn unwrap_redirect(url: &Url) -> Option<Url> {
+    let host = url.host_str()?.to_ascii_lowercase();
+
+    for (domain, path, param) in REDIRECTORS {
+        if !host_matches(&host, domain) || url.path() != *path {
+            continue;
+        }
+
+        // href.li puts the whole destination after the "?"
+        let target = if param.is_empty() {
+            url.query().map(|q| q.to_string())
+        } else {
+            url.query_pairs()
+                .find(|(key, _)| key == param)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        if let Some(target) = target {
+            if let Ok(parsed) = Url::parse(&target) {
+                if parsed.scheme() == "http" || parsed.scheme() == "https" {
+                    return Some(parsed);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    TRACKING_PARAMS.contains(&key.as_str())
+        || TRACKING_PARAM_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+}
+
+fn strip_tracking_params(url: &mut Url) -> bool {
+    let query = match url.query() {
+        Some(q) => q.to_string(),
+        None => return false,
+    };
+
+    // Work on the raw query so the parameters we keep retain their encoding
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            !is_tracking_param(key)
+        })
+        .collect();
+
+    if kept.len() == query.split('&').count() {
+        return false;
+    }
+
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.set_query(Some(&kept.join("&")));
+    }
+    true
+}
+
+fn apply_domain_rules(url: &mut Url, rules: &[(String, String)]) -> bool {
+    let host = match url.host_str() {
+        Some(h) => h.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    let replacement = match rules.iter().find(|(from, _)| host_matches(&host, from)) {
+        Some((_, to)) => to,
+        None => return false,
+    };
+
+    if replacement.contains("://") {
+        let base = match Url::parse(replacement) {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+        if url.set_scheme(base.scheme()).is_err() {
+            return false;
+        }
+        if url.set_host(base.host_str()).is_err() {
+            return false;
+        }
+        let _ = url.set_port(base.port());
+    } else if url.set_host(Some(replacement)).is_err() {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links() -> LinkOptions {
+        LinkOptions {
+            strip_tracking_params: true,
+            unwrap_redirects: true,
+            domain_rewrites: vec![
+                ("twitter.com".to_string(), "nitter.net".to_string()),
+                (
+                    "example.org".to_string(),
+                    "http://exampleabcdef.onion".to_string(),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_strip_tracking_params() {
+        let links = links();
+        assert_eq!(
+            rewrite_url(
+                "https://news.example.com/story?id=7&utm_source=x&utm_medium=y&fbclid=abc",
+                &links
+            ),
+            "https://news.example.com/story?id=7"
+        );
+        assert_eq!(
+            rewrite_url("https://shop.example.com/?gclid=123", &links),
+            "https://shop.example.com/"
+        );
+        // Untouched URLs come back exactly as written
+        assert_eq!(
+            rewrite_url("https://example.com?q=a%20b", &links),
+            "https://example.com?q=a%20b"
+        );
+        assert_eq!(
+            rewrite_url("/relative?utm_source=x", &links),
+            "/relative?utm_source=x"
+        );
+    }
+
+    #[test]
+    fn test_unwrap_redirects() {
+        let links = links();
+        assert_eq!(
+            rewrite_url(
+                "https://www.google.com/url?q=https%3A%2F%2Fblog.example.com%2Fpost%3Futm_campaign%3Dz&sa=D",
+                &links
+            ),
+            "https://blog.example.com/post"
+        );
+        assert_eq!(
+            rewrite_url(
+                "https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2Fa&h=AT0",
+                &links
+            ),
+            "https://example.com/a"
+        );
+        // Redirect wrappers pointing at non-http targets are left alone
+        assert_eq!(
+            rewrite_url("https://www.google.com/url?q=javascript:alert(1)", &links),
+            "https://www.google.com/url?q=javascript:alert(1)"
+        );
+    }
+
+    #[test]
+    fn test_domain_rules() {
+        let links = links();
+        assert_eq!(
+            rewrite_url("https://twitter.com/user/status/1", &links),
+            "https://nitter.net/user/status/1"
+        );
+        assert_eq!(
+            rewrite_url("https://www.example.org/page?utm_source=a", &links),
+            "http://exampleabcdef.onion/page"
+        );
+        assert_eq!(
+            rewrite_url("https://notexample.org/", &links),
+            "https://notexample.org/"
+        );
+    }
+
+    #[test]
+    fn test_disabled_stages() {
+        let mut links = links();
+        links.strip_tracking_params = false;
+        links.unwrap_redirects = false;
+        links.domain_rewrites.clear();
+        let url = "https://www.google.com/url?q=https://example.com&utm_source=x";
+        assert_eq!(rewrite_url(url, &links), url);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+/// Everything that changes how a document renders. Every field has a default,
+/// and missing fields fall back to it when deserialising, so callers only
+/// need to set what they care about:
+///
+/// ```
+/// use nonograph_markup::{render_markdown_with_options, RenderOptions};
+///
+/// let mut options = RenderOptions::default();
+/// options.features.tables = false;
+/// let html = render_markdown_with_options("| a | b |\n|---|---|\n| 1 | 2 |", &options);
+/// assert!(!html.contains("<table>"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderOptions {
+    /// Name of the syntect theme used for fenced code blocks. Unknown names
+    /// fall back to `base16-ocean.dark`.
+    pub theme: String,
+    /// Link and image URLs longer than this are left as plain text.
+    pub max_url_length: usize,
+    /// Opens links in a new tab (`target="_blank"`).
+    pub external_link_security: bool,
+    pub media: MediaOptions,
+    pub links: LinkOptions,
+    pub features: Features,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            theme: "base16-ocean.dark".to_string(),
+            max_url_length: 4096,
+            external_link_security: true,
+            media: MediaOptions::default(),
+            links: LinkOptions::default(),
+            features: Features::default(),
+        }
+    }
+}
+
+/// Parts of the dialect that can be switched off. Disabled syntax is left in
+/// the output as the text the author typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Features {
+    /// `| a | b |` pipe tables
+    pub tables: bool,
+    /// `[^1]` references and their definitions
+    pub footnotes: bool,
+    /// `![alt](url)` images and videos. When off they render as links.
+    pub media: bool,
+    /// `#hidden#` click-to-reveal text
+    pub secrets: bool,
+    /// Colours fenced code blocks. Has no effect unless the crate was built
+    /// with the `highlight` or `highlight-pure` feature.
+    pub syntax_highlighting: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            tables: true,
+            footnotes: true,
+            media: true,
+            secrets: true,
+            syntax_highlighting: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaMode {
+    Embed,
+    Placeholder,
+    Link,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaOptions {
+    /// How third-party media is shown. Relative URLs always embed.
+    pub mode: MediaMode,
+    /// Hosts (and their subdomains) that always embed regardless of `mode`
+    #[serde(default)]
+    pub embed_hosts: Vec<String>,
+}
+
+impl Default for MediaOptions {
+    fn default() -> Self {
+        MediaOptions {
+            mode: MediaMode::Embed,
+            embed_hosts: Vec::new(),
+        }
+    }
+}
+
+impl MediaOptions {
+    /// Returns how media from `host` should be rendered. Hosts on the
+    /// allowlist (or any of their subdomains) always embed directly.
+    pub fn mode_for_host(&self, host: &str) -> MediaMode {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let allowed = self.embed_hosts.iter().any(|entry| {
+            let entry = entry.trim().trim_end_matches('.').to_ascii_lowercase();
+            !entry.is_empty() && (host == entry || host.ends_with(&format!(".{}", entry)))
+        });
+
+        if allowed {
+            MediaMode::Embed
+        } else {
+            self.mode
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkOptions {
+    /// Removes `utm_*`, `fbclid` and similar tracking parameters
+    pub strip_tracking_params: bool,
+    /// Replaces known redirect wrappers (google.com/url, l.facebook.com, ...)
+    /// with their destination
+    pub unwrap_redirects: bool,
+    /// `(host, replacement)` pairs. The replacement is either a host or a
+    /// base URL such as `http://abc...xyz.onion`.
+    pub domain_rewrites: Vec<(String, String)>,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            strip_tracking_params: true,
+            unwrap_redirects: true,
+            domain_rewrites: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_mode_for_host() {
+        let media = MediaOptions {
+            mode: MediaMode::Placeholder,
+            embed_hosts: vec!["i.imgur.com".to_string(), "Example.org.".to_string()],
+        };
+
+        assert_eq!(media.mode_for_host("i.imgur.com"), MediaMode::Embed);
+        assert_eq!(media.mode_for_host("example.org"), MediaMode::Embed);
+        assert_eq!(media.mode_for_host("cdn.example.org"), MediaMode::Embed);
+        assert_eq!(media.mode_for_host("imgur.com"), MediaMode::Placeholder);
+        assert_eq!(
+            media.mode_for_host("badexample.org"),
+            MediaMode::Placeholder
+        );
+    }
+
+    #[test]
+    fn test_partial_options_deserialize() {
+        let options: RenderOptions =
+            serde_json::from_str(r#"{"max_url_length": 10, "features": {"tables": false}}"#)
+                .unwrap();
+        assert_eq!(options.max_url_length, 10);
+        assert!(!options.features.tables);
+        assert!(options.features.footnotes);
+        assert_eq!(options.theme, RenderOptions::default().theme);
+        assert_eq!(options.media.mode, MediaMode::Embed);
+    }
+}
@@ -1,16 +1,22 @@
 use std::net::IpAddr;
+#[cfg(feature = "syntect")]
 use syntect::easy::HighlightLines;
+#[cfg(feature = "syntect")]
 use syntect::highlighting::ThemeSet;
+#[cfg(feature = "syntect")]
 use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+#[cfg(feature = "syntect")]
 use syntect::parsing::SyntaxSet;
+#[cfg(feature = "syntect")]
 use syntect::util::LinesWithEndings;
 use url::Url;
 
-fn process_images(text: &str) -> String {
-    process_images_with_config(text, &crate::config::Config::default())
-}
+use crate::links::rewrite_url;
+use crate::options::{MediaMode, RenderOptions};
 
-fn is_ip_blocked(ip: IpAddr) -> bool {
+/// Returns true for loopback, private, link-local, CGNAT, documentation,
+/// multicast and reserved addresses, including IPv4 embedded in IPv6.
+pub fn is_ip_blocked(ip: IpAddr) -> bool {
     match ip {
         IpAddr::V4(v4) => {
             let o = v4.octets();
@@ -38,8 +44,31 @@ fn is_ip_blocked(ip: IpAddr) -> bool {
             if o[0] == 169 && o[1] == 254 {
                 return true;
             }
-            // Broadcast / limited broadcast
-            if o == [255, 255, 255, 255] {
+            // Carrier-grade NAT: 100.64.0.0/10
+            if o[0] == 100 && (o[1] & 0xc0) == 64 {
+                return true;
+            }
+            // IETF protocol assignments: 192.0.0.0/24
+            if o[0] == 192 && o[1] == 0 && o[2] == 0 {
+                return true;
+            }
+            // Documentation: 192.0.2.0/24, 198.51.100.0/24, 203.0.113.0/24
+            if (o[0] == 192 && o[1] == 0 && o[2] == 2)
+                || (o[0] == 198 && o[1] == 51 && o[2] == 100)
+                || (o[0] == 203 && o[1] == 0 && o[2] == 113)
+            {
+                return true;
+            }
+            // Benchmarking: 198.18.0.0/15
+            if o[0] == 198 && (o[1] == 18 || o[1] == 19) {
+                return true;
+            }
+            // Multicast: 224.0.0.0/4
+            if (224..=239).contains(&o[0]) {
+                return true;
+            }
+            // Reserved: 240.0.0.0/4 (includes limited broadcast)
+            if o[0] >= 240 {
                 return true;
             }
             false
@@ -70,12 +99,33 @@ fn is_ip_blocked(ip: IpAddr) -> bool {
             if first & 0xffc0 == 0xfe80 {
                 return true;
             }
+            // Multicast: ff00::/8
+            if first & 0xff00 == 0xff00 {
+                return true;
+            }
+            // Documentation: 2001:db8::/32
+            if first == 0x2001 && v6.segments()[1] == 0x0db8 {
+                return true;
+            }
+            // NAT64 (64:ff9b::/96) — check the embedded v4 address
+            let segments = v6.segments();
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let embedded = std::net::Ipv4Addr::new(
+                    (segments[6] >> 8) as u8,
+                    segments[6] as u8,
+                    (segments[7] >> 8) as u8,
+                    segments[7] as u8,
+                );
+                return is_ip_blocked(IpAddr::V4(embedded));
+            }
             false
         }
     }
 }
 
-fn is_safe_url(url: &str) -> bool {
+/// Relative URLs are allowed. Absolute ones must be http(s) and must not
+/// point at localhost or a blocked IP literal.
+pub fn is_safe_url(url: &str) -> bool {
     if !url.contains("://") {
         return true;
     }
@@ -126,12 +176,14 @@ fn is_safe_url(url: &str) -> bool {
     true
 }
 
-#[allow(dead_code)]
+/// Renders markup to sanitised HTML with [`RenderOptions::default`].
 pub fn render_markdown(content: &str) -> String {
-    render_markdown_with_config(content, &crate::config::Config::default())
+    render_markdown_with_options(content, &RenderOptions::default())
 }
 
-pub fn render_markdown_with_config(content: &str, config: &crate::config::Config) -> String {
+/// Renders markup to sanitised HTML. The output only contains tags and
+/// attributes on the ammonia allowlist, so it is safe to embed as-is.
+pub fn render_markdown_with_options(content: &str, options: &RenderOptions) -> String {
     let cleaned_content = remove_standalone_list_tags(content);
 
     let (protected_content, fenced_blocks) = extract_fenced_code_blocks(&cleaned_content);
@@ -147,7 +199,9 @@ pub fn render_markdown_with_config(content: &str, config: &crate::config::Config
     working_content = working_content_no_links;
 
     // Process footnotes before text formatting to avoid conflicts with ^ and []
-    working_content = process_footnotes(&working_content);
+    if options.features.footnotes {
+        working_content = process_footnotes(&working_content);
+    }
 
     working_content = safe_replace(&working_content, "**", "**", "<strong>", "</strong>");
     working_content = safe_replace(&working_content, "*", "*", "<em>", "</em>");
@@ -155,31 +209,37 @@ pub fn render_markdown_with_config(content: &str, config: &crate::config::Config
     working_content = safe_replace(&working_content, "~", "~", "<del>", "</del>");
     working_content = safe_replace(&working_content, "^", "^", "<sup>", "</sup>");
     working_content = safe_replace(&working_content, "==", "==", "<mark>", "</mark>");
-    working_content = safe_replace(
-        &working_content,
-        "#",
-        "#",
-        "<span class=\"secret\">",
-        "</span>",
-    );
+    if options.features.secrets {
+        working_content = safe_replace(
+            &working_content,
+            "#",
+            "#",
+            "<span class=\"secret\">",
+            "</span>",
+        );
+    }
 
     working_content = restore_media_syntax(&working_content, &media_blocks);
     working_content = restore_link_syntax(&working_content, &link_blocks);
-    working_content = process_images(&working_content);
-    working_content = process_links(&working_content);
-    working_content = process_tables(&working_content);
+    working_content = process_images_with_options(&working_content, options);
+    working_content = process_links_with_options(&working_content, options);
+    if options.features.tables {
+        working_content = process_tables(&working_content);
+    }
     working_content = process_lists(&working_content);
     working_content = process_dividers(&working_content);
     working_content = format_paragraphs_with_headers(&working_content);
     working_content =
-        restore_fenced_code_blocks_with_config(&working_content, &fenced_blocks, config);
+        restore_fenced_code_blocks_with_options(&working_content, &fenced_blocks, options);
     working_content = restore_code_blocks(&working_content, &code_blocks);
-    working_content = restore_footnotes(&working_content);
+    if options.features.footnotes {
+        working_content = restore_footnotes(&working_content);
+    }
 
     sanitize_html(working_content)
 }
 
-fn process_images_with_config(text: &str, config: &crate::config::Config) -> String {
+fn process_images_with_options(text: &str, options: &RenderOptions) -> String {
     let mut result = String::with_capacity(text.len() + 1024);
     let chars: Vec<char> = text.chars().collect();
     let mut i = 0;
@@ -216,51 +276,19 @@ fn process_images_with_config(text: &str, config: &crate::config::Config) -> Str
                             chars[(bracket_end_idx + 2)..paren_end_idx].iter().collect();
 
                         if !image_url.is_empty()
-                            && image_url.len() <= config.security.max_url_length
+                            && image_url.len() <= options.max_url_length
                             && is_safe_url(&image_url)
                         {
-                            let is_video = is_video_url(&image_url);
-
-                            // Check if alt text is present for caption
-                            if !alt_text.trim().is_empty() {
-                                result.push_str("<div class=\"media-with-caption\">");
-                                if is_video {
-                                    result.push_str("<video controls style=\"width: 100%;\">");
-                                    result.push_str("<source src=\"");
-                                    result.push_str(&html_escape(&image_url));
-                                    result.push_str("\" type=\"");
-                                    result.push_str(&get_video_mime_type(&image_url));
-                                    result.push_str("\">");
-                                    result.push_str("Your browser does not support the video tag.");
-                                    result.push_str("</video>");
-                                } else {
-                                    result.push_str("<img src=\"");
-                                    result.push_str(&html_escape(&image_url));
-                                    result.push_str("\" alt=\"");
-                                    result.push_str(&html_escape(&alt_text));
-                                    result.push_str("\">");
-                                }
-                                result.push_str("<div class=\"media-caption\">");
-                                result.push_str(&html_escape(&alt_text));
-                                result.push_str("</div>");
-                                result.push_str("</div>");
+                            if options.features.media {
+                                result.push_str(&render_media(&image_url, &alt_text, options));
                             } else {
-                                if is_video {
-                                    result.push_str("<video controls style=\"width: 100%;\">");
-                                    result.push_str("<source src=\"");
-                                    result.push_str(&html_escape(&image_url));
-                                    result.push_str("\" type=\"");
-                                    result.push_str(&get_video_mime_type(&image_url));
-                                    result.push_str("\">");
-                                    result.push_str("Your browser does not support the video tag.");
-                                    result.push_str("</video>");
+                                // Left for the link pass, so media becomes a plain link
+                                let label = if alt_text.trim().is_empty() {
+                                    &image_url
                                 } else {
-                                    result.push_str("<img src=\"");
-                                    result.push_str(&html_escape(&image_url));
-                                    result.push_str("\" alt=\"");
-                                    result.push_str(&html_escape(&alt_text));
-                                    result.push_str("\">");
-                                }
+                                    &alt_text
+                                };
+                                result.push_str(&format!("[{}]({})", label, image_url));
                             }
 
                             i = paren_end_idx + 1;
@@ -279,6 +307,97 @@ fn process_images_with_config(text: &str, config: &crate::config::Config) -> Str
     result
 }
 
+fn render_media(url: &str, alt_text: &str, options: &RenderOptions) -> String {
+    // Relative URLs point at this instance, so they never count as third-party
+    let host = if url.contains("://") {
+        Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+    } else {
+        None
+    };
+    let mode = match &host {
+        Some(host) => options.media.mode_for_host(host),
+        None => MediaMode::Embed,
+    };
+    let host = host.unwrap_or_default();
+    let is_video = is_video_url(url);
+    let caption = alt_text.trim();
+
+    match mode {
+        MediaMode::Embed => render_media_element(url, alt_text, is_video),
+        MediaMode::Link => {
+            let label = if caption.is_empty() {
+                host.as_str()
+            } else {
+                caption
+            };
+            format!(
+                "<a href=\"{}\" class=\"media-link\">{}</a>",
+                html_escape(url),
+                html_escape(label)
+            )
+        }
+        MediaMode::Placeholder => {
+            // Only a link to the media: browsers turn lazy loading off when
+            // scripting is disabled, so any <img> here would load with the
+            // page. With scripting, the post page swaps the media in on click.
+            let kind = if is_video { "video" } else { "image" };
+            let mut card = String::from("<div class=\"media-placeholder\"><a href=\"");
+            card.push_str(&html_escape(url));
+            card.push_str("\" class=\"media-placeholder-link media-placeholder-");
+            card.push_str(kind);
+            card.push_str("\"><span class=\"media-placeholder-kind\">");
+            card.push_str(if is_video { "Video" } else { "Image" });
+            card.push_str(" from ");
+            card.push_str(&html_escape(&host));
+            card.push_str("</span>");
+            if !caption.is_empty() {
+                card.push_str("<span class=\"media-placeholder-caption\">");
+                card.push_str(&html_escape(caption));
+                card.push_str("</span>");
+            }
+            card.push_str("<span class=\"media-placeholder-action\">Click to load</span>");
+            card.push_str("</a></div>");
+            card
+        }
+    }
+}
+
+fn render_media_element(url: &str, alt_text: &str, is_video: bool) -> String {
+    let mut result = String::new();
+
+    // Check if alt text is present for caption
+    let has_caption = !alt_text.trim().is_empty();
+    if has_caption {
+        result.push_str("<div class=\"media-with-caption\">");
+    }
+    if is_video {
+        result.push_str("<video controls style=\"width: 100%;\">");
+        result.push_str("<source src=\"");
+        result.push_str(&html_escape(url));
+        result.push_str("\" type=\"");
+        result.push_str(get_video_mime_type(url));
+        result.push_str("\">");
+        result.push_str("Your browser does not support the video tag.");
+        result.push_str("</video>");
+    } else {
+        result.push_str("<img src=\"");
+        result.push_str(&html_escape(url));
+        result.push_str("\" alt=\"");
+        result.push_str(&html_escape(alt_text));
+        result.push_str("\">");
+    }
+    if has_caption {
+        result.push_str("<div class=\"media-caption\">");
+        result.push_str(&html_escape(alt_text));
+        result.push_str("</div>");
+        result.push_str("</div>");
+    }
+
+    result
+}
+
 fn is_video_url(url: &str) -> bool {
     let video_extensions = ["mp4", "webm", "ogg", "mov", "avi", "mkv"];
     let lower_url = url.to_lowercase();
@@ -306,10 +425,6 @@ fn get_video_mime_type(url: &str) -> &'static str {
     }
 }
 
-fn process_links(text: &str) -> String {
-    process_links_with_config(text, &crate::config::Config::default())
-}
-
 fn safe_replace(
     text: &str,
     start_pattern: &str,
@@ -430,6 +545,9 @@ fn sanitize_html(html: String) -> String {
     builder.clean(&html).to_string()
 }
 
+/// Strips every HTML tag from plain-text fields such as titles and author
+/// names and flattens them to a single line. The result still needs escaping
+/// before it goes into HTML.
 pub fn sanitize_text(text: &str) -> String {
     let builder = ammonia::Builder::empty();
     let sanitized = builder.clean(text).to_string();
@@ -444,6 +562,8 @@ pub fn sanitize_text(text: &str) -> String {
 }
 
 // Thanks for the code. You know who you are.
+/// Like [`html_escape`], but also flattens newlines so the value stays on
+/// one line inside an attribute.
 pub fn html_attr_escape(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -702,6 +822,7 @@ fn map_language_for_css(lang: &str) -> &str {
     }
 }
 
+#[cfg(feature = "syntect")]
 fn map_language_for_syntect(lang: &str) -> &str {
     match lang.to_lowercase().as_str() {
         // Primary languages
@@ -833,7 +954,7 @@ fn map_language_for_syntect(lang: &str) -> &str {
 
 #[allow(dead_code)]
 fn restore_fenced_code_blocks(text: &str, fenced_blocks: &[(String, String, u32)]) -> String {
-    restore_fenced_code_blocks_with_config(text, fenced_blocks, &crate::config::Config::default())
+    restore_fenced_code_blocks_with_options(text, fenced_blocks, &RenderOptions::default())
 }
 
 fn process_lists(text: &str) -> String {
@@ -1102,10 +1223,26 @@ fn render_list_tree(items: &[ListItem]) -> String {
     }
 }
 
-fn restore_fenced_code_blocks_with_config(
+fn restore_fenced_code_blocks_with_options(
     text: &str,
     fenced_blocks: &[(String, String, u32)],
-    config: &crate::config::Config,
+    options: &RenderOptions,
+) -> String {
+    #[cfg(feature = "syntect")]
+    if options.features.syntax_highlighting {
+        return highlight_fenced_code_blocks(text, fenced_blocks, &options.theme);
+    }
+    #[cfg(not(feature = "syntect"))]
+    let _ = options;
+
+    plain_fenced_code_blocks(text, fenced_blocks)
+}
+
+#[cfg(feature = "syntect")]
+fn highlight_fenced_code_blocks(
+    text: &str,
+    fenced_blocks: &[(String, String, u32)],
+    theme_name: &str,
 ) -> String {
     let mut result = text.to_string();
 
@@ -1113,16 +1250,13 @@ fn restore_fenced_code_blocks_with_config(
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
 
-    let theme = ts
-        .themes
-        .get(&config.theme.syntax_highlighting)
-        .unwrap_or_else(|| {
-            eprintln!(
-                "Warning: Theme '{}' not found, falling back to 'base16-ocean.dark'",
-                config.theme.syntax_highlighting
-            );
-            &ts.themes["base16-ocean.dark"]
-        });
+    let theme = ts.themes.get(theme_name).unwrap_or_else(|| {
+        eprintln!(
+            "Warning: Theme '{}' not found, falling back to 'base16-ocean.dark'",
+            theme_name
+        );
+        &ts.themes["base16-ocean.dark"]
+    });
 
     for (index, (language, code_content, line_count)) in fenced_blocks.iter().enumerate() {
         let placeholder = format!("{{{{FENCEDBLOCK{}}}}}", index);
@@ -1144,6 +1278,28 @@ fn restore_fenced_code_blocks_with_config(
     result
 }
 
+/// Used when highlighting is switched off or the crate was built without
+/// syntect: code blocks keep the same markup but the lines are only escaped,
+/// not coloured.
+fn plain_fenced_code_blocks(text: &str, fenced_blocks: &[(String, String, u32)]) -> String {
+    let mut result = text.to_string();
+
+    for (index, (language, code_content, line_count)) in fenced_blocks.iter().enumerate() {
+        let placeholder = format!("{{{{FENCEDBLOCK{}}}}}", index);
+
+        let highlighted_code: String = code_content
+            .split_inclusive('\n')
+            .map(|line| format!("<span class=\"code-line\">{}</span>", html_escape(line)))
+            .collect();
+
+        let replacement = code_block_html(language, None, &highlighted_code, *line_count);
+        result = result.replace(&placeholder, &replacement);
+    }
+
+    result
+}
+
+#[cfg(feature = "syntect")]
 fn render_code_block(
     syntax_set: &SyntaxSet,
     theme: &syntect::highlighting::Theme,
@@ -1190,6 +1346,23 @@ fn render_code_block(
         highlighted_code.push_str(&format!("<span class=\"code-line\">{}</span>", line_html));
     }
 
+    let detected = if auto_detected && syntax.name != "Plain Text" {
+        Some(syntax.name.as_str())
+    } else {
+        None
+    };
+
+    code_block_html(original_language, detected, &highlighted_code, line_count)
+}
+
+/// Wraps already highlighted (or escaped) `code-line` spans in the code block
+/// chrome: language label, wrap/collapse/copy buttons and line numbers.
+fn code_block_html(
+    original_language: &str,
+    detected_language: Option<&str>,
+    highlighted_code: &str,
+    line_count: u32,
+) -> String {
     // Generate line numbers
     let line_numbers = (1..=line_count)
         .map(|i| format!("<span class=\"line-number\">{}</span>", i))
@@ -1198,10 +1371,10 @@ fn render_code_block(
 
     // Create the complete HTML structure
     let css_lang = map_language_for_css(original_language);
-    let lang_display = if auto_detected && syntax.name != "Plain Text" {
+    let lang_display = if let Some(detected) = detected_language {
         format!(
             "<span class=\"code-language code-language-detected\" title=\"auto-detected\">{}</span>",
-            syntax.name.to_uppercase()
+            detected.to_uppercase()
         )
     } else if css_lang.is_empty() {
         String::new()
@@ -1286,6 +1459,7 @@ fn format_paragraphs_with_headers(text: &str) -> String {
             || trimmed.starts_with("{{CODEBLOCK")
             || trimmed.starts_with("<img ")
             || trimmed.starts_with("<video ")
+            || trimmed.starts_with("<div class=\"media-placeholder\">")
             || trimmed.starts_with("<table>")
         {
             result.push_str(trimmed);
@@ -1542,6 +1716,7 @@ fn parse_table_alignments(separator: &str) -> Vec<TableAlignment> {
         .collect()
 }
 
+/// Escapes `&`, `<`, `>` and both quote characters.
 pub fn html_escape(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -1902,7 +2077,7 @@ fn restore_footnotes(text: &str) -> String {
     result
 }
 
-fn process_links_with_config(text: &str, config: &crate::config::Config) -> String {
+fn process_links_with_options(text: &str, options: &RenderOptions) -> String {
     let mut result = String::with_capacity(text.len() + 1024);
     let chars: Vec<char> = text.chars().collect();
     let mut i = 0;
@@ -1940,11 +2115,11 @@ fn process_links_with_config(text: &str, config: &crate::config::Config) -> Stri
 
                         if !link_text.is_empty()
                             && !link_url.is_empty()
-                            && link_url.len() <= config.security.max_url_length
+                            && link_url.len() <= options.max_url_length
                         {
                             result.push_str("<a href=\"");
-                            result.push_str(&link_url);
-                            if config.security.external_link_security {
+                            result.push_str(&rewrite_url(&link_url, &options.links));
+                            if options.external_link_security {
                                 result.push_str("\" target=\"_blank\">");
                             } else {
                                 result.push_str("\">");
@@ -1959,16 +2134,16 @@ fn process_links_with_config(text: &str, config: &crate::config::Config) -> Stri
 
                 // Check for [url] pattern (bare URL in brackets)
                 let link_url: String = chars[(i + 1)..bracket_end_idx].iter().collect();
-                if link_url.len() <= config.security.max_url_length && link_url.starts_with("http")
-                {
+                if link_url.len() <= options.max_url_length && link_url.starts_with("http") {
+                    let rewritten_url = rewrite_url(&link_url, &options.links);
                     result.push_str("<a href=\"");
-                    result.push_str(&link_url);
-                    if config.security.external_link_security {
+                    result.push_str(&rewritten_url);
+                    if options.external_link_security {
                         result.push_str("\" target=\"_blank\">");
                     } else {
                         result.push_str("\">");
                     }
-                    result.push_str(&link_url);
+                    result.push_str(&rewritten_url);
                     result.push_str("</a>");
                     i = bracket_end_idx + 1;
                     continue;
@@ -2334,29 +2509,25 @@ var x = 1;
     #[test]
     fn test_theme_configuration() {
         // Test with valid theme
-        let config = crate::config::Config {
-            theme: crate::config::Theme {
-                syntax_highlighting: "Solarized (light)".to_string(),
-            },
+        let options = RenderOptions {
+            theme: "Solarized (light)".to_string(),
             ..Default::default()
         };
 
         let code = "```rust\nlet x = 5;\n```";
-        let result = render_markdown_with_config(code, &config);
+        let result = render_markdown_with_options(code, &options);
         assert_code_block_structure(&result, Some("rust"), true);
         assert!(result.contains("let"));
         assert!(result.contains("x"));
         assert!(result.contains("5"));
 
         // Test with invalid theme (should fall back to default)
-        let invalid_config = crate::config::Config {
-            theme: crate::config::Theme {
-                syntax_highlighting: "NonExistentTheme".to_string(),
-            },
+        let invalid_options = RenderOptions {
+            theme: "NonExistentTheme".to_string(),
             ..Default::default()
         };
 
-        let invalid_result = render_markdown_with_config(code, &invalid_config);
+        let invalid_result = render_markdown_with_options(code, &invalid_options);
         assert_code_block_structure(&invalid_result, Some("rust"), true);
         assert!(invalid_result.contains("let"));
         assert!(invalid_result.contains("x"));
@@ -3890,6 +4061,16 @@ Final paragraph with normal text."#;
             "http://[::ffff:127.0.0.1]/",
             "http://[fc00::1]/",
             "http://[fe80::1]/",
+            "http://100.64.0.1/",
+            "http://192.0.2.1/",
+            "http://198.51.100.7/",
+            "http://203.0.113.9/",
+            "http://198.18.0.1/",
+            "http://224.0.0.1/",
+            "http://240.0.0.1/",
+            "http://[ff02::1]/",
+            "http://[2001:db8::1]/",
+            "http://[64:ff9b::7f00:1]/",
         ];
 
         for dangerous_url in dangerous_urls {
@@ -4035,4 +4216,109 @@ Final paragraph with normal text."#;
         assert!(multiple_result.contains("<hr class=\"divider-thin\">"));
         assert!(multiple_result.contains("<hr class=\"divider-double\">"));
     }
+
+    #[test]
+    fn test_media_placeholder_mode() {
+        let mut options = RenderOptions::default();
+        options.media.mode = MediaMode::Placeholder;
+        options.media.embed_hosts = vec!["trusted.example".to_string()];
+
+        let result =
+            render_markdown_with_options("![A cat](https://images.example.com/cat.jpg)", &options);
+        assert!(result.contains(
+            "<div class=\"media-placeholder\"><a href=\"https://images.example.com/cat.jpg\" class=\"media-placeholder-link media-placeholder-image\""
+        ));
+        assert!(result.contains("Image from images.example.com"));
+        assert!(result.contains("<span class=\"media-placeholder-caption\">A cat</span>"));
+        assert!(!result.starts_with("<p>"));
+        // Nothing is fetched from the host until the reader asks, even without scripting
+        assert!(!result.contains("src="));
+
+        let video =
+            render_markdown_with_options("![](https://media.example.com/clip.mp4)", &options);
+        assert!(video.contains("Video from media.example.com"));
+        assert!(video.contains("media-placeholder-video"));
+        assert!(!video.contains("src=") && !video.contains("<video"));
+
+        // Allowlisted hosts and relative URLs embed directly
+        let trusted =
+            render_markdown_with_options("![](https://cdn.trusted.example/a.png)", &options);
+        assert!(!trusted.contains("media-placeholder"));
+        assert!(trusted.contains("<img src=\"https://cdn.trusted.example/a.png\""));
+        let local = render_markdown_with_options("![](./local.png)", &options);
+        assert!(!local.contains("media-placeholder"));
+    }
+
+    #[test]
+    fn test_media_link_mode() {
+        let mut options = RenderOptions::default();
+        options.media.mode = MediaMode::Link;
+
+        let result =
+            render_markdown_with_options("![A cat](https://images.example.com/cat.jpg)", &options);
+        assert!(!result.contains("<img"));
+        assert!(result.contains("href=\"https://images.example.com/cat.jpg\""));
+        assert!(result.contains(">A cat</a>"));
+
+        let uncaptioned =
+            render_markdown_with_options("![](https://images.example.com/cat.jpg)", &options);
+        assert!(uncaptioned.contains(">images.example.com</a>"));
+    }
+
+    #[test]
+    fn test_link_rewriting_in_rendered_posts() {
+        let result = render_markdown(
+            "[story](https://news.example.com/a?id=1&utm_source=feed) and [https://www.google.com/url?q=https://example.com/b&sa=D]",
+        );
+        assert!(result.contains("href=\"https://news.example.com/a?id=1\""));
+        assert!(!result.contains("utm_source"));
+        assert!(result.contains(">https://example.com/b</a>"));
+
+        let mut options = RenderOptions::default();
+        options.links.strip_tracking_params = false;
+        let untouched = render_markdown_with_options(
+            "[story](https://example.com/a?utm_source=feed)",
+            &options,
+        );
+        assert!(untouched.contains("utm_source=feed"));
+    }
+
+    #[test]
+    fn test_feature_toggles() {
+        let source = "#secret# with a note[^1]\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n![cat](https://example.com/cat.png)\n\n[^1]: The note";
+        let enabled = render_markdown(source);
+        assert!(enabled.contains("<span class=\"secret\">secret</span>"));
+        assert!(enabled.contains("<table>"));
+        assert!(enabled.contains("<img src=\"https://example.com/cat.png\""));
+        assert!(enabled.contains("footnote"));
+
+        let mut options = RenderOptions::default();
+        options.features.secrets = false;
+        options.features.tables = false;
+        options.features.media = false;
+        options.features.footnotes = false;
+        let disabled = render_markdown_with_options(source, &options);
+        assert!(disabled.contains("#secret#"));
+        assert!(!disabled.contains("<table>"));
+        assert!(disabled.contains("| a | b |"));
+        assert!(!disabled.contains("<img"));
+        assert!(disabled.contains("href=\"https://example.com/cat.png\""));
+        assert!(disabled.contains(">cat</a>"));
+        assert!(!disabled.contains('!'));
+        assert!(disabled.contains("[^1]"));
+
+        let bare = render_markdown_with_options("![](https://example.com/cat.png)", &options);
+        assert!(bare.contains(">https://example.com/cat.png</a>"));
+        assert!(!bare.contains('!'));
+    }
+
+    #[test]
+    fn test_highlighting_toggle() {
+        let mut options = RenderOptions::default();
+        options.features.syntax_highlighting = false;
+        let plain = render_markdown_with_options("```rust\nlet x = 1 < 2;\n```", &options);
+        assert!(plain.contains("<span class=\"code-line\">let x = 1 &lt; 2;"));
+        assert!(plain.contains("code-language\">RUST"));
+        assert!(!plain.contains("style="));
+    }
 }
@@ -0,0 +1,219 @@
+use crate::inline::{is_linkable, replace_inline, InlineLink};
+
+/// Converts nonograph markup to plain text wrapped at `width` columns, for
+/// Gopher and other text-only clients. Links and media keep their text and
+/// get a `[n]` marker pointing into a "References" list at the end.
+pub fn render_plain_text(content: &str, width: usize) -> String {
+    let width = width.max(20);
+    let mut out: Vec<String> = Vec::new();
+    let mut references: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+
+        if in_fence {
+            if trimmed.starts_with("```") {
+                in_fence = false;
+            } else {
+                out.push(format!("    {}", line).trim_end().to_string());
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") {
+            in_fence = true;
+            continue;
+        }
+
+        // Tables are already laid out in columns, so they stay as typed
+        if trimmed.starts_with('|') {
+            out.push(trimmed.trim_end().to_string());
+            continue;
+        }
+
+        // "// " lines are author comments and never published
+        if trimmed.starts_with("// ") {
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+            continue;
+        }
+
+        if let Some((underline, text)) = heading(trimmed) {
+            let text = inline_text(text, &mut references);
+            out.extend(wrap(&text, width, "", ""));
+            if let Some(underline) = underline {
+                let length = out.last().map_or(0, |l| l.chars().count());
+                out.push(underline.to_string().repeat(length));
+            }
+            continue;
+        }
+
+        let (first, rest, text) = line_prefix(trimmed);
+        let text = inline_text(text, &mut references);
+        out.extend(wrap(&text, width, &first, &rest));
+    }
+
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+
+    if !references.is_empty() {
+        out.push(String::new());
+        out.push("References".to_string());
+        out.push("----------".to_string());
+        for (index, url) in references.iter().enumerate() {
+            out.push(format!("[{}] {}", index + 1, url));
+        }
+    }
+
+    let mut text = out.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Headings keep their text. The first two levels are underlined with `=`
+/// and `-`, the rest stand alone.
+fn heading(line: &str) -> Option<(Option<char>, &str)> {
+    for (marker, underline) in [
+        ("#### ", None),
+        ("### ", None),
+        ("## ", Some('-')),
+        ("# ", Some('=')),
+    ] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return Some((underline, rest.trim()));
+        }
+    }
+    None
+}
+
+/// Returns the prefix for the first wrapped line, the hanging indent for the
+/// rest and the remaining text.
+fn line_prefix(line: &str) -> (String, String, &str) {
+    if let Some(rest) = line.strip_prefix('>') {
+        return ("> ".to_string(), "> ".to_string(), rest.trim_start());
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            if let Some(task) = rest.strip_prefix("[ ] ") {
+                return ("  [ ] ".to_string(), "      ".to_string(), task);
+            }
+            if let Some(task) = rest
+                .strip_prefix("[x] ")
+                .or_else(|| rest.strip_prefix("[X] "))
+            {
+                return ("  [x] ".to_string(), "      ".to_string(), task);
+            }
+            return ("  * ".to_string(), "    ".to_string(), rest);
+        }
+    }
+
+    // Numbered items keep their number and indent under the text
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(rest) = line[digits..].strip_prefix(". ") {
+            let first = format!("  {}. ", &line[..digits]);
+            let hanging = " ".repeat(first.len());
+            return (first, hanging, rest);
+        }
+    }
+
+    (String::new(), String::new(), line)
+}
+
+/// Strips inline markup from a line and numbers its links and media.
+/// Unsafe targets keep their text but get no reference.
+fn inline_text(line: &str, references: &mut Vec<String>) -> String {
+    let mut reference = |url: &str| {
+        references.push(url.to_string());
+        references.len()
+    };
+
+    replace_inline(line, |link| match link {
+        InlineLink::Link { label, url } if is_linkable(url) => {
+            format!("{} [{}]", label, reference(url))
+        }
+        InlineLink::Link { label, .. } => label.to_string(),
+        InlineLink::Media { alt, url } => {
+            let text = if alt.is_empty() {
+                "[Image]".to_string()
+            } else {
+                format!("[Image: {}]", alt)
+            };
+            if is_linkable(url) {
+                format!("{} [{}]", text, reference(url))
+            } else {
+                text
+            }
+        }
+        InlineLink::Bare { url } => url.to_string(),
+    })
+}
+
+/// Word-wraps `text` to `width` columns. Words longer than a line are left
+/// whole rather than broken, so URLs stay usable.
+fn wrap(text: &str, width: usize, first_prefix: &str, rest_prefix: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = first_prefix.to_string();
+    let mut current_len = first_prefix.chars().count();
+    let mut has_word = false;
+
+    for word in text.split_whitespace() {
+        let word_len = word.chars().count();
+        if has_word && current_len + 1 + word_len > width {
+            lines.push(current);
+            current = rest_prefix.to_string();
+            current_len = rest_prefix.chars().count();
+            has_word = false;
+        }
+        if has_word {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.push_str(word);
+        current_len += word_len;
+        has_word = true;
+    }
+
+    lines.push(current.trim_end().to_string());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_structure() {
+        let source = "# Title\n## Section\n### Small\n\n- one\n- [x] done\n1. first\n\n> quoted\n\n// hidden note\n```\nlet **x** = 1;\n```\n| a | b |";
+        assert_eq!(
+            render_plain_text(source, 72),
+            "Title\n=====\nSection\n-------\nSmall\n\n  * one\n  [x] done\n  1. first\n\n> quoted\n\n    let **x** = 1;\n| a | b |\n"
+        );
+    }
+
+    #[test]
+    fn test_wrapping_uses_hanging_indents() {
+        let source = "- alpha beta gamma delta epsilon zeta\n> one two three four five six seven";
+        assert_eq!(
+            render_plain_text(source, 20),
+            "  * alpha beta gamma\n    delta epsilon\n    zeta\n> one two three four\n> five six seven\n"
+        );
+    }
+
+    #[test]
+    fn test_links_become_references() {
+        let source = "Read **[the docs](https://example.com/docs)** and [https://example.org].\n\n![a cat](/cat.png) [bad](javascript:void) note[^1]";
+        assert_eq!(
+            render_plain_text(source, 72),
+            "Read the docs [1] and https://example.org.\n\n[Image: a cat] [2] bad note[1]\n\nReferences\n----------\n[1] https://example.com/docs\n[2] /cat.png\n"
+        );
+    }
+}
@@ -1,17 +1,54 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use nonograph_markup::sanitize_text;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::fs;
+use std::fmt;
+use std::time::Duration;
+
+use crate::config::Archiver;
+use crate::fetch::SafeFetcher;
+use crate::media::MediaStore;
+use crate::nodes::{self, Node};
+use crate::{allocate_post_id, save, Post, PostKind};
 
 #[derive(Debug, Deserialize)]
-struct TelegraphResponse {
+struct TelegraphResponse<T> {
     ok: bool,
-    result: Option<TelegraphPage>,
+    result: Option<T>,
     error: Option<String>,
 }
 
+/// A request to the Telegraph API that never got an answer, as opposed to
+/// an error the API returned. Only these are worth retrying.
+#[derive(Debug)]
+pub struct TransportError(String);
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// One page of `getPageList` results
+#[derive(Debug, Deserialize)]
+struct PageList {
+    total_count: usize,
+    pages: Vec<PageListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageListEntry {
+    url: String,
+}
+
+/// Pages requested per `getPageList` call, the most the API allows
+const PAGE_LIST_LIMIT: usize = 200;
+
 #[derive(Debug, Deserialize)]
 struct TelegraphPage {
+    #[allow(dead_code)]
     path: String,
     url: String,
     title: String,
@@ -22,50 +59,205 @@ struct TelegraphPage {
     #[allow(dead_code)]
     image_url: Option<String>,
     content: Option<Vec<Node>>,
+    #[allow(dead_code)]
     views: u32,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-#[serde(untagged)]
-enum Node {
-    Text(String),
-    Element(NodeElement),
-}
+/// Extra frontmatter written alongside the standard post fields
+type ProvenanceFields = Vec<(&'static str, String)>;
 
-#[derive(Debug, Deserialize, Clone)]
-struct NodeElement {
-    tag: String,
-    attrs: Option<HashMap<String, String>>,
-    children: Option<Vec<Node>>,
+pub struct TelegraphArchiver {
+    config: Archiver,
+    /// Set by `--with-media`: images and videos are copied here
+    media: Option<MediaStore>,
 }
 
-pub struct TelegraphArchiver;
-
 impl TelegraphArchiver {
-    pub fn new() -> Self {
-        Self
+    pub fn new(config: &Archiver) -> Self {
+        Self {
+            config: config.clone(),
+            media: None,
+        }
+    }
+
+    pub fn with_media(mut self, store: MediaStore) -> Self {
+        self.media = Some(store);
+        self
     }
 
     pub async fn archive_url(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let (post, fields) = self.fetch_post(url, save::post_file_exists).await?;
+
+        // Save through the same path as posts written here
+        save::save_post_with_fields(&post, &fields)?;
+
+        // Return Nonograph URL
+        Ok(format!("/{}", post.id))
+    }
+
+    /// Fetches a page and everything it needs, ready to be saved.
+    async fn fetch_post(
+        &self,
+        url: &str,
+        taken: impl Fn(&str) -> bool,
+    ) -> Result<(Post, ProvenanceFields), Box<dyn std::error::Error>> {
         // Extract path from Telegraph URL
         let path = self.extract_path_from_url(url)?;
 
         // Fetch content from Telegraph API
-        let page = self.fetch_telegraph_page(&path).await?;
+        let mut page = self.fetch_telegraph_page(&path).await?;
 
-        // Convert to Nonograph markdown
-        let markdown = self.convert_to_markdown(&page)?;
+        if let Some(store) = &self.media {
+            self.localise_media(&mut page, store).await;
+        }
 
-        // Generate filename and save
-        let filename = self.generate_filename(&page);
-        let file_path = format!("content/{}", filename);
+        // The API doesn't say when the page was published, its HTML does
+        let published = self.fetch_published_date(&page.url).await;
 
-        // Save to content directory
-        fs::write(&file_path, markdown)?;
+        self.build_post(&page, published, taken)
+    }
 
-        // Return Nonograph URL
-        let nonograph_id = filename.trim_end_matches(".md");
-        Ok(format!("/{}", nonograph_id))
+    /// A fetcher with the configured timeouts, User-Agent and proxy. The
+    /// API origin is trusted, so it may point at a local stand-in.
+    fn fetcher(&self) -> Result<SafeFetcher, Box<dyn std::error::Error>> {
+        let mut fetcher = SafeFetcher::new()
+            .with_timeouts(
+                Duration::from_secs(self.config.connect_timeout_secs),
+                Duration::from_secs(self.config.read_timeout_secs),
+            )
+            .trusting(&url::Url::parse(self.api_url())?);
+        if !self.config.user_agent.is_empty() {
+            fetcher = fetcher.with_user_agent(&self.config.user_agent);
+        }
+        if !self.config.proxy.is_empty() {
+            fetcher = fetcher.with_proxy(&self.config.proxy);
+        }
+        Ok(fetcher)
+    }
+
+    fn api_url(&self) -> &str {
+        self.config.api_url.trim_end_matches('/')
+    }
+
+    /// Turns a fetched page into a post with a fresh id, plus the frontmatter
+    /// fields that record where it came from. `date` is the original publish
+    /// date when known.
+    fn build_post(
+        &self,
+        page: &TelegraphPage,
+        published: Option<DateTime<Utc>>,
+        taken: impl Fn(&str) -> bool,
+    ) -> Result<(Post, ProvenanceFields), Box<dyn std::error::Error>> {
+        let archived_at = Utc::now();
+        let id = allocate_post_id(&page.title, taken)?;
+
+        let post = Post {
+            id,
+            title: sanitize_text(&page.title),
+            author: page
+                .author_name
+                .as_deref()
+                .map(sanitize_text)
+                .unwrap_or_default(),
+            content: String::new(),
+            raw_content: nodes::to_markdown(
+                page.content.as_deref().unwrap_or_default(),
+                &absolute_url,
+            ),
+            created_at: published.unwrap_or(archived_at),
+            password_hash: None,
+            kind: PostKind::Markdown,
+        };
+
+        let mut fields = Vec::new();
+        if let Some(author_url) = page.author_url.as_ref().filter(|url| !url.is_empty()) {
+            fields.push(("author_url", author_url.clone()));
+        }
+        fields.push(("source", page.url.clone()));
+        fields.push(("archived", archived_at.format("%Y-%m-%d").to_string()));
+
+        Ok((post, fields))
+    }
+
+    /// Copies every image and video into `store` and points the page at the
+    /// local copies. Anything that can't be downloaded stays remote.
+    async fn localise_media(&self, page: &mut TelegraphPage, store: &MediaStore) {
+        let Some(content) = page.content.as_mut() else {
+            return;
+        };
+
+        let mut sources = Vec::new();
+        collect_media_sources(content, &mut sources);
+
+        let fetcher = match self.fetcher() {
+            Ok(fetcher) => fetcher,
+            Err(e) => {
+                eprintln!("Keeping remote media: {}", e);
+                return;
+            }
+        };
+        let mut local = HashMap::new();
+        for src in sources {
+            if local.contains_key(&src) {
+                continue;
+            }
+            match store.localise(&fetcher, &absolute_url(&src)).await {
+                Ok(url) => {
+                    local.insert(src, url);
+                }
+                Err(e) => eprintln!("Keeping remote media {}: {}", src, e),
+            }
+        }
+
+        rewrite_media_sources(content, &local);
+    }
+
+    async fn fetch_published_date(&self, page_url: &str) -> Option<DateTime<Utc>> {
+        let fetcher = self.fetcher().ok()?;
+        let html = fetcher.get_text(page_url).await.ok()?;
+        published_date(&html)
+    }
+
+    /// The form page URLs are recorded in as a post's `source`, so the same
+    /// page is recognised however its link was written.
+    pub fn canonical_url(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(format!(
+            "https://telegra.ph/{}",
+            self.extract_path_from_url(url)?
+        ))
+    }
+
+    /// URLs of every page on the account behind `access_token`.
+    pub async fn fetch_account_pages(
+        &self,
+        access_token: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let fetcher = self.fetcher()?;
+        let mut urls = Vec::new();
+
+        loop {
+            let mut api_url = url::Url::parse(&format!("{}/getPageList", self.api_url()))?;
+            api_url
+                .query_pairs_mut()
+                .append_pair("access_token", access_token)
+                .append_pair("offset", &urls.len().to_string())
+                .append_pair("limit", &PAGE_LIST_LIMIT.to_string());
+
+            let telegraph_response: TelegraphResponse<PageList> =
+                fetcher.get_json(api_url.as_str()).await?;
+            if !telegraph_response.ok {
+                return Err(format!("Telegraph API error: {:?}", telegraph_response.error).into());
+            }
+            let list = telegraph_response
+                .result
+                .ok_or("No result in Telegraph response")?;
+
+            let fetched = list.pages.len();
+            urls.extend(list.pages.into_iter().map(|page| page.url));
+            if fetched == 0 || urls.len() >= list.total_count {
+                return Ok(urls);
+            }
+        }
     }
 
     fn extract_path_from_url(&self, url: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -89,13 +281,11 @@ impl TelegraphArchiver {
         &self,
         path: &str,
     ) -> Result<TelegraphPage, Box<dyn std::error::Error>> {
-        let api_url = format!(
-            "https://api.telegra.ph/getPage/{}?return_content=true",
-            path
-        );
+        let api_url = format!("{}/getPage/{}?return_content=true", self.api_url(), path);
 
-        let response = reqwest::get(&api_url).await?;
-        let telegraph_response: TelegraphResponse = response.json().await?;
+        let fetcher = self.fetcher()?;
+        let telegraph_response: TelegraphResponse<TelegraphPage> =
+            fetcher.get_json(&api_url).await.map_err(TransportError)?;
 
         if !telegraph_response.ok {
             return Err(format!("Telegraph API error: {:?}", telegraph_response.error).into());
@@ -105,426 +295,257 @@ impl TelegraphArchiver {
             .result
             .ok_or("No result in Telegraph response".into())
     }
+}
 
-    fn convert_to_markdown(
-        &self,
-        page: &TelegraphPage,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let mut markdown = String::new();
-
-        // Use current date as archival date
-        let now: DateTime<Utc> = Utc::now();
-        let archival_date = format!("{}", now.format("%B %d, %Y"));
-
-        // Add date and author in proper format
-        if let Some(author) = &page.author_name {
-            markdown.push_str(&format!("{} | {}\n\n", archival_date, author));
-        } else {
-            markdown.push_str(&format!("{}\n\n", archival_date));
-        }
+/// Telegraph serves uploads from relative `/file/...` paths.
+fn absolute_url(src: &str) -> String {
+    if src.starts_with("/file/") {
+        format!("https://telegra.ph{}", src)
+    } else {
+        src.to_string()
+    }
+}
 
-        // Add title as H1
-        markdown.push_str(&format!("# {}\n", page.title));
+/// Tags whose `src` is archived by `--with-media`; figures hold these too.
+/// Iframes are third-party players and stay as they are.
+const MEDIA_TAGS: &[&str] = &["img", "video"];
 
-        // Convert content
-        if let Some(content) = &page.content {
-            for node in content {
-                self.convert_node_to_markdown(node, &mut markdown, 0)?;
+fn collect_media_sources(nodes: &[Node], sources: &mut Vec<String>) {
+    for node in nodes {
+        if let Node::Element(element) = node {
+            if MEDIA_TAGS.contains(&element.tag.as_str()) {
+                if let Some(src) = element.attrs.as_ref().and_then(|attrs| attrs.get("src")) {
+                    sources.push(src.clone());
+                }
+            }
+            if let Some(children) = &element.children {
+                collect_media_sources(children, sources);
             }
         }
-
-        // Clean up excessive newlines
-        let cleaned = self.clean_excessive_newlines(&markdown);
-        Ok(cleaned)
     }
+}
 
-    fn convert_node_to_markdown(
-        &self,
-        node: &Node,
-        output: &mut String,
-        depth: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.convert_node_to_markdown_with_context(node, output, depth, None)
+fn rewrite_media_sources(nodes: &mut [Node], local: &HashMap<String, String>) {
+    for node in nodes {
+        if let Node::Element(element) = node {
+            if MEDIA_TAGS.contains(&element.tag.as_str()) {
+                if let Some(src) = element
+                    .attrs
+                    .as_mut()
+                    .and_then(|attrs| attrs.get_mut("src"))
+                {
+                    if let Some(local_url) = local.get(src.as_str()) {
+                        *src = local_url.clone();
+                    }
+                }
+            }
+            if let Some(children) = element.children.as_mut() {
+                rewrite_media_sources(children, local);
+            }
+        }
     }
+}
 
-    fn convert_node_to_markdown_with_context(
-        &self,
-        node: &Node,
-        output: &mut String,
-        depth: usize,
-        image_caption: Option<&str>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        match node {
-            Node::Text(text) => {
-                output.push_str(text);
-            }
-            Node::Element(element) => {
-                match element.tag.as_str() {
-                    "p" => {
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n\n");
-                    }
-                    "br" => {
-                        output.push_str("\n");
-                    }
-                    "strong" | "b" => {
-                        output.push_str("**");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("**");
-                    }
-                    "em" | "i" => {
-                        output.push_str("*");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("*");
-                    }
-                    "u" => {
-                        output.push_str("_");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("_");
-                    }
-                    "s" => {
-                        output.push_str("~");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("~");
-                    }
-                    "code" => {
-                        output.push_str("`");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("`");
-                    }
-                    "pre" => {
-                        output.push_str("```\n");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n```\n\n");
-                    }
-                    "a" => {
-                        if let Some(attrs) = &element.attrs {
-                            if let Some(href) = attrs.get("href") {
-                                output.push_str("[");
-                                if let Some(children) = &element.children {
-                                    for child in children {
-                                        self.convert_node_to_markdown_with_context(
-                                            child,
-                                            output,
-                                            depth,
-                                            image_caption,
-                                        )?;
-                                    }
-                                } else {
-                                    output.push_str(href);
-                                }
-                                output.push_str(&format!("]({})", href));
-                            }
-                        }
-                    }
-                    "img" => {
-                        if let Some(attrs) = &element.attrs {
-                            if let Some(src) = attrs.get("src") {
-                                let full_url = if src.starts_with("/file/") {
-                                    format!("https://telegra.ph{}", src)
-                                } else {
-                                    src.clone()
-                                };
-                                let caption = image_caption.unwrap_or("");
-                                output.push_str(&format!("![{}]({})", caption, full_url));
-                            }
-                        }
-                    }
-                    "h3" => {
-                        output.push_str("### ");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n\n");
-                    }
-                    "h4" => {
-                        output.push_str("#### ");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n\n");
-                    }
-                    "blockquote" => {
-                        output.push_str("> ");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n\n");
-                    }
-                    "ul" => {
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        output.push_str("\n");
-                    }
-                    "ol" => {
-                        if let Some(children) = &element.children {
-                            for (i, child) in children.iter().enumerate() {
-                                if let Node::Element(li) = child {
-                                    if li.tag == "li" {
-                                        output.push_str(&format!("{}. ", i + 1));
-                                        if let Some(li_children) = &li.children {
-                                            for li_child in li_children {
-                                                self.convert_node_to_markdown_with_context(
-                                                    li_child,
-                                                    output,
-                                                    depth,
-                                                    image_caption,
-                                                )?;
-                                            }
-                                        }
-                                        output.push_str("\n");
-                                    }
-                                }
-                            }
-                        }
-                        output.push_str("\n");
-                    }
-                    "li" => {
-                        // Handle unordered list items
-                        if depth == 0 {
-                            output.push_str("- ");
-                        }
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth + 1,
-                                    image_caption,
-                                )?;
-                            }
-                        }
-                        if depth == 0 {
-                            output.push_str("\n");
-                        }
-                    }
-                    "hr" => {
-                        output.push_str("---\n\n");
-                    }
-                    "figure" => {
-                        // Extract caption from figcaption first
-                        let mut caption = String::new();
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                if let Node::Element(elem) = child {
-                                    if elem.tag == "figcaption" {
-                                        if let Some(caption_children) = &elem.children {
-                                            for caption_child in caption_children {
-                                                if let Node::Text(text) = caption_child {
-                                                    caption.push_str(text);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
+/// Date from the `article:published_time` meta tag Telegraph pages carry.
+fn published_date(html: &str) -> Option<DateTime<Utc>> {
+    let tag_start = html.find(r#"property="article:published_time""#)?;
+    let tag = &html[tag_start..];
+    let tag = &tag[..tag.find('>')?];
+    let value_start = tag.find(r#"content=""#)? + r#"content=""#.len();
+    let date = tag[value_start..].get(..10)?;
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)
+        .map(|datetime| DateTime::<Utc>::from_naive_utc_and_offset(datetime, Utc))
+}
 
-                        // Now process all children with the caption context
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                if let Node::Element(elem) = child {
-                                    if elem.tag != "figcaption" {
-                                        let caption_ref = if caption.is_empty() {
-                                            None
-                                        } else {
-                                            Some(caption.as_str())
-                                        };
-                                        self.convert_node_to_markdown_with_context(
-                                            child,
-                                            output,
-                                            depth,
-                                            caption_ref,
-                                        )?;
-                                    }
-                                }
-                            }
-                        }
-                        output.push_str("\n");
-                    }
-                    "figcaption" => {
-                        // Figcaptions are now handled by the figure element above
-                        // Skip processing them directly to avoid duplication
-                    }
-                    "iframe" | "video" => {
-                        if let Some(attrs) = &element.attrs {
-                            if let Some(src) = attrs.get("src") {
-                                let full_url = if src.starts_with("/file/") {
-                                    format!("https://telegra.ph{}", src)
-                                } else {
-                                    src.clone()
-                                };
-                                let caption = image_caption.unwrap_or("Video");
-                                output.push_str(&format!("![{}]({})\n\n", caption, full_url));
-                            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodes::NodeElement;
+    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use rocket::tokio::net::TcpListener;
+    use rocket::tokio::{spawn, time::sleep};
+    use sha2::{Digest, Sha256};
+    use std::sync::{Arc, Mutex};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really";
+
+    /// A stand-in for the Telegraph API and telegra.ph on a local port.
+    /// Returns its base URL and the request lines and User-Agents it saw.
+    async fn mock_telegraph() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let (server_base, seen) = (base.clone(), requests.clone());
+        spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (base, seen) = (server_base.clone(), seen.clone());
+                spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
                         }
+                        head.extend_from_slice(&buf[..n]);
                     }
-                    "aside" => {
-                        // Convert aside to blockquote
-                        output.push_str("> ");
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
+                    let head = String::from_utf8_lossy(&head).into_owned();
+                    let target = head.split(' ').nth(1).unwrap_or_default().to_string();
+                    let user_agent = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("user-agent: "))
+                        .unwrap_or_default();
+                    seen.lock()
+                        .unwrap()
+                        .push(format!("{} {}", target, user_agent));
+
+                    let page = format!(
+                        r#"{{"ok": true, "result": {{"path": "Sample-Page-01-02", "url": "{base}/Sample-Page-01-02",
+                            "title": "Sample Page", "description": "", "author_name": "Ann", "views": 3,
+                            "content": [{{"tag": "p", "children": ["Hello"]}},
+                                        {{"tag": "img", "attrs": {{"src": "{base}/file/pic.png"}}}}]}}}}"#
+                    );
+                    let (content_type, body): (&str, Vec<u8>) = match target.as_str() {
+                        "/getPage/Sample-Page-01-02?return_content=true" => {
+                            ("application/json", page.into_bytes())
                         }
-                        output.push_str("\n\n");
-                    }
-                    _ => {
-                        // For unhandled tags, just process children
-                        if let Some(children) = &element.children {
-                            for child in children {
-                                self.convert_node_to_markdown_with_context(
-                                    child,
-                                    output,
-                                    depth,
-                                    image_caption,
-                                )?;
-                            }
+                        "/getPage/Slow-01-02?return_content=true" => {
+                            sleep(Duration::from_secs(10)).await;
+                            return;
                         }
-                    }
-                }
+                        "/Sample-Page-01-02" => (
+                            "text/html",
+                            br#"<meta property="article:published_time" content="2017-06-01T13:44:02+0000">"#.to_vec(),
+                        ),
+                        "/file/pic.png" => ("image/png", PNG.to_vec()),
+                        "/getPageList?access_token=token&offset=0&limit=200" => (
+                            "application/json",
+                            br#"{"ok": true, "result": {"total_count": 3, "pages": [{"url": "https://telegra.ph/A-01-01"}, {"url": "https://telegra.ph/B-01-01"}]}}"#.to_vec(),
+                        ),
+                        "/getPageList?access_token=token&offset=2&limit=200" => (
+                            "application/json",
+                            br#"{"ok": true, "result": {"total_count": 3, "pages": [{"url": "https://telegra.ph/C-01-01"}]}}"#.to_vec(),
+                        ),
+                        _ => (
+                            "application/json",
+                            br#"{"ok": false, "error": "PAGE_NOT_FOUND"}"#.to_vec(),
+                        ),
+                    };
+
+                    let header = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        content_type,
+                        body.len()
+                    );
+                    let _ = stream.write_all(header.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                });
             }
-        }
-        Ok(())
+        });
+
+        (base, requests)
     }
 
-    fn clean_excessive_newlines(&self, content: &str) -> String {
-        // Replace 3+ consecutive newlines with just 2
-        let mut result = content.to_string();
-        while result.contains("\n\n\n") {
-            result = result.replace("\n\n\n", "\n\n");
+    fn mock_config(api_url: &str) -> Archiver {
+        Archiver {
+            api_url: format!("{}/", api_url),
+            read_timeout_secs: 1,
+            user_agent: "nonograph-test".to_string(),
+            ..Archiver::default()
         }
-        result
     }
 
-    fn generate_filename(&self, page: &TelegraphPage) -> String {
-        // Use the path from Telegraph as base, but make it filesystem-safe
-        let mut filename = page.path.clone();
-
-        // Replace any unsafe characters
-        filename = filename.replace('/', "-");
-        filename = filename.replace('\\', "-");
-        filename = filename.replace(':', "-");
-        filename = filename.replace('?', "-");
-        filename = filename.replace('*', "-");
-        filename = filename.replace('"', "-");
-        filename = filename.replace('<', "-");
-        filename = filename.replace('>', "-");
-        filename = filename.replace('|', "-");
-
-        // Ensure it ends with .md
-        if !filename.ends_with(".md") {
-            filename.push_str(".md");
-        }
+    #[rocket::async_test]
+    async fn test_fetch_post_from_configured_api() {
+        let (base, requests) = mock_telegraph().await;
+        let media_dir = tempfile::tempdir().unwrap();
+        let config = Archiver {
+            media_dir: media_dir.path().to_string_lossy().into_owned(),
+            ..mock_config(&base)
+        };
+        let archiver = TelegraphArchiver::new(&config).with_media(MediaStore::new(&config));
+
+        let (post, fields) = archiver
+            .fetch_post("https://telegra.ph/Sample-Page-01-02", |_| false)
+            .await
+            .unwrap();
+
+        assert_eq!(post.title, "Sample Page");
+        assert_eq!(post.created_at.format("%Y-%m-%d").to_string(), "2017-06-01");
+        let local = format!("/media/{:x}.png", Sha256::digest(PNG));
+        assert_eq!(post.raw_content, format!("Hello\n\n![]({})", local));
+        assert!(media_dir.path().join(&local["/media/".len()..]).exists());
+        assert!(fields.contains(&("source", format!("{}/Sample-Page-01-02", base))));
+
+        let mut requests = requests.lock().unwrap().clone();
+        requests.sort();
+        assert_eq!(
+            requests,
+            vec![
+                "/Sample-Page-01-02 nonograph-test",
+                "/file/pic.png nonograph-test",
+                "/getPage/Sample-Page-01-02?return_content=true nonograph-test",
+            ]
+        );
 
-        filename
+        let err = archiver
+            .fetch_post("https://telegra.ph/Missing-01-02", |_| false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("PAGE_NOT_FOUND"));
+        assert!(!err.is::<TransportError>());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[rocket::async_test]
+    async fn test_fetch_account_pages_paginates() {
+        let (base, _) = mock_telegraph().await;
+        let archiver = TelegraphArchiver::new(&mock_config(&base));
+
+        assert_eq!(
+            archiver.fetch_account_pages("token").await.unwrap(),
+            vec![
+                "https://telegra.ph/A-01-01",
+                "https://telegra.ph/B-01-01",
+                "https://telegra.ph/C-01-01"
+            ]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_read_timeout() {
+        let (base, _) = mock_telegraph().await;
+        let archiver = TelegraphArchiver::new(&mock_config(&base));
+
+        let err = archiver
+            .fetch_post("https://telegra.ph/Slow-01-02", |_| false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{}", err);
+        assert!(err.is::<TransportError>());
+    }
+
+    #[rocket::async_test]
+    async fn test_untrusted_local_hosts_stay_blocked() {
+        let (base, _) = mock_telegraph().await;
+        let archiver = TelegraphArchiver::new(&Archiver::default());
+
+        // Only the configured API origin may be local
+        assert!(archiver
+            .fetcher()
+            .unwrap()
+            .get_text(&format!("{}/Sample-Page-01-02", base))
+            .await
+            .is_err());
+    }
 
     #[test]
     fn test_extract_path_from_url() {
-        let archiver = TelegraphArchiver::new();
+        let archiver = TelegraphArchiver::new(&Archiver::default());
 
         let url = "https://telegra.ph/Sample-Page-12-15";
         let path = archiver.extract_path_from_url(url).unwrap();
@@ -535,21 +556,80 @@ mod tests {
     }
 
     #[test]
-    fn test_generate_filename() {
-        let archiver = TelegraphArchiver::new();
+    fn test_build_post() {
+        let archiver = TelegraphArchiver::new(&Archiver::default());
         let page = TelegraphPage {
             path: "Sample-Page-12-15".to_string(),
             url: "https://telegra.ph/Sample-Page-12-15".to_string(),
             title: "Sample Page".to_string(),
             description: "A sample page".to_string(),
-            author_name: None,
-            author_url: None,
+            author_name: Some("Ann".to_string()),
+            author_url: Some("https://t.me/ann".to_string()),
             image_url: None,
-            content: None,
+            content: Some(vec![Node::Element(NodeElement {
+                tag: "p".to_string(),
+                attrs: None,
+                children: Some(vec![Node::Text("Hello".to_string())]),
+            })]),
             views: 100,
         };
+        let published = published_date(
+            r#"<meta property="article:published_time" content="2017-06-01T13:44:02+0000">"#,
+        );
 
-        let filename = archiver.generate_filename(&page);
-        assert_eq!(filename, "Sample-Page-12-15.md");
+        let today = Utc::now().format("%m-%d-%Y").to_string();
+        let first = format!("sample-page-{}", today);
+        let (post, fields) = archiver
+            .build_post(&page, published, |id| id == first)
+            .unwrap();
+
+        assert_eq!(post.id, format!("sample-page-{}-1", today));
+        assert_eq!(post.title, "Sample Page");
+        assert_eq!(post.author, "Ann");
+        assert_eq!(post.raw_content, "Hello\n\n");
+        assert_eq!(post.created_at.format("%Y-%m-%d").to_string(), "2017-06-01");
+        assert_eq!(
+            fields,
+            vec![
+                ("author_url", "https://t.me/ann".to_string()),
+                ("source", "https://telegra.ph/Sample-Page-12-15".to_string()),
+                ("archived", Utc::now().format("%Y-%m-%d").to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_media_sources_are_collected_and_rewritten() {
+        let mut nodes: Vec<Node> = serde_json::from_str(
+            r#"[
+                {"tag": "figure", "children": [
+                    {"tag": "img", "attrs": {"src": "/file/abc.jpg"}},
+                    {"tag": "figcaption", "children": ["Caption"]}
+                ]},
+                {"tag": "p", "children": [{"tag": "video", "attrs": {"src": "/file/clip.mp4"}}]},
+                {"tag": "iframe", "attrs": {"src": "https://www.youtube.com/embed/x"}}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut sources = Vec::new();
+        collect_media_sources(&nodes, &mut sources);
+        assert_eq!(sources, vec!["/file/abc.jpg", "/file/clip.mp4"]);
+
+        let local = HashMap::from([("/file/abc.jpg".to_string(), "/media/abc.jpg".to_string())]);
+        rewrite_media_sources(&mut nodes, &local);
+
+        let markdown = nodes::to_markdown(&nodes, &absolute_url);
+        assert!(markdown.contains("![Caption](/media/abc.jpg)"));
+        assert!(markdown.contains("(https://telegra.ph/file/clip.mp4)"));
+    }
+
+    #[test]
+    fn test_published_date() {
+        assert_eq!(published_date("<html><head></head></html>"), None);
+        assert_eq!(
+            published_date(r#"<meta property="article:published_time" content="nope">"#),
+            None
+        );
     }
 }
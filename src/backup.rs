@@ -0,0 +1,313 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::config::Config;
+
+/// Listing of every file in the archive, stored as its first entry
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// How often a file that changes while it's being read is retried
+const READ_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    generator: String,
+    created_at: String,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Relative to the instance directory, e.g. `content/<id>.md`
+    path: String,
+    /// Set for posts and pages in `content/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    sha256: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    /// Already present with identical contents
+    pub identical: Vec<String>,
+    /// Already present with different contents; left untouched
+    pub conflicts: Vec<String>,
+}
+
+/// Writes every file in `content/` and the archiver's media directory plus
+/// Config.toml and the link rewrite rules into a zip at `file`, with a
+/// manifest of SHA-256 hashes. Returns the number of posts backed up.
+pub fn backup(base_dir: &Path, config: &Config, file: &Path) -> Result<usize, String> {
+    let mut paths = list_dir(base_dir, "content")?;
+
+    // Media copied in by the archiver
+    let media_dir = config.archiver.media_dir.trim().trim_end_matches('/');
+    if is_safe_path(media_dir) {
+        paths.extend(list_dir(base_dir, media_dir)?);
+    }
+
+    paths.push("Config.toml".to_string());
+    let rules_file = config.links.rewrite_rules_file.trim();
+    if is_safe_path(rules_file) {
+        paths.push(rules_file.to_string());
+    }
+
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+    for path in paths {
+        let Some(data) = read_consistent(&base_dir.join(&path))? else {
+            continue;
+        };
+        let id = path
+            .strip_prefix("content/")
+            .and_then(|name| name.strip_suffix(".md"))
+            .map(str::to_string);
+        entries.push(ManifestEntry {
+            path: path.clone(),
+            id,
+            sha256: hex_digest(&data),
+        });
+        files.push((path, data));
+    }
+    let posts = entries.iter().filter(|entry| entry.id.is_some()).count();
+
+    let manifest = Manifest {
+        generator: format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        created_at: Utc::now().to_rfc3339(),
+        files: entries,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialise manifest: {}", e))?;
+
+    let archive = write_archive(&manifest_json, &files)
+        .map_err(|e| format!("Failed to build archive: {}", e))?;
+
+    // Written beside the target first so an interrupted backup never
+    // replaces a good one
+    let partial = file.with_extension("partial");
+    fs::write(&partial, archive)
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    fs::rename(&partial, file).map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+
+    Ok(posts)
+}
+
+/// Restores a backup into `base_dir`. Every checksum is verified before
+/// anything is written, and files that already exist with other contents
+/// are reported as conflicts instead of being overwritten.
+pub fn restore(file: &Path, base_dir: &Path) -> Result<RestoreReport, String> {
+    let data = fs::read(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Not a backup archive: {}", e))?;
+
+    let manifest: Manifest = serde_json::from_slice(&read_entry(&mut archive, MANIFEST_NAME)?)
+        .map_err(|e| format!("Invalid manifest: {}", e))?;
+
+    let mut files = Vec::new();
+    for entry in &manifest.files {
+        if !is_safe_path(&entry.path) {
+            return Err(format!("Refusing to restore unsafe path {}", entry.path));
+        }
+        let contents = read_entry(&mut archive, &entry.path)?;
+        if hex_digest(&contents) != entry.sha256 {
+            return Err(format!("Checksum mismatch for {}", entry.path));
+        }
+        files.push((&entry.path, contents));
+    }
+
+    let mut report = RestoreReport::default();
+    for (path, contents) in files {
+        let target = base_dir.join(path);
+        match fs::read(&target) {
+            Ok(existing) if existing == contents => report.identical.push(path.clone()),
+            Ok(_) => report.conflicts.push(path.clone()),
+            Err(_) => {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+                }
+                fs::write(&target, contents)
+                    .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+                report.restored.push(path.clone());
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Files directly inside `<base_dir>/<dir>`, as sorted relative paths.
+fn list_dir(base_dir: &Path, dir: &str) -> Result<Vec<String>, String> {
+    let full = base_dir.join(dir);
+    if !full.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        fs::read_dir(&full).map_err(|e| format!("Failed to read {}: {}", full.display(), e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+
+    Ok(names
+        .into_iter()
+        .map(|name| format!("{}/{}", dir, name))
+        .collect())
+}
+
+/// Reads a file, retrying while its mtime or length moves underneath us so
+/// a post that is mid-write isn't captured half-saved. Missing files are
+/// `None`.
+fn read_consistent(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    for _ in 0..READ_ATTEMPTS {
+        let Ok(before) = fs::metadata(path) else {
+            return Ok(None);
+        };
+        let data =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let after =
+            fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        if before.modified().ok() == after.modified().ok() && after.len() == data.len() as u64 {
+            return Ok(Some(data));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    Err(format!("{} kept changing while being read", path.display()))
+}
+
+fn write_archive(manifest: &[u8], files: &[(String, Vec<u8>)]) -> zip::result::ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(MANIFEST_NAME, options)?;
+    zip.write_all(manifest)?;
+    for (path, data) in files {
+        zip.start_file(path, options)?;
+        zip.write_all(data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| format!("{} is missing from the archive", name))?;
+    let mut data = Vec::new();
+    entry
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {} from the archive: {}", name, e))?;
+    Ok(data)
+}
+
+/// Only plain relative paths inside the instance directory are backed up
+/// or restored.
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const POST: &str = "---\ntitle: Hello\ndate: 2025-01-01\n---\n\nHello\n";
+
+    #[test]
+    fn test_backup_and_restore() {
+        let source = tempdir().unwrap();
+        fs::create_dir_all(source.path().join("content")).unwrap();
+        fs::write(source.path().join("content/hello-01-01-2025.md"), POST).unwrap();
+        fs::write(source.path().join("content/about.md"), POST).unwrap();
+        fs::write(source.path().join("Config.toml"), "[server]\n").unwrap();
+        fs::create_dir_all(source.path().join("media")).unwrap();
+        fs::write(source.path().join("media/abc.png"), "png").unwrap();
+
+        let file = source.path().join("backup.zip");
+        assert_eq!(backup(source.path(), &Config::default(), &file).unwrap(), 2);
+
+        // Into an empty directory everything is restored
+        let target = tempdir().unwrap();
+        let report = restore(&file, target.path()).unwrap();
+        assert_eq!(
+            report.restored,
+            vec![
+                "content/about.md",
+                "content/hello-01-01-2025.md",
+                "media/abc.png",
+                "Config.toml"
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(target.path().join("content/hello-01-01-2025.md")).unwrap(),
+            POST
+        );
+
+        // A second time nothing changes, and edits are never overwritten
+        fs::write(target.path().join("content/about.md"), "edited").unwrap();
+        let report = restore(&file, target.path()).unwrap();
+        assert!(report.restored.is_empty());
+        assert_eq!(report.conflicts, vec!["content/about.md"]);
+        assert_eq!(report.identical.len(), 3);
+        assert_eq!(
+            fs::read_to_string(target.path().join("content/about.md")).unwrap(),
+            "edited"
+        );
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_checksums_and_paths() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("backup.zip");
+
+        let manifest = |path: &str, sha256: &str| {
+            format!(
+                r#"{{"generator":"test","created_at":"","files":[{{"path":"{}","sha256":"{}"}}]}}"#,
+                path, sha256
+            )
+        };
+
+        let archive = write_archive(
+            manifest("content/a.md", &hex_digest(b"original")).as_bytes(),
+            &[("content/a.md".to_string(), b"tampered".to_vec())],
+        )
+        .unwrap();
+        fs::write(&file, archive).unwrap();
+        assert!(restore(&file, dir.path())
+            .unwrap_err()
+            .contains("Checksum mismatch"));
+        assert!(!dir.path().join("content/a.md").exists());
+
+        let archive = write_archive(
+            manifest("../escape.md", &hex_digest(b"x")).as_bytes(),
+            &[("../escape.md".to_string(), b"x".to_vec())],
+        )
+        .unwrap();
+        fs::write(&file, archive).unwrap();
+        assert!(restore(&file, dir.path())
+            .unwrap_err()
+            .contains("unsafe path"));
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use rocket::futures::stream::{self, StreamExt};
+use rocket::tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+
+use crate::archiver::{TelegraphArchiver, TransportError};
+use crate::frontmatter_field;
+
+/// Pages fetched at the same time
+const CONCURRENCY: usize = 4;
+
+/// Tries per page before it's reported as failed
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled for each one after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Outcomes so far, kept while a run has failures so rerunning the same
+/// command resumes instead of starting over
+pub const PROGRESS_FILE: &str = ".archive-progress.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Archived { post: String },
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+/// Archives every URL in `urls`, a few at a time. Pages whose source URL
+/// already belongs to a post in `content_dir`, that an earlier run recorded
+/// in `progress_path` or that an earlier URL in the list points at are
+/// skipped. Results come back in input order.
+pub async fn archive_all(
+    archiver: &TelegraphArchiver,
+    urls: Vec<String>,
+    content_dir: &Path,
+    progress_path: &Path,
+) -> Vec<(String, Outcome)> {
+    let mut progress: BTreeMap<String, Outcome> = fs::read_to_string(progress_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let archived = archived_sources(content_dir);
+
+    let mut results: Vec<(String, Option<Outcome>)> = Vec::new();
+    let mut pending: Vec<(usize, String)> = Vec::new();
+    for url in urls {
+        if results.iter().any(|(seen, _)| *seen == url) {
+            continue;
+        }
+
+        let outcome = match archiver.canonical_url(&url) {
+            Err(e) => Some(Outcome::Failed {
+                error: e.to_string(),
+            }),
+            Ok(source) => match (archived.get(&source), progress.get(&source)) {
+                (Some(post), _) | (None, Some(Outcome::Archived { post })) => {
+                    Some(Outcome::Skipped {
+                        reason: format!("already archived as {}", post),
+                    })
+                }
+                _ => match pending.iter().find(|(_, queued)| *queued == source) {
+                    Some((index, _)) => Some(Outcome::Skipped {
+                        reason: format!("same page as {}", results[*index].0),
+                    }),
+                    None => {
+                        pending.push((results.len(), source));
+                        None
+                    }
+                },
+            },
+        };
+        results.push((url, outcome));
+    }
+
+    let mut archiving = stream::iter(pending)
+        .map(|(index, source)| async move {
+            let outcome = archive_with_retry(archiver, &source).await;
+            (index, source, outcome)
+        })
+        .buffer_unordered(CONCURRENCY);
+
+    while let Some((index, source, outcome)) = archiving.next().await {
+        progress.insert(source, outcome.clone());
+        save_progress(progress_path, &progress);
+        results[index].1 = Some(outcome);
+    }
+
+    let failed = progress
+        .values()
+        .any(|outcome| matches!(outcome, Outcome::Failed { .. }));
+    if !failed {
+        let _ = fs::remove_file(progress_path);
+    }
+
+    results
+        .into_iter()
+        .filter_map(|(url, outcome)| outcome.map(|outcome| (url, outcome)))
+        .collect()
+}
+
+/// Archives `url`, retrying with backoff when the API can't be reached.
+/// Errors the API answers with, such as a missing page, fail straight away.
+async fn archive_with_retry(archiver: &TelegraphArchiver, url: &str) -> Outcome {
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let error = match archiver.archive_url(url).await {
+            Ok(post) => return Outcome::Archived { post },
+            Err(e) if e.is::<TransportError>() && attempt < MAX_ATTEMPTS => e.to_string(),
+            Err(e) => {
+                return Outcome::Failed {
+                    error: e.to_string(),
+                }
+            }
+        };
+
+        eprintln!(
+            "Attempt {} for {} failed ({}), retrying in {}s",
+            attempt,
+            url,
+            error,
+            backoff.as_secs()
+        );
+        sleep(backoff).await;
+        backoff *= 2;
+    }
+
+    unreachable!("the last attempt always returns")
+}
+
+fn save_progress(path: &Path, progress: &BTreeMap<String, Outcome>) {
+    match serde_json::to_string_pretty(progress) {
+        Ok(json) => {
+            if let Err(e) = fs::write(path, json) {
+                eprintln!("Failed to save progress to {}: {}", path.display(), e);
+            }
+        }
+        Err(e) => eprintln!("Failed to serialise progress: {}", e),
+    }
+}
+
+/// Source URLs of archived posts, mapped to their post URLs.
+pub fn archived_sources(content_dir: &Path) -> HashMap<String, String> {
+    let Ok(entries) = fs::read_dir(content_dir) else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let id = path.file_stem()?.to_str()?.to_string();
+            let source = frontmatter_field(&fs::read_to_string(&path).ok()?, "source")?;
+            Some((source, format!("/{}", id)))
+        })
+        .collect()
+}
+
+/// URLs from a list file: one per line, blank lines and `#` comments ignored.
+pub fn read_url_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// A table of every outcome followed by the totals.
+pub fn summary_table(results: &[(String, Outcome)]) -> String {
+    let width = results
+        .iter()
+        .map(|(url, _)| url.len())
+        .max()
+        .unwrap_or(0)
+        .max("URL".len());
+
+    let mut table = format!("{:<8}  {:<width$}  DETAIL\n", "STATUS", "URL");
+    let (mut archived, mut skipped, mut failed) = (0, 0, 0);
+    for (url, outcome) in results {
+        let (status, detail) = match outcome {
+            Outcome::Archived { post } => {
+                archived += 1;
+                ("archived", post)
+            }
+            Outcome::Skipped { reason } => {
+                skipped += 1;
+                ("skipped", reason)
+            }
+            Outcome::Failed { error } => {
+                failed += 1;
+                ("failed", error)
+            }
+        };
+        table.push_str(&format!("{:<8}  {:<width$}  {}\n", status, url, detail));
+    }
+    table.push_str(&format!(
+        "\n{} archived, {} skipped, {} failed\n",
+        archived, skipped, failed
+    ));
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Archiver;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_url_list() {
+        let list = "# Reading list\nhttps://telegra.ph/A-01-01\n\n  https://telegra.ph/B-01-02  \n";
+        assert_eq!(
+            read_url_list(list),
+            vec!["https://telegra.ph/A-01-01", "https://telegra.ph/B-01-02"]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_archive_all_skips_without_fetching() {
+        let dir = tempdir().unwrap();
+        let content = dir.path().join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(
+            content.join("a-10-18-2026.md"),
+            "---\ntitle: A\ndate: 2025-01-01\nsource: https://telegra.ph/A-01-01\n---\n\nA\n",
+        )
+        .unwrap();
+
+        // An earlier run that stopped after archiving B
+        let progress = dir.path().join(PROGRESS_FILE);
+        fs::write(
+            &progress,
+            r#"{"https://telegra.ph/B-01-02": {"status": "archived", "post": "/b-10-18-2026"}}"#,
+        )
+        .unwrap();
+
+        let urls = read_url_list(
+            "http://telegra.ph/A-01-01\nhttps://telegra.ph/B-01-02\nhttps://example.com/C\nhttp://telegra.ph/A-01-01\n",
+        );
+        let results = archive_all(
+            &TelegraphArchiver::new(&Archiver::default()),
+            urls,
+            &content,
+            &progress,
+        )
+        .await;
+
+        assert_eq!(
+            results,
+            vec![
+                (
+                    "http://telegra.ph/A-01-01".to_string(),
+                    Outcome::Skipped {
+                        reason: "already archived as /a-10-18-2026".to_string()
+                    }
+                ),
+                (
+                    "https://telegra.ph/B-01-02".to_string(),
+                    Outcome::Skipped {
+                        reason: "already archived as /b-10-18-2026".to_string()
+                    }
+                ),
+                (
+                    "https://example.com/C".to_string(),
+                    Outcome::Failed {
+                        error: "URL is not a Telegraph page".to_string()
+                    }
+                ),
+            ]
+        );
+        // Nothing failed while archiving, so there's nothing to resume
+        assert!(!progress.exists());
+
+        let table = summary_table(&results);
+        assert!(table.starts_with("STATUS    URL "));
+        assert!(
+            table.contains("\nfailed    https://example.com/C       URL is not a Telegraph page\n")
+        );
+        assert!(table.ends_with("\n0 archived, 2 skipped, 1 failed\n"));
+    }
+
+    #[rocket::async_test]
+    async fn test_archive_all_dedupes_and_fails_fast() {
+        use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use rocket::tokio::net::TcpListener;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // An API that knows no pages, counting the requests it gets
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        rocket::tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                seen.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let body = r#"{"ok": false, "error": "PAGE_NOT_FOUND"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let dir = tempdir().unwrap();
+        let archiver = TelegraphArchiver::new(&Archiver {
+            api_url,
+            ..Archiver::default()
+        });
+        let urls = vec![
+            "https://telegra.ph/Gone-01-01".to_string(),
+            "http://telegra.ph/Gone-01-01".to_string(),
+        ];
+        let results =
+            archive_all(&archiver, urls, dir.path(), &dir.path().join(PROGRESS_FILE)).await;
+
+        assert_eq!(
+            results,
+            vec![
+                (
+                    "https://telegra.ph/Gone-01-01".to_string(),
+                    Outcome::Failed {
+                        error: "Telegraph API error: Some(\"PAGE_NOT_FOUND\")".to_string()
+                    }
+                ),
+                (
+                    "http://telegra.ph/Gone-01-01".to_string(),
+                    Outcome::Skipped {
+                        reason: "same page as https://telegra.ph/Gone-01-01".to_string()
+                    }
+                ),
+            ]
+        );
+        // Asked once: the duplicate wasn't fetched and the error wasn't retried
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
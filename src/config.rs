@@ -1,4 +1,6 @@
+use nonograph_markup::{LinkOptions, MediaOptions, RenderOptions};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
@@ -10,6 +12,20 @@ pub struct Config {
     pub performance: Performance,
     pub security: Security,
     pub theme: Theme,
+    #[serde(default)]
+    pub media: MediaOptions,
+    #[serde(default)]
+    pub links: Links,
+    #[serde(default)]
+    pub gemini: Gemini,
+    #[serde(default)]
+    pub gopher: Gopher,
+    #[serde(default)]
+    pub export: Export,
+    #[serde(default)]
+    pub archiver: Archiver,
+    #[serde(default)]
+    pub mirror: Mirror,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +34,13 @@ pub struct Limits {
     pub alias_max_length: usize,
     pub content_max_length: usize,
     pub form_data_limit_kb: u32,
+    /// Largest document accepted by upload endpoints such as DOCX import
+    #[serde(default = "default_upload_max_size_kb")]
+    pub upload_max_size_kb: u32,
+}
+
+fn default_upload_max_size_kb() -> u32 {
+    10240
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +49,10 @@ pub struct Server {
     pub address: String,
     pub onion_url: String,
     pub onion_hostname_file: String,
+    /// Where readers reach this instance, e.g. `https://nonograph.example`,
+    /// for links shown outside a request. Empty uses the listening address.
+    #[serde(default)]
+    pub public_url: String,
 }
 
 fn default_onion_hostname_file() -> String {
@@ -57,6 +84,167 @@ pub struct Theme {
     pub syntax_highlighting: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Links {
+    pub strip_tracking_params: bool,
+    pub unwrap_redirects: bool,
+    #[serde(default)]
+    pub rewrite_rules_file: String,
+    /// Loaded from `rewrite_rules_file` when the config is read
+    #[serde(skip)]
+    pub domain_rewrites: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gemini {
+    pub enabled: bool,
+    pub address: String,
+    pub port: u16,
+    /// Host name clients use, checked against the host in each request
+    pub hostname: String,
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl Default for Gemini {
+    fn default() -> Self {
+        Gemini {
+            enabled: false,
+            address: "0.0.0.0".to_string(),
+            port: 1965,
+            hostname: "localhost".to_string(),
+            cert_path: "gemini/cert.pem".to_string(),
+            key_path: "gemini/key.pem".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gopher {
+    pub enabled: bool,
+    pub address: String,
+    pub port: u16,
+    /// Host name written into menu entries so clients know where to connect
+    pub hostname: String,
+    /// Column at which posts are wrapped
+    #[serde(default = "default_gopher_width")]
+    pub line_width: usize,
+}
+
+fn default_gopher_width() -> usize {
+    70
+}
+
+impl Default for Gopher {
+    fn default() -> Self {
+        Gopher {
+            enabled: false,
+            address: "0.0.0.0".to_string(),
+            port: 70,
+            hostname: "localhost".to_string(),
+            line_width: default_gopher_width(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Export {
+    /// Downloads remote images into exported files. When off they become links.
+    pub embed_remote_images: bool,
+    /// Larger images are linked instead of embedded
+    pub max_image_size_kb: usize,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Export {
+            embed_remote_images: false,
+            max_image_size_kb: 5120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Archiver {
+    /// Telegraph API to archive from; a local stand-in works too
+    pub api_url: String,
+    pub connect_timeout_secs: u64,
+    /// How long to wait for a response, or for more of its body, before
+    /// giving up
+    pub read_timeout_secs: u64,
+    /// Sent with archiver requests; empty uses the HTTP client's default
+    pub user_agent: String,
+    /// Proxy (`socks5h://`, `http://`, `https://`) for all archiver traffic,
+    /// e.g. `socks5h://127.0.0.1:9050` for Tor. Empty connects directly.
+    pub proxy: String,
+    /// Where `archive --with-media` stores downloads, served at `/media/`
+    pub media_dir: String,
+    /// Larger files are left as remote links
+    pub max_media_size_kb: usize,
+    /// Serves the `/archive` page for archiving from the browser
+    pub web_enabled: bool,
+    /// Operator token the `/archive` page asks for; the page stays off
+    /// while it's empty
+    pub web_token: String,
+}
+
+/// Other instances whose posts are kept here too, in case they go down
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mirror {
+    /// Minutes between syncs while the server runs; 0 leaves syncing to
+    /// `nonograph mirror --sync`
+    pub sync_interval_mins: u64,
+    /// Proxy (`socks5h://`, `http://`, `https://`) for reaching peers,
+    /// needed for `.onion` ones
+    pub proxy: String,
+    pub peers: Vec<Peer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peer {
+    /// The instance's base URL, e.g. `https://nonogra.ph`
+    pub url: String,
+    /// Ids of the posts to mirror
+    #[serde(default)]
+    pub posts: Vec<String>,
+}
+
+impl Default for Archiver {
+    fn default() -> Self {
+        Archiver {
+            api_url: "https://api.telegra.ph".to_string(),
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            user_agent: String::new(),
+            proxy: String::new(),
+            media_dir: "media".to_string(),
+            max_media_size_kb: 20480,
+            web_enabled: false,
+            web_token: String::new(),
+        }
+    }
+}
+
+impl Archiver {
+    /// The `/archive` pages are served only when enabled with a token
+    pub fn web_archiving_enabled(&self) -> bool {
+        self.web_enabled && !self.web_token.is_empty()
+    }
+}
+
+impl Default for Links {
+    fn default() -> Self {
+        Links {
+            strip_tracking_params: true,
+            unwrap_redirects: true,
+            rewrite_rules_file: String::new(),
+            domain_rewrites: Vec::new(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -65,12 +253,14 @@ impl Default for Config {
                 alias_max_length: 32,
                 content_max_length: 128000,
                 form_data_limit_kb: 512,
+                upload_max_size_kb: default_upload_max_size_kb(),
             },
             server: Server {
                 port: 8000,
                 address: "127.0.0.1".to_string(),
                 onion_url: String::new(),
                 onion_hostname_file: default_onion_hostname_file(),
+                public_url: String::new(),
             },
             cache: Cache {
                 max_cache_size_mb: 128,
@@ -89,6 +279,13 @@ impl Default for Config {
             theme: Theme {
                 syntax_highlighting: "base16-ocean.dark".to_string(),
             },
+            media: MediaOptions::default(),
+            links: Links::default(),
+            gemini: Gemini::default(),
+            gopher: Gopher::default(),
+            export: Export::default(),
+            archiver: Archiver::default(),
+            mirror: Mirror::default(),
         }
     }
 }
@@ -133,7 +330,48 @@ fn normalize_onion_url(input: &str) -> Option<String> {
     Some(format!("{}://{}{}", scheme, host_lower, path))
 }
 
+#[derive(Debug, Deserialize)]
+struct LinkRulesFile {
+    #[serde(default)]
+    domains: HashMap<String, String>,
+}
+
+/// Reads the domain rewrite rules file. Each entry under `[domains]` maps a
+/// clearnet host to a replacement host or base URL, e.g.
+/// `"twitter.com" = "nitter.net"` or `"example.com" = "http://abc...xyz.onion"`.
+pub fn load_link_rules(path: &str) -> Result<Vec<(String, String)>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read link rules {}: {}", path, e))?;
+    let rules: LinkRulesFile = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse link rules {}: {}", path, e))?;
+
+    let mut domains: Vec<(String, String)> = rules
+        .domains
+        .into_iter()
+        .map(|(from, to)| (from.trim().to_ascii_lowercase(), to.trim().to_string()))
+        .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+        .collect();
+    domains.sort();
+    Ok(domains)
+}
+
 impl Config {
+    /// The rendering settings handed to the markup parser.
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            theme: self.theme.syntax_highlighting.clone(),
+            max_url_length: self.security.max_url_length,
+            external_link_security: self.security.external_link_security,
+            media: self.media.clone(),
+            links: LinkOptions {
+                strip_tracking_params: self.links.strip_tracking_params,
+                unwrap_redirects: self.links.unwrap_redirects,
+                domain_rewrites: self.links.domain_rewrites.clone(),
+            },
+            ..Default::default()
+        }
+    }
+
     pub fn load() -> Result<Self, String> {
         let config_path = Path::new("Config.toml");
 
@@ -141,12 +379,31 @@ impl Config {
             let content = fs::read_to_string(config_path)
                 .map_err(|e| format!("Failed to read Config.toml: {}", e))?;
 
-            toml::from_str(&content).map_err(|e| format!("Failed to parse Config.toml: {}", e))
+            let mut config: Config = toml::from_str(&content)
+                .map_err(|e| format!("Failed to parse Config.toml: {}", e))?;
+
+            config.apply_link_rules();
+            Ok(config)
         } else {
             Ok(Config::default())
         }
     }
 
+    /// Fills `domain_rewrites` from `rewrite_rules_file`. A file that can't
+    /// be loaded is logged and leaves no rules, rather than costing the rest
+    /// of the configuration.
+    fn apply_link_rules(&mut self) {
+        let path = self.links.rewrite_rules_file.trim();
+        if path.is_empty() {
+            return;
+        }
+        self.links.domain_rewrites = load_link_rules(path).unwrap_or_else(|e| {
+            eprintln!("⚠️  {}", e);
+            eprintln!("   Continuing without link rewrite rules");
+            Vec::new()
+        });
+    }
+
     pub fn load_with_logging() -> Self {
         match Self::load() {
             Ok(config) => {
@@ -158,6 +415,24 @@ impl Config {
                     config.limits.content_max_length
                 );
                 println!("   Cache size: {} MB", config.cache.max_cache_size_mb);
+                if !config.links.domain_rewrites.is_empty() {
+                    println!(
+                        "   Link rewrite rules: {}",
+                        config.links.domain_rewrites.len()
+                    );
+                }
+                if config.gemini.enabled {
+                    println!(
+                        "   Gemini: gemini://{}:{}",
+                        config.gemini.hostname, config.gemini.port
+                    );
+                }
+                if config.gopher.enabled {
+                    println!(
+                        "   Gopher: gopher://{}:{}",
+                        config.gopher.hostname, config.gopher.port
+                    );
+                }
                 config
             }
             Err(e) => {
@@ -172,6 +447,18 @@ impl Config {
         self.limits.form_data_limit_kb * 1024
     }
 
+    /// Base for absolute links to posts, without a trailing slash.
+    pub fn public_base_url(&self) -> String {
+        match self.server.public_url.trim().trim_end_matches('/') {
+            "" => format!("http://{}:{}", self.server.address, self.server.port),
+            url => url.to_string(),
+        }
+    }
+
+    pub fn upload_limit_bytes(&self) -> u32 {
+        self.limits.upload_max_size_kb * 1024
+    }
+
     pub fn resolve_onion_url(&self) -> Option<String> {
         let candidate = if !self.server.onion_url.trim().is_empty() {
             self.server.onion_url.trim().to_string()
@@ -231,6 +518,7 @@ mod tests {
         assert_eq!(config.limits.alias_max_length, 32);
         assert_eq!(config.limits.content_max_length, 128000);
         assert_eq!(config.server.port, 8000);
+        assert!(!config.archiver.web_archiving_enabled());
     }
 
     #[test]
@@ -326,4 +614,57 @@ mod tests {
         assert!(default_config.security.external_link_security);
         assert!(default_config.security.csrf_protection_enabled);
     }
+
+    #[test]
+    fn test_media_section_is_optional() {
+        // Configs written before [media] existed still parse and embed everything
+        let mut value: toml::Value = toml::from_str(include_str!("../Config.toml")).unwrap();
+        value.as_table_mut().unwrap().remove("media");
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(config.media.mode, nonograph_markup::MediaMode::Embed);
+    }
+
+    #[test]
+    fn test_render_options_from_config() {
+        let mut config = Config::default();
+        config.security.max_url_length = 64;
+        config.theme.syntax_highlighting = "InspiredGitHub".to_string();
+        config.links.domain_rewrites = vec![("a.com".to_string(), "b.com".to_string())];
+
+        let options = config.render_options();
+        assert_eq!(options.max_url_length, 64);
+        assert_eq!(options.theme, "InspiredGitHub");
+        assert_eq!(options.links.domain_rewrites.len(), 1);
+        assert!(options.features.tables);
+    }
+
+    #[test]
+    fn test_load_link_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("LinkRules.toml");
+        fs::write(
+            &path,
+            "[domains]\n\"Twitter.com\" = \"nitter.net\"\n\"empty.com\" = \"\"\n",
+        )
+        .unwrap();
+
+        let rules = load_link_rules(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            rules,
+            vec![("twitter.com".to_string(), "nitter.net".to_string())]
+        );
+        assert!(load_link_rules("/nonexistent/LinkRules.toml").is_err());
+
+        // A broken rules file costs the rules, not the rest of the config
+        let mut config = Config::default();
+        config.limits.title_max_length = 7;
+        config.links.rewrite_rules_file = path.to_string_lossy().into_owned();
+        config.apply_link_rules();
+        assert_eq!(config.links.domain_rewrites.len(), 1);
+
+        fs::write(&path, "[domains\n").unwrap();
+        config.apply_link_rules();
+        assert!(config.links.domain_rewrites.is_empty());
+        assert_eq!(config.limits.title_max_length, 7);
+    }
 }
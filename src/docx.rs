@@ -0,0 +1,1079 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+
+use chrono::{DateTime, Utc};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::import::resolve_reference;
+
+/// Fonts whose runs are taken to be code
+const MONOSPACE_FONTS: &[&str] = &[
+    "cascadia code",
+    "cascadia mono",
+    "consolas",
+    "courier",
+    "courier new",
+    "dejavu sans mono",
+    "fira code",
+    "jetbrains mono",
+    "liberation mono",
+    "lucida console",
+    "menlo",
+    "monaco",
+    "roboto mono",
+    "source code pro",
+];
+
+/// Cap on each decompressed package part, so a small upload can't inflate
+/// into gigabytes
+const MAX_PART_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Deepest element nesting accepted. The converter recurses through the
+/// tree, so unbounded nesting could overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Markup for each kind of inline formatting, outermost first
+const MARKERS: [&str; 7] = ["**", "*", "_", "~", "==", "^", "`"];
+const BOLD: usize = 0;
+const ITALIC: usize = 1;
+const UNDERLINE: usize = 2;
+const STRIKE: usize = 3;
+const HIGHLIGHT: usize = 4;
+const SUPERSCRIPT: usize = 5;
+const CODE: usize = 6;
+
+/// Which of `MARKERS` apply to a run
+type Format = [bool; MARKERS.len()];
+
+/// A Word document converted to nonograph markup.
+#[derive(Debug)]
+pub struct Document {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub markup: String,
+    /// Content the markup can't represent, which was left out or changed
+    pub warnings: Vec<String>,
+}
+
+/// Converts the bytes of a .docx file. A paragraph in the Title style
+/// becomes the title; otherwise the one in the document properties is used.
+pub fn convert(data: &[u8]) -> Result<Document, String> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Not a .docx file: {}", e))?;
+    let body = read_part(&mut archive, "word/document.xml")?
+        .and_then(|document| document.child("body").cloned())
+        .ok_or("Not a .docx file: it has no document body")?;
+    let properties = read_part(&mut archive, "docProps/core.xml")?;
+    let package = Package::read(&mut archive)?;
+
+    let mut converter = Converter {
+        package: &package,
+        title: None,
+        notes: Vec::new(),
+        unsupported: BTreeMap::new(),
+    };
+    let mut blocks = Vec::new();
+    converter.blocks(&body, &mut blocks);
+
+    let property = |name: &str| {
+        properties
+            .as_ref()
+            .and_then(|properties| properties.child(name))
+            .map(|element| element.text().trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    Ok(Document {
+        title: converter.title.take().or_else(|| property("title")),
+        author: property("creator"),
+        created: property("created")
+            .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.with_timezone(&Utc)),
+        markup: render(&blocks, &converter.notes),
+        warnings: converter
+            .unsupported
+            .iter()
+            .map(|(unsupported, count)| unsupported.warning(*count))
+            .collect(),
+    })
+}
+
+fn read_part(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<XmlElement>, String> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", name, e)),
+    };
+    let size = file.size();
+    let xml = read_capped(&mut file, size, MAX_PART_BYTES)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    parse_xml(&xml)
+        .map(Some)
+        .map_err(|e| format!("Invalid {}: {}", name, e))
+}
+
+/// Reads at most `cap` bytes. `size` is what the zip header claims, which
+/// is checked first; the read itself stops past `cap` in case it lies.
+fn read_capped(reader: impl Read, size: u64, cap: u64) -> Result<String, String> {
+    let too_large = || format!("larger than {} MB", cap / 1024 / 1024);
+    if size > cap {
+        return Err(too_large());
+    }
+    let mut xml = String::new();
+    reader
+        .take(cap + 1)
+        .read_to_string(&mut xml)
+        .map_err(|e| e.to_string())?;
+    if xml.len() as u64 > cap {
+        return Err(too_large());
+    }
+    Ok(xml)
+}
+
+/// An element of a package part. Names are kept without their namespace
+/// prefix, which differs between the programs that write .docx files.
+#[derive(Debug, Clone, Default)]
+struct XmlElement {
+    name: String,
+    attrs: HashMap<String, String>,
+    children: Vec<Xml>,
+}
+
+#[derive(Debug, Clone)]
+enum Xml {
+    Element(XmlElement),
+    Text(String),
+}
+
+impl XmlElement {
+    fn new(start: &BytesStart) -> Result<Self, String> {
+        let mut attrs = HashMap::new();
+        for attr in start.attributes() {
+            let attr = attr.map_err(|e| e.to_string())?;
+            let value = attr
+                .normalized_value(XmlVersion::Implicit1_0)
+                .map_err(|e| e.to_string())?;
+            attrs.insert(
+                attr.key.local_name().as_ref().to_string(),
+                value.into_owned(),
+            );
+        }
+        Ok(Self {
+            name: start.local_name().as_ref().to_string(),
+            attrs,
+            children: Vec::new(),
+        })
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.get(name).map(String::as_str)
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|child| match child {
+            Xml::Element(element) => Some(element),
+            Xml::Text(_) => None,
+        })
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// The `val` of the child `name`, as used by most WordprocessingML properties
+    fn value(&self, name: &str) -> Option<&str> {
+        self.child(name).and_then(|child| child.attr("val"))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.elements()
+            .any(|element| element.name == name || element.contains(name))
+    }
+
+    fn text(&self) -> String {
+        self.children
+            .iter()
+            .map(|child| match child {
+                Xml::Element(element) => element.text(),
+                Xml::Text(text) => text.clone(),
+            })
+            .collect()
+    }
+
+    /// Whether an on/off property such as `<w:b/>` is set, if it's there at all.
+    fn toggle(&self, name: &str) -> Option<bool> {
+        let child = self.child(name)?;
+        Some(!matches!(
+            child.attr("val"),
+            Some("0" | "false" | "off" | "none")
+        ))
+    }
+}
+
+fn parse_xml(xml: &str) -> Result<XmlElement, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut open = vec![XmlElement::default()];
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("at byte {}: {}", reader.error_position(), e))?;
+        let text = match event {
+            Event::Start(start) => {
+                if open.len() > MAX_DEPTH {
+                    return Err(format!("elements nested more than {} deep", MAX_DEPTH));
+                }
+                open.push(XmlElement::new(&start)?);
+                continue;
+            }
+            Event::Empty(start) => {
+                let element = XmlElement::new(&start)?;
+                if let Some(parent) = open.last_mut() {
+                    parent.children.push(Xml::Element(element));
+                }
+                continue;
+            }
+            Event::End(_) => {
+                let element = open.pop().filter(|_| !open.is_empty());
+                match (element, open.last_mut()) {
+                    (Some(element), Some(parent)) => parent.children.push(Xml::Element(element)),
+                    _ => return Err("unexpected closing tag".to_string()),
+                }
+                continue;
+            }
+            Event::Text(content) => content.xml10_content(),
+            Event::CData(content) => content.xml10_content(),
+            Event::GeneralRef(reference) => resolve_reference(&reference)?.into(),
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        if let Some(parent) = open.last_mut() {
+            match parent.children.last_mut() {
+                Some(Xml::Text(previous)) => previous.push_str(&text),
+                _ => parent.children.push(Xml::Text(text.into_owned())),
+            }
+        }
+    }
+
+    if open.len() != 1 {
+        return Err("unclosed element".to_string());
+    }
+    open.pop()
+        .and_then(|document| document.elements().next().cloned())
+        .ok_or_else(|| "no root element".to_string())
+}
+
+#[derive(Debug, Default)]
+struct Style {
+    /// Lowercased, as Word and LibreOffice capitalise built-in names differently
+    name: String,
+    based_on: Option<String>,
+    paragraph: Option<XmlElement>,
+    run: Option<XmlElement>,
+}
+
+/// The parts of the package the document body refers to.
+#[derive(Default)]
+struct Package {
+    styles: HashMap<String, Style>,
+    /// Whether each level of each numbering is ordered, by `numId` and `ilvl`
+    lists: HashMap<String, HashMap<String, bool>>,
+    /// Relationship targets, such as hyperlink URLs, by id
+    targets: HashMap<String, String>,
+    footnotes: HashMap<String, XmlElement>,
+    endnotes: HashMap<String, XmlElement>,
+}
+
+impl Package {
+    fn read(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Self, String> {
+        let mut package = Package::default();
+
+        if let Some(styles) = read_part(archive, "word/styles.xml")? {
+            for style in styles.elements().filter(|element| element.name == "style") {
+                let Some(id) = style.attr("styleId") else {
+                    continue;
+                };
+                package.styles.insert(
+                    id.to_string(),
+                    Style {
+                        name: style.value("name").unwrap_or(id).to_lowercase(),
+                        based_on: style.value("basedOn").map(str::to_string),
+                        paragraph: style.child("pPr").cloned(),
+                        run: style.child("rPr").cloned(),
+                    },
+                );
+            }
+        }
+
+        if let Some(numbering) = read_part(archive, "word/numbering.xml")? {
+            let mut abstract_lists = HashMap::new();
+            for list in numbering
+                .elements()
+                .filter(|element| element.name == "abstractNum")
+            {
+                let levels: HashMap<String, bool> = list
+                    .elements()
+                    .filter(|element| element.name == "lvl")
+                    .filter_map(|level| {
+                        let format = level.value("numFmt").unwrap_or("bullet");
+                        Some((
+                            level.attr("ilvl")?.to_string(),
+                            !matches!(format, "bullet" | "none"),
+                        ))
+                    })
+                    .collect();
+                if let Some(id) = list.attr("abstractNumId") {
+                    abstract_lists.insert(id.to_string(), levels);
+                }
+            }
+            for num in numbering.elements().filter(|element| element.name == "num") {
+                let levels = num
+                    .value("abstractNumId")
+                    .and_then(|id| abstract_lists.get(id));
+                if let (Some(id), Some(levels)) = (num.attr("numId"), levels) {
+                    package.lists.insert(id.to_string(), levels.clone());
+                }
+            }
+        }
+
+        if let Some(relationships) = read_part(archive, "word/_rels/document.xml.rels")? {
+            for relationship in relationships.elements() {
+                if let (Some(id), Some(target)) =
+                    (relationship.attr("Id"), relationship.attr("Target"))
+                {
+                    package.targets.insert(id.to_string(), target.to_string());
+                }
+            }
+        }
+
+        for (part, notes) in [
+            ("word/footnotes.xml", &mut package.footnotes),
+            ("word/endnotes.xml", &mut package.endnotes),
+        ] {
+            if let Some(part) = read_part(archive, part)? {
+                for note in part.elements() {
+                    if let Some(id) = note.attr("id") {
+                        notes.insert(id.to_string(), note.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(package)
+    }
+
+    /// A style followed by the styles it's based on.
+    fn style_chain(&self, id: &str) -> Vec<&Style> {
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+        // Bounded, in case a broken document has a loop
+        while let Some(style) = next.and_then(|id| self.styles.get(id)) {
+            if chain.len() == 10 {
+                break;
+            }
+            chain.push(style);
+            next = style.based_on.as_deref();
+        }
+        chain
+    }
+
+    fn paragraph_kind(&self, properties: Option<&XmlElement>) -> ParagraphKind {
+        let chain = properties
+            .and_then(|properties| properties.value("pStyle"))
+            .map(|id| self.style_chain(id))
+            .unwrap_or_default();
+
+        for style in &chain {
+            let name = style.name.as_str();
+            if name == "title" {
+                return ParagraphKind::Title;
+            }
+            if let Some(level) = name
+                .strip_prefix("heading ")
+                .and_then(|level| level.parse().ok())
+            {
+                return ParagraphKind::Heading(level);
+            }
+            if name.contains("code") || name.contains("preformatted") {
+                return ParagraphKind::Code;
+            }
+            if name.contains("quot") || name == "block text" {
+                return ParagraphKind::Quote;
+            }
+        }
+
+        // Direct paragraph properties take precedence over the style's
+        let properties: Vec<&XmlElement> = properties
+            .into_iter()
+            .chain(chain.iter().filter_map(|style| style.paragraph.as_ref()))
+            .collect();
+        let numbering = properties
+            .iter()
+            .find_map(|properties| properties.child("numPr"));
+        if let Some(id) = numbering
+            .and_then(|numbering| numbering.value("numId"))
+            .filter(|id| *id != "0")
+        {
+            let level = numbering.and_then(|numbering| numbering.value("ilvl"));
+            let ordered = self
+                .lists
+                .get(id)
+                .and_then(|levels| levels.get(level.unwrap_or("0")))
+                .copied()
+                .unwrap_or(false);
+            return ParagraphKind::ListItem {
+                level: level.and_then(|level| level.parse().ok()).unwrap_or(0),
+                ordered,
+            };
+        }
+        match properties
+            .iter()
+            .find_map(|properties| properties.value("outlineLvl"))
+            .and_then(|level| level.parse::<usize>().ok())
+        {
+            // Level 9 is body text
+            Some(level) if level < 9 => ParagraphKind::Heading(level + 1),
+            _ => ParagraphKind::Text,
+        }
+    }
+
+    /// Formatting from a run's character style, overridden by its own.
+    fn run_format(&self, properties: Option<&XmlElement>) -> Format {
+        let mut format = Format::default();
+        if let Some(id) = properties.and_then(|properties| properties.value("rStyle")) {
+            for style in self.style_chain(id).iter().rev() {
+                if style.name.contains("code") || style.name.contains("typewriter") {
+                    format[CODE] = true;
+                }
+                if let Some(run) = &style.run {
+                    apply_run_properties(&mut format, run);
+                }
+            }
+        }
+        if let Some(properties) = properties {
+            apply_run_properties(&mut format, properties);
+        }
+        format
+    }
+}
+
+fn apply_run_properties(format: &mut Format, properties: &XmlElement) {
+    for (name, marker) in [
+        ("b", BOLD),
+        ("i", ITALIC),
+        ("u", UNDERLINE),
+        ("strike", STRIKE),
+        ("dstrike", STRIKE),
+        ("highlight", HIGHLIGHT),
+    ] {
+        if let Some(on) = properties.toggle(name) {
+            format[marker] = on;
+        }
+    }
+    if let Some(alignment) = properties.value("vertAlign") {
+        format[SUPERSCRIPT] = alignment == "superscript";
+    }
+    if let Some(fonts) = properties.child("rFonts") {
+        if let Some(font) = fonts.attr("ascii").or_else(|| fonts.attr("hAnsi")) {
+            format[CODE] = MONOSPACE_FONTS.contains(&font.to_lowercase().as_str());
+        }
+    }
+}
+
+enum ParagraphKind {
+    Title,
+    Heading(usize),
+    Quote,
+    Code,
+    ListItem { level: usize, ordered: bool },
+    Text,
+}
+
+/// Things the markup has no way to show, counted so they can be reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Unsupported {
+    Image,
+    Drawing,
+    TextBox,
+    EmbeddedObject,
+    Equation,
+    Comment,
+    MergedCell,
+    NestedTable,
+}
+
+impl Unsupported {
+    fn warning(self, count: usize) -> String {
+        let (verb, one, many) = match self {
+            Unsupported::Image => ("Left out", "an embedded image", "embedded images"),
+            Unsupported::Drawing => ("Left out", "a chart or shape", "charts and shapes"),
+            Unsupported::TextBox => ("Left out", "a text box", "text boxes"),
+            Unsupported::EmbeddedObject => ("Left out", "an embedded object", "embedded objects"),
+            Unsupported::Equation => ("Left out", "an equation", "equations"),
+            Unsupported::Comment => ("Left out", "a comment", "comments"),
+            Unsupported::MergedCell => ("Split", "a merged table cell", "merged table cells"),
+            Unsupported::NestedTable => ("Left out", "a nested table", "nested tables"),
+        };
+        match count {
+            1 => format!("{} {}", verb, one),
+            _ => format!("{} {} {}", verb, count, many),
+        }
+    }
+}
+
+enum Block {
+    Text(String),
+    Heading(usize, String),
+    Quote(String),
+    Code(String),
+    List(Vec<(usize, bool, String)>),
+    Table(String),
+}
+
+struct Converter<'a> {
+    package: &'a Package,
+    title: Option<String>,
+    /// Footnotes and endnotes, in the order they're first referenced
+    notes: Vec<String>,
+    unsupported: BTreeMap<Unsupported, usize>,
+}
+
+impl Converter<'_> {
+    fn skip(&mut self, unsupported: Unsupported) {
+        *self.unsupported.entry(unsupported).or_default() += 1;
+    }
+
+    fn blocks(&mut self, parent: &XmlElement, blocks: &mut Vec<Block>) {
+        for element in parent.elements() {
+            match element.name.as_str() {
+                "p" => self.paragraph(element, blocks),
+                "tbl" => {
+                    let table = self.table(element);
+                    if !table.is_empty() {
+                        blocks.push(Block::Table(table));
+                    }
+                }
+                "sdt" => {
+                    if let Some(content) = element.child("sdtContent") {
+                        self.blocks(content, blocks);
+                    }
+                }
+                "customXml" | "ins" => self.blocks(element, blocks),
+                "AlternateContent" => {
+                    if let Some(choice) = element.child("Choice") {
+                        self.blocks(choice, blocks);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn paragraph(&mut self, paragraph: &XmlElement, blocks: &mut Vec<Block>) {
+        let kind = self.package.paragraph_kind(paragraph.child("pPr"));
+        let mut inline = Inline::default();
+        self.inline(paragraph, &mut inline);
+
+        // A paragraph set entirely in a monospace font is a line of code
+        let kind = match kind {
+            ParagraphKind::Text if inline.is_code() => ParagraphKind::Code,
+            kind => kind,
+        };
+        if let ParagraphKind::Code = kind {
+            let line = inline.plain.trim_end().to_string();
+            match blocks.last_mut() {
+                Some(Block::Code(code)) => {
+                    code.push('\n');
+                    code.push_str(&line);
+                }
+                _ => blocks.push(Block::Code(line)),
+            }
+            return;
+        }
+
+        let text = inline.finish();
+        if text.is_empty() {
+            return;
+        }
+        match kind {
+            ParagraphKind::Title if self.title.is_none() => {
+                self.title = Some(text.replace('\n', " "))
+            }
+            ParagraphKind::Title => blocks.push(Block::Heading(1, text)),
+            ParagraphKind::Heading(level) => blocks.push(Block::Heading(level, text)),
+            ParagraphKind::Quote => blocks.push(Block::Quote(text)),
+            ParagraphKind::ListItem { level, ordered } => match blocks.last_mut() {
+                Some(Block::List(items)) => items.push((level, ordered, text)),
+                _ => blocks.push(Block::List(vec![(level, ordered, text)])),
+            },
+            ParagraphKind::Code | ParagraphKind::Text => blocks.push(Block::Text(text)),
+        }
+    }
+
+    /// The runs and links of a paragraph, or of something inside one.
+    fn inline(&mut self, parent: &XmlElement, inline: &mut Inline) {
+        for element in parent.elements() {
+            match element.name.as_str() {
+                "r" => {
+                    let format = self.package.run_format(element.child("rPr"));
+                    self.run(element, format, inline);
+                }
+                "hyperlink" => {
+                    let mut text = Inline::default();
+                    self.inline(element, &mut text);
+                    let plain = text.plain.clone();
+                    let text = text.finish();
+                    // Links to bookmarks in the document have nowhere to go
+                    match element
+                        .attr("id")
+                        .and_then(|id| self.package.targets.get(id))
+                    {
+                        Some(target) if !text.is_empty() => {
+                            inline.raw(&format!("[{}]({})", text, target), &plain)
+                        }
+                        _ => inline.raw(&text, &plain),
+                    }
+                }
+                "ins" | "smartTag" | "customXml" | "fldSimple" | "moveTo" => {
+                    self.inline(element, inline)
+                }
+                "sdt" => {
+                    if let Some(content) = element.child("sdtContent") {
+                        self.inline(content, inline);
+                    }
+                }
+                "oMath" | "oMathPara" => self.skip(Unsupported::Equation),
+                "AlternateContent" => {
+                    if let Some(choice) = element.child("Choice") {
+                        self.inline(choice, inline);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run(&mut self, run: &XmlElement, format: Format, inline: &mut Inline) {
+        for element in run.elements() {
+            match element.name.as_str() {
+                "t" => inline.push(&element.text(), format),
+                "tab" => inline.push(" ", format),
+                "br" if matches!(element.attr("type"), None | Some("textWrapping")) => {
+                    inline.push("\n", format)
+                }
+                "cr" => inline.push("\n", format),
+                "noBreakHyphen" => inline.push("-", format),
+                "footnoteReference" | "endnoteReference" => {
+                    if let Some(reference) = self.note(element) {
+                        inline.raw(&reference, "");
+                    }
+                }
+                "commentReference" => self.skip(Unsupported::Comment),
+                "drawing" | "pict" => self.skip(if element.contains("txbxContent") {
+                    Unsupported::TextBox
+                } else if element.contains("blip") || element.contains("imagedata") {
+                    Unsupported::Image
+                } else {
+                    Unsupported::Drawing
+                }),
+                "object" => self.skip(Unsupported::EmbeddedObject),
+                "AlternateContent" => {
+                    if let Some(choice) = element.child("Choice") {
+                        self.run(choice, format, inline);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Converts a footnote or endnote and returns the reference to it.
+    fn note(&mut self, reference: &XmlElement) -> Option<String> {
+        let package = self.package;
+        let notes = match reference.name.as_str() {
+            "footnoteReference" => &package.footnotes,
+            _ => &package.endnotes,
+        };
+        let note = notes.get(reference.attr("id")?)?;
+
+        let mut text = Inline::default();
+        for paragraph in note.elements().filter(|element| element.name == "p") {
+            text.push(" ", Format::default());
+            self.inline(paragraph, &mut text);
+        }
+        // Definitions are a single line
+        self.notes.push(text.finish().replace('\n', " "));
+        Some(format!("[^{}]", self.notes.len()))
+    }
+
+    fn table(&mut self, table: &XmlElement) -> String {
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for row in table.elements().filter(|element| element.name == "tr") {
+            let mut cells = Vec::new();
+            for cell in row.elements().filter(|element| element.name == "tc") {
+                let properties = cell.child("tcPr");
+                let span = properties
+                    .and_then(|properties| properties.value("gridSpan"))
+                    .and_then(|span| span.parse().ok())
+                    .unwrap_or(1usize)
+                    .max(1);
+                let merge = properties.and_then(|properties| properties.child("vMerge"));
+                // Cells below the first of a vertical merge hold nothing
+                let continued = merge.is_some_and(|merge| merge.attr("val") != Some("restart"));
+                if span > 1 || merge.is_some_and(|_| !continued) {
+                    self.skip(Unsupported::MergedCell);
+                }
+
+                cells.push(if continued {
+                    String::new()
+                } else {
+                    self.cell(cell)
+                });
+                cells.extend(std::iter::repeat_n(String::new(), span - 1));
+            }
+            rows.push(cells);
+        }
+
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if width == 0 {
+            return String::new();
+        }
+        let mut lines = Vec::new();
+        for (index, mut cells) in rows.into_iter().enumerate() {
+            cells.resize(width, String::new());
+            lines.push(format!("| {} |", cells.join(" | ")));
+            // The first row is the header
+            if index == 0 {
+                lines.push(format!("|{}", "---|".repeat(width)));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn cell(&mut self, cell: &XmlElement) -> String {
+        let mut paragraphs = Vec::new();
+        for element in cell.elements() {
+            match element.name.as_str() {
+                "p" => {
+                    let mut inline = Inline::default();
+                    self.inline(element, &mut inline);
+                    paragraphs.push(inline.finish());
+                }
+                "tbl" => self.skip(Unsupported::NestedTable),
+                _ => {}
+            }
+        }
+
+        // Cells are a single line, and `|` would end them early
+        paragraphs
+            .iter()
+            .filter(|text| !text.is_empty())
+            .map(|text| text.replace('\n', " "))
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace('|', "¦")
+    }
+}
+
+/// Builds a line of markup from formatted runs, opening and closing
+/// markers as the formatting changes. Whitespace is kept outside markers,
+/// since markup like `**bold **` isn't recognised.
+#[derive(Default)]
+struct Inline {
+    markup: String,
+    open: Vec<usize>,
+    pending: String,
+    /// The text without any formatting
+    plain: String,
+    /// Whether any text isn't code
+    prose: bool,
+}
+
+impl Inline {
+    fn push(&mut self, text: &str, format: Format) {
+        self.plain.push_str(text);
+        let core = text.trim();
+        if core.is_empty() {
+            self.pending.push_str(text);
+            return;
+        }
+        if !format[CODE] {
+            self.prose = true;
+        }
+
+        let start = text.len() - text.trim_start().len();
+        self.pending.push_str(&text[..start]);
+        self.switch(format);
+        self.markup.push_str(core);
+        self.pending.push_str(&text[start + core.len()..]);
+    }
+
+    /// Adds markup that's already formatted, such as a link.
+    fn raw(&mut self, markup: &str, plain: &str) {
+        self.plain.push_str(plain);
+        self.prose = true;
+        self.switch(Format::default());
+        self.markup.push_str(markup);
+    }
+
+    fn switch(&mut self, format: Format) {
+        let keep = self
+            .open
+            .iter()
+            .position(|marker| !format[*marker])
+            .unwrap_or(self.open.len());
+        for marker in self.open.drain(keep..).rev() {
+            self.markup.push_str(MARKERS[marker]);
+        }
+        self.markup.push_str(&std::mem::take(&mut self.pending));
+        for (marker, on) in format.iter().enumerate() {
+            if *on && !self.open.contains(&marker) {
+                self.markup.push_str(MARKERS[marker]);
+                self.open.push(marker);
+            }
+        }
+    }
+
+    fn is_code(&self) -> bool {
+        !self.prose && !self.plain.trim().is_empty()
+    }
+
+    fn finish(mut self) -> String {
+        self.switch(Format::default());
+        self.markup.trim().to_string()
+    }
+}
+
+fn render(blocks: &[Block], notes: &[String]) -> String {
+    let mut sections = Vec::new();
+    for block in blocks {
+        sections.push(match block {
+            Block::Text(text) => text.clone(),
+            Block::Heading(level, text) => {
+                format!("{} {}", "#".repeat((*level).clamp(1, 4)), text)
+            }
+            Block::Quote(text) => format!("> {}", text.replace('\n', "\n> ")),
+            Block::Code(code) => {
+                let code = code.trim_matches('\n');
+                if code.trim().is_empty() {
+                    continue;
+                }
+                format!("```\n{}\n```", code)
+            }
+            Block::List(items) => {
+                let mut counters: Vec<usize> = Vec::new();
+                let mut lines = Vec::new();
+                for (level, ordered, text) in items {
+                    counters.resize(level + 1, 0);
+                    counters[*level] += 1;
+                    let marker = match ordered {
+                        true => format!("{}.", counters[*level]),
+                        false => "-".to_string(),
+                    };
+                    lines.push(format!("{}{} {}", "  ".repeat(*level), marker, text));
+                }
+                lines.join("\n")
+            }
+            Block::Table(table) => table.clone(),
+        });
+    }
+
+    if !notes.is_empty() {
+        let definitions: Vec<String> = notes
+            .iter()
+            .enumerate()
+            .map(|(index, note)| format!("[^{}]: {}", index + 1, note))
+            .collect();
+        sections.push(definitions.join("\n"));
+    }
+    sections.join("\n\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+    fn docx(parts: &[(&str, String)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, xml) in parts {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(xml.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn paragraph(style: &str, runs: &str) -> String {
+        format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="{}"/></w:pPr>{}</w:p>"#,
+            style, runs
+        )
+    }
+
+    fn run(properties: &str, text: &str) -> String {
+        format!(
+            r#"<w:r><w:rPr>{}</w:rPr><w:t xml:space="preserve">{}</w:t></w:r>"#,
+            properties, text
+        )
+    }
+
+    #[test]
+    fn test_convert() {
+        let body = [
+            paragraph("Title", &run("", "Field Notes")),
+            paragraph("Heading1", &run("", "Spring")),
+            paragraph(
+                "Normal",
+                &[
+                    run("", "Plain, "),
+                    run("<w:b/>", "bold "),
+                    run("<w:b/><w:i/>", "both"),
+                    run("", ", "),
+                    run("<w:u w:val=\"single\"/>", "under"),
+                    run("", " and "),
+                    run("<w:strike/>", "gone"),
+                    run("", ". Run "),
+                    run("<w:rStyle w:val=\"CodeChar\"/>", "make"),
+                    run("", " then "),
+                    r#"<w:hyperlink r:id="rId9"><w:r><w:t>read &amp; see</w:t></w:r></w:hyperlink>"#.to_string(),
+                    r#"<w:r><w:footnoteReference w:id="1"/></w:r>"#.to_string(),
+                    r#"<w:r><w:commentReference w:id="0"/></w:r>"#.to_string(),
+                ]
+                .concat(),
+            ),
+            paragraph(
+                "Normal",
+                r#"<w:r><w:drawing><a:graphic xmlns:a="urn:a"><a:blip r:embed="rId5"/></a:graphic></w:drawing></w:r>"#,
+            ),
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>First</w:t></w:r></w:p>"#.to_string(),
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Nested</w:t></w:r></w:p>"#.to_string(),
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Second</w:t></w:r></w:p>"#.to_string(),
+            paragraph("Normal", &run("<w:rFonts w:ascii=\"Consolas\"/>", "fn main() {}")),
+            paragraph("Normal", &run("<w:rFonts w:ascii=\"Consolas\"/>", "  // done")),
+            r#"<w:tbl><w:tr><w:tc><w:tcPr><w:gridSpan w:val="2"/></w:tcPr><w:p><w:r><w:t>Both</w:t></w:r></w:p></w:tc></w:tr>
+               <w:tr><w:tc><w:p><w:r><w:t>a|b</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>c</w:t></w:r></w:p></w:tc></w:tr></w:tbl>"#.to_string(),
+        ]
+        .concat();
+
+        let data = docx(&[
+            (
+                "word/document.xml",
+                format!("<w:document {}><w:body>{}<w:sectPr/></w:body></w:document>", W, body),
+            ),
+            (
+                "word/styles.xml",
+                format!(
+                    r#"<w:styles {}>
+                    <w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/></w:style>
+                    <w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>
+                    <w:style w:type="character" w:styleId="CodeChar"><w:name w:val="Inline Code"/></w:style>
+                    </w:styles>"#,
+                    W
+                ),
+            ),
+            (
+                "word/numbering.xml",
+                format!(
+                    r#"<w:numbering {}>
+                    <w:abstractNum w:abstractNumId="4"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl>
+                    <w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+                    <w:num w:numId="1"><w:abstractNumId w:val="4"/></w:num>
+                    </w:numbering>"#,
+                    W
+                ),
+            ),
+            (
+                "word/_rels/document.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                <Relationship Id="rId9" Type="hyperlink" Target="https://example.com/notes?a=1&amp;b=2" TargetMode="External"/>
+                </Relationships>"#
+                    .to_string(),
+            ),
+            (
+                "word/footnotes.xml",
+                format!(
+                    r#"<w:footnotes {}><w:footnote w:type="separator" w:id="-1"><w:p/></w:footnote>
+                    <w:footnote w:id="1"><w:p><w:r><w:footnoteRef/></w:r>{}</w:p></w:footnote></w:footnotes>"#,
+                    W,
+                    run("", " Seen in "),
+                )
+            ),
+            (
+                "docProps/core.xml",
+                r#"<cp:coreProperties xmlns:cp="urn:cp" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+                <dc:title>Template title</dc:title><dc:creator>Ann</dc:creator>
+                <dcterms:created>2024-05-06T07:08:09Z</dcterms:created></cp:coreProperties>"#
+                    .to_string(),
+            ),
+        ]);
+
+        let document = convert(&data).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Field Notes"));
+        assert_eq!(document.author.as_deref(), Some("Ann"));
+        assert_eq!(
+            document.created.unwrap().to_rfc3339(),
+            "2024-05-06T07:08:09+00:00"
+        );
+        assert_eq!(
+            document.markup,
+            "# Spring\n\n\
+             Plain, **bold *both***, _under_ and ~gone~. Run `make` then \
+             [read & see](https://example.com/notes?a=1&b=2)[^1]\n\n\
+             1. First\n  - Nested\n2. Second\n\n\
+             ```\nfn main() {}\n  // done\n```\n\n\
+             | Both |  |\n|---|---|\n| a¦b | c |\n\n\
+             [^1]: Seen in\n"
+        );
+        assert_eq!(
+            document.warnings,
+            vec![
+                "Left out an embedded image",
+                "Left out a comment",
+                "Split a merged table cell"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_rejects_other_files() {
+        assert!(convert(b"not a zip").is_err());
+        let data = docx(&[("content.xml", "<office:document/>".to_string())]);
+        assert_eq!(
+            convert(&data).unwrap_err(),
+            "Not a .docx file: it has no document body"
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let nested = format!(
+            "<w:document {}><w:body>{}{}</w:body></w:document>",
+            W,
+            "<w:sdt>".repeat(MAX_DEPTH),
+            "</w:sdt>".repeat(MAX_DEPTH)
+        );
+        let err = convert(&docx(&[("word/document.xml", nested)])).unwrap_err();
+        assert!(err.contains("nested more than"), "{}", err);
+
+        // A header claiming a small size doesn't let more through
+        let data = "x".repeat(2 * 1024 * 1024 + 1);
+        let cap = 2 * 1024 * 1024;
+        assert!(read_capped(data.as_bytes(), 0, cap).is_err());
+        assert!(read_capped(data.as_bytes(), cap + 1, cap).is_err());
+        assert_eq!(
+            read_capped(&data.as_bytes()[1..], 0, cap).unwrap().len(),
+            cap as usize
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::fetch::EmbeddedImage;
+use crate::nojs;
+
+/// Turns a rendered post page into a file that works offline and on its own:
+/// scripts are removed, the `<noscript>` fallbacks apply unconditionally so
+/// spoilers and code blocks read without JavaScript, and downloaded images
+/// are inlined as data URIs. Code highlighting is already inline styles.
+/// Site-relative links, such as the footer's, are made absolute against
+/// `base_url` so they still lead back to the instance.
+pub fn single_file_html(
+    page_html: &str,
+    images: &BTreeMap<String, EmbeddedImage>,
+    base_url: &str,
+) -> String {
+    let mut html = nojs::strip_javascript(page_html)
+        .replace("<noscript>", "")
+        .replace("</noscript>", "")
+        .replacen("<body>", "<body class=\"noscript\">", 1);
+
+    for (url, image) in images {
+        // Attribute values in rendered content are escaped like this
+        let escaped = url.replace('&', "&amp;").replace('"', "&quot;");
+        html = html.replace(
+            &format!("src=\"{}\"", escaped),
+            &format!(
+                "src=\"data:{};base64,{}\"",
+                image.media_type,
+                STANDARD.encode(&image.data)
+            ),
+        );
+    }
+
+    for attr in ["href", "src"] {
+        html = absolute_links(&html, attr, base_url);
+    }
+
+    html
+}
+
+/// Prefixes `base_url` to every site-relative `attr` value. Protocol-relative
+/// values (`//host/path`) are left alone.
+fn absolute_links(html: &str, attr: &str, base_url: &str) -> String {
+    let marker = format!("{}=\"/", attr);
+    let mut parts = html.split(marker.as_str());
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        result.push_str(&format!("{}=\"", attr));
+        if !part.starts_with('/') {
+            result.push_str(base_url);
+        }
+        result.push('/');
+        result.push_str(part);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_file_html() {
+        let page = "<html><head><style>p{}</style></head><body><p>Hi</p><img src=\"https://example.com/a.png?x=1&amp;y=2\" alt=\"a\"><img src=\"https://example.com/b.png\"><a href=\"/legal\">legal</a><a href=\"//example.com/\">x</a><noscript><style>.secret{}</style></noscript><script>alert(1)</script></body></html>";
+        let mut images = BTreeMap::new();
+        images.insert(
+            "https://example.com/a.png?x=1&y=2".to_string(),
+            EmbeddedImage {
+                media_type: "image/png".to_string(),
+                data: vec![1, 2, 3],
+            },
+        );
+
+        assert_eq!(
+            single_file_html(page, &images, "https://nonogra.ph"),
+            "<html><head><style>p{}</style></head><body class=\"noscript\"><p>Hi</p><img src=\"data:image/png;base64,AQID\" alt=\"a\"><img src=\"https://example.com/b.png\"><a href=\"https://nonogra.ph/legal\">legal</a><a href=\"//example.com/\">x</a><style>.secret{}</style></body></html>"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+pub const PAYLOAD_VERSION: u32 = 1;
+
+/// AES-GCM IVs are 12 bytes, which is 16 characters of base64
+const IV_BASE64_LEN: usize = 16;
+
+/// Ciphertext of an end-to-end encrypted post as produced by the browser.
+/// The server stores this verbatim and never sees the key, which lives in the
+/// URL fragment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    pub v: u32,
+    pub iv: String,
+    pub ct: String,
+}
+
+impl EncryptedPayload {
+    pub fn validate(&self, max_ciphertext_len: usize) -> Result<(), String> {
+        if self.v != PAYLOAD_VERSION {
+            return Err("unsupported_payload_version".to_string());
+        }
+
+        if self.iv.len() != IV_BASE64_LEN || !is_base64(&self.iv) {
+            return Err("invalid_iv".to_string());
+        }
+
+        if self.ct.is_empty() || !is_base64(&self.ct) {
+            return Err("invalid_ciphertext".to_string());
+        }
+
+        if self.ct.len() > max_ciphertext_len {
+            return Err("content_too_long".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Serialises the payload into the body stored after the frontmatter.
+    pub fn to_storage(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_storage(raw: &str) -> Option<Self> {
+        serde_json::from_str(raw.trim()).ok()
+    }
+}
+
+fn is_base64(value: &str) -> bool {
+    let body = value.trim_end_matches('=');
+    value.len() - body.len() <= 2
+        && body
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> EncryptedPayload {
+        EncryptedPayload {
+            v: 1,
+            iv: "AAECAwQFBgcICQoL".to_string(),
+            ct: "c2VjcmV0IGJ5dGVzIGdvIGhlcmU=".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_payload() {
+        assert!(payload().validate(1024).is_ok());
+
+        let mut bad_version = payload();
+        bad_version.v = 2;
+        assert_eq!(
+            bad_version.validate(1024).unwrap_err(),
+            "unsupported_payload_version"
+        );
+
+        let mut bad_iv = payload();
+        bad_iv.iv = "short".to_string();
+        assert_eq!(bad_iv.validate(1024).unwrap_err(), "invalid_iv");
+
+        let mut bad_ct = payload();
+        bad_ct.ct = "<script>alert(1)</script>".to_string();
+        assert_eq!(bad_ct.validate(1024).unwrap_err(), "invalid_ciphertext");
+
+        assert_eq!(payload().validate(8).unwrap_err(), "content_too_long");
+    }
+
+    #[test]
+    fn test_storage_round_trip() {
+        let stored = payload().to_storage();
+        assert!(stored.starts_with('{'));
+        assert_eq!(EncryptedPayload::from_storage(&stored), Some(payload()));
+        assert_eq!(
+            EncryptedPayload::from_storage(&format!("{}\n", stored)),
+            Some(payload())
+        );
+        assert_eq!(EncryptedPayload::from_storage("plain markdown"), None);
+    }
+}
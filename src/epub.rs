@@ -0,0 +1,629 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Write};
+
+use nonograph_markup::html_escape;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::fetch::EmbeddedImage;
+use crate::Post;
+
+const STYLESHEET: &str = include_str!("../templates/epub.css");
+
+/// Elements that never have content and must be self-closed in XHTML
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "br", "col", "embed", "hr", "img", "input", "source", "track", "wbr",
+];
+
+/// Builds an EPUB 3 book from a post's rendered HTML.
+pub fn build_epub(
+    post: &Post,
+    generator: &str,
+    images: &BTreeMap<String, EmbeddedImage>,
+) -> Result<Vec<u8>, String> {
+    let image_paths: BTreeMap<&str, String> = images
+        .iter()
+        .enumerate()
+        .map(|(index, (url, image))| {
+            let path = format!(
+                "images/image-{}.{}",
+                index + 1,
+                extension_for(&image.media_type)
+            );
+            (url.as_str(), path)
+        })
+        .collect();
+
+    let (body, headings) = to_xhtml(&post.content, &image_paths);
+    let remote_media = body.contains("<source src=\"http") || body.contains("<video src=\"http");
+
+    let title = html_escape(&post.title);
+    let author = if post.author.is_empty() {
+        "Anonymous".to_string()
+    } else {
+        html_escape(&post.author)
+    };
+
+    let content = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="en" lang="en">
+<head>
+<meta charset="utf-8"/>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+<header class="post-header">
+<h1>{title}</h1>
+<p class="article-meta">{author} · {date}</p>
+</header>
+<section class="article-content">
+{body}
+</section>
+</body>
+</html>
+"#,
+        title = title,
+        author = author,
+        date = post.created_at.format("%B %d, %Y"),
+        body = body,
+    );
+
+    let nav = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="en" lang="en">
+<head>
+<meta charset="utf-8"/>
+<title>{title}</title>
+</head>
+<body>
+<nav epub:type="toc" id="toc">
+<h1>Contents</h1>
+<ol>
+<li><a href="content.xhtml">{title}</a>{headings}</li>
+</ol>
+</nav>
+</body>
+</html>
+"#,
+        title = title,
+        headings = nav_list(&headings),
+    );
+
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    manifest.push_str(&format!(
+        "<item id=\"content\" href=\"content.xhtml\" media-type=\"application/xhtml+xml\"{}/>\n",
+        if remote_media {
+            " properties=\"remote-resources\""
+        } else {
+            ""
+        }
+    ));
+    manifest.push_str("<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n");
+    for (index, (url, image)) in images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            image_paths[url.as_str()],
+            image.media_type
+        ));
+    }
+
+    let package = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="en">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">urn:nonograph:{id}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:creator>{author}</dc:creator>
+<dc:language>en</dc:language>
+<dc:date>{date}</dc:date>
+<meta property="dcterms:modified">{modified}</meta>
+<meta name="generator" content="{generator}"/>
+</metadata>
+<manifest>
+{manifest}</manifest>
+<spine>
+<itemref idref="content"/>
+</spine>
+</package>
+"#,
+        id = html_escape(&post.id),
+        title = title,
+        author = author,
+        date = post.created_at.format("%Y-%m-%d"),
+        modified = post.created_at.format("%Y-%m-%dT%H:%M:%SZ"),
+        generator = html_escape(generator),
+        manifest = manifest,
+    );
+
+    let container = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+    let mut files: Vec<(String, &[u8])> = vec![
+        ("META-INF/container.xml".to_string(), container.as_bytes()),
+        ("OEBPS/content.opf".to_string(), package.as_bytes()),
+        ("OEBPS/nav.xhtml".to_string(), nav.as_bytes()),
+        ("OEBPS/content.xhtml".to_string(), content.as_bytes()),
+        ("OEBPS/style.css".to_string(), STYLESHEET.as_bytes()),
+    ];
+    for (url, image) in images {
+        files.push((
+            format!("OEBPS/{}", image_paths[url.as_str()]),
+            image.data.as_slice(),
+        ));
+    }
+
+    write_zip(&files).map_err(|e| format!("Failed to write EPUB: {}", e))
+}
+
+/// The `mimetype` entry has to come first and be stored uncompressed so
+/// readers can sniff the format from the first bytes of the file.
+fn write_zip(files: &[(String, &[u8])]) -> zip::result::ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    zip.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        zip.start_file(name.as_str(), deflated)?;
+        zip.write_all(data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn extension_for(media_type: &str) -> &'static str {
+    match media_type {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "jpg",
+    }
+}
+
+/// Nested `<ol>` for the table of contents. Each heading takes the deeper
+/// headings that follow it as children.
+fn nav_list(headings: &[Heading]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("<ol>");
+    let mut index = 0;
+    while index < headings.len() {
+        let heading = &headings[index];
+        let end = headings[index + 1..]
+            .iter()
+            .position(|next| next.level <= heading.level)
+            .map_or(headings.len(), |offset| index + 1 + offset);
+
+        out.push_str(&format!(
+            "<li><a href=\"content.xhtml#{}\">{}</a>{}</li>",
+            heading.id,
+            heading.text.trim(),
+            nav_list(&headings[index + 1..end])
+        ));
+        index = end;
+    }
+    out.push_str("</ol>");
+    out
+}
+
+struct Heading {
+    level: u8,
+    id: String,
+    text: String,
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Open {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+    Close(String),
+}
+
+/// Splits sanitised HTML into tags and text. Attribute values are kept as
+/// serialised (still entity-escaped). Comments are dropped.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        if open > 0 {
+            tokens.push(Token::Text(&rest[..open]));
+        }
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        if let Some(closing) = rest.strip_prefix("</") {
+            let end = closing.find('>').unwrap_or(closing.len());
+            tokens.push(Token::Close(closing[..end].trim().to_ascii_lowercase()));
+            rest = closing.get(end + 1..).unwrap_or("");
+            continue;
+        }
+
+        let (token, consumed) = parse_open_tag(rest);
+        tokens.push(token);
+        rest = &rest[consumed..];
+    }
+
+    tokens
+}
+
+/// Parses `<name attr="value" ...>` at the start of `input`, respecting
+/// quotes so a `>` inside an attribute doesn't end the tag.
+fn parse_open_tag(input: &str) -> (Token<'_>, usize) {
+    let bytes = input.as_bytes();
+    let mut pos = 1;
+    let name_end = input[pos..]
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .map_or(input.len(), |offset| pos + offset);
+    let name = input[pos..name_end].to_ascii_lowercase();
+    pos = name_end;
+
+    let mut attrs = Vec::new();
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'>' => return (Token::Open { name, attrs }, pos + 1),
+            b'/' => pos += 1,
+            c if c.is_ascii_whitespace() => pos += 1,
+            _ => {
+                let attr_end = input[pos..]
+                    .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                    .map_or(input.len(), |offset| pos + offset);
+                let attr_name = input[pos..attr_end].to_ascii_lowercase();
+                pos = attr_end;
+
+                let mut value = String::new();
+                if bytes.get(pos) == Some(&b'=') {
+                    pos += 1;
+                    match bytes.get(pos) {
+                        Some(&quote) if quote == b'"' || quote == b'\'' => {
+                            let value_end = input[pos + 1..]
+                                .find(quote as char)
+                                .map_or(input.len(), |offset| pos + 1 + offset);
+                            value = input[pos + 1..value_end].to_string();
+                            pos = (value_end + 1).min(input.len());
+                        }
+                        _ => {
+                            let value_end = input[pos..]
+                                .find(|c: char| c.is_whitespace() || c == '>')
+                                .map_or(input.len(), |offset| pos + offset);
+                            value = input[pos..value_end].to_string();
+                            pos = value_end;
+                        }
+                    }
+                }
+                if !attr_name.is_empty() {
+                    attrs.push((attr_name, value));
+                }
+            }
+        }
+    }
+
+    (Token::Open { name, attrs }, input.len())
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+/// `&nbsp;` is the one named entity html5ever emits that XML doesn't know
+fn xml_text(text: &str) -> String {
+    text.replace("&nbsp;", "&#160;")
+}
+
+/// Absolute URLs of the images in `html`, along with archived media served
+/// from `/media/`, in document order.
+pub fn image_sources(html: &str) -> Vec<String> {
+    tokenize(html)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Open { name, attrs } if name == "img" => attr(&attrs, "src").map(unescape),
+            _ => None,
+        })
+        .filter(|src| {
+            src.starts_with("http://") || src.starts_with("https://") || src.starts_with("/media/")
+        })
+        .collect()
+}
+
+/// Rewrites the sanitised post HTML as well-formed XHTML for the book and
+/// collects the headings for the table of contents. Interactive parts (code
+/// block buttons, header anchors) are dropped, downloaded images point at
+/// their copy in the book and the rest become links.
+fn to_xhtml(html: &str, image_paths: &BTreeMap<&str, String>) -> (String, Vec<Heading>) {
+    let tokens = tokenize(html);
+    let ids: HashSet<&str> = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Open { attrs, .. } => attr(attrs, "id"),
+            _ => None,
+        })
+        .collect();
+
+    let mut out = String::with_capacity(html.len());
+    let mut headings = Vec::new();
+    let mut heading: Option<Heading> = None;
+    // Name and nesting depth of the element being dropped
+    let mut skipping: Option<(String, usize)> = None;
+    // For each open <div>, whether its tags are left out (content kept)
+    let mut divs: Vec<bool> = Vec::new();
+
+    for token in &tokens {
+        match token {
+            Token::Text(text) => {
+                if skipping.is_some() {
+                    continue;
+                }
+                let text = xml_text(text);
+                if let Some(heading) = heading.as_mut() {
+                    heading.text.push_str(&text);
+                }
+                out.push_str(&text);
+            }
+            Token::Close(name) => {
+                if let Some((skip_name, depth)) = skipping.as_mut() {
+                    if skip_name == name {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            skipping = None;
+                        }
+                    }
+                    continue;
+                }
+                if VOID_ELEMENTS.contains(&name.as_str()) {
+                    continue;
+                }
+                if name == "div" && divs.pop().unwrap_or(false) {
+                    continue;
+                }
+                if heading
+                    .as_ref()
+                    .is_some_and(|h| name == &format!("h{}", h.level))
+                {
+                    headings.extend(heading.take());
+                }
+                out.push_str(&format!("</{}>", name));
+            }
+            Token::Open { name, attrs } => {
+                if let Some((skip_name, depth)) = skipping.as_mut() {
+                    if skip_name == name && !VOID_ELEMENTS.contains(&name.as_str()) {
+                        *depth += 1;
+                    }
+                    continue;
+                }
+
+                // Code blocks only keep their <code>: <pre> can't hold the
+                // header and line number <div>s in XHTML
+                let class = attr(attrs, "class").unwrap_or_default();
+                if name == "button"
+                    || name == "svg"
+                    || class.contains("header-anchor")
+                    || class.contains("code-header")
+                    || class.contains("line-numbers")
+                {
+                    skipping = Some((name.clone(), 1));
+                    continue;
+                }
+                if name == "div" {
+                    let unwrap = class.contains("code-wrapper");
+                    divs.push(unwrap);
+                    if unwrap {
+                        continue;
+                    }
+                }
+
+                if name == "img" {
+                    out.push_str(&image_xhtml(attrs, image_paths));
+                    continue;
+                }
+
+                if let Some(level) = heading_level(name) {
+                    if let Some(id) = attr(attrs, "id") {
+                        heading = Some(Heading {
+                            level,
+                            id: id.to_string(),
+                            text: String::new(),
+                        });
+                    }
+                }
+
+                out.push('<');
+                out.push_str(name);
+                for (key, value) in attrs {
+                    let value = if name == "a" && key == "href" {
+                        match fix_fragment(value, &ids) {
+                            Some(value) => value,
+                            None => continue,
+                        }
+                    } else {
+                        value.clone()
+                    };
+                    out.push_str(&format!(" {}=\"{}\"", key, xml_attr(&value)));
+                }
+                if VOID_ELEMENTS.contains(&name.as_str()) {
+                    out.push_str("/>");
+                } else {
+                    out.push('>');
+                }
+            }
+        }
+    }
+
+    (out, headings)
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        _ => None,
+    }
+}
+
+/// Attribute values come out of html5ever with `&` and `"` escaped but `<`
+/// left alone, which XML doesn't allow.
+fn xml_attr(value: &str) -> String {
+    xml_text(value).replace('<', "&lt;")
+}
+
+/// Footnote references link to `#FN1` while the note's id is `fn1`. Fragments
+/// are matched case-insensitively here, and links to ids that don't exist
+/// lose their `href` since a broken fragment makes the book invalid.
+fn fix_fragment(href: &str, ids: &HashSet<&str>) -> Option<String> {
+    let Some(fragment) = href.strip_prefix('#') else {
+        return Some(href.to_string());
+    };
+    if ids.contains(fragment) {
+        return Some(href.to_string());
+    }
+    ids.iter()
+        .find(|id| id.eq_ignore_ascii_case(fragment))
+        .map(|id| format!("#{}", id))
+}
+
+fn image_xhtml(attrs: &[(String, String)], image_paths: &BTreeMap<&str, String>) -> String {
+    let src = attr(attrs, "src").map(unescape).unwrap_or_default();
+    let alt = attr(attrs, "alt").unwrap_or_default();
+
+    if let Some(path) = image_paths.get(src.as_str()) {
+        return format!("<img src=\"{}\" alt=\"{}\"/>", path, xml_attr(alt));
+    }
+
+    let label = if alt.is_empty() {
+        "[Image]".to_string()
+    } else {
+        format!("[Image: {}]", xml_attr(alt))
+    };
+    if src.starts_with("http://") || src.starts_with("https://") {
+        format!("<a href=\"{}\">{}</a>", html_escape(&src), label)
+    } else {
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PostKind;
+    use chrono::{TimeZone, Utc};
+    use nonograph_markup::render_markdown;
+    use std::io::Read;
+
+    fn post(raw_content: &str) -> Post {
+        Post {
+            id: "book-01-01-2025".to_string(),
+            title: "A Book & Its Title".to_string(),
+            author: String::new(),
+            content: render_markdown(raw_content),
+            raw_content: raw_content.to_string(),
+            created_at: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+            password_hash: None,
+            kind: PostKind::Markdown,
+        }
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_build_epub_layout() {
+        let source = "# One\n## Two\ntext[^1]\n\n## Three\n# Four\n\n[^1]: note";
+        let data = build_epub(&post(source), "nonograph v0.4.2", &BTreeMap::new()).unwrap();
+
+        // The uncompressed mimetype entry comes first, right after its header
+        assert_eq!(&data[30..38], b"mimetype");
+        assert_eq!(&data[38..58], b"application/epub+zip");
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        let package = read_entry(&mut archive, "OEBPS/content.opf");
+        assert!(package.contains("<dc:title>A Book &amp; Its Title</dc:title>"));
+        assert!(package.contains("<dc:creator>Anonymous</dc:creator>"));
+        assert!(package.contains("<dc:date>2025-01-01</dc:date>"));
+        assert!(package.contains("<meta name=\"generator\" content=\"nonograph v0.4.2\"/>"));
+
+        let nav = read_entry(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains(
+            "<ol><li><a href=\"content.xhtml#h1\">One</a><ol><li><a href=\"content.xhtml#h2\">Two</a></li><li><a href=\"content.xhtml#h3\">Three</a></li></ol></li><li><a href=\"content.xhtml#h4\">Four</a></li></ol>"
+        ));
+
+        let content = read_entry(&mut archive, "OEBPS/content.xhtml");
+        assert!(!content.contains("header-anchor"));
+        assert!(content.contains("href=\"#fn1\""));
+        assert!(!content.contains("#FN1"));
+        assert!(archive.by_name("OEBPS/style.css").is_ok());
+    }
+
+    #[test]
+    fn test_to_xhtml() {
+        let html = "<p>a&nbsp;b<br>c</p><img src=\"https://example.com/a.png\" alt=\"x > y\"><img src=\"/local.png\" alt=\"\"><img src=\"https://example.com/b.png?x=1&amp;y=2\" alt=\"b\"><input type=\"checkbox\" checked=\"\"><a href=\"#missing\">m</a><pre><div class=\"code-header\"><button class=\"copy-button\"><svg><rect></rect></svg>Copy</button></div><div class=\"line-numbers\"><span>1</span></div><div class=\"code-wrapper\"><code>x</code></div></pre><div>y</div>";
+        let mut paths = BTreeMap::new();
+        paths.insert(
+            "https://example.com/b.png?x=1&y=2",
+            "images/image-1.png".to_string(),
+        );
+
+        let (xhtml, headings) = to_xhtml(html, &paths);
+        assert_eq!(
+            xhtml,
+            "<p>a&#160;b<br/>c</p><a href=\"https://example.com/a.png\">[Image: x > y]</a>[Image]<img src=\"images/image-1.png\" alt=\"b\"/><input type=\"checkbox\" checked=\"\"/><a>m</a><pre><code>x</code></pre><div>y</div>"
+        );
+        assert!(headings.is_empty());
+    }
+
+    #[test]
+    fn test_image_sources() {
+        let html =
+            render_markdown("![a](https://example.com/a.png) ![b](/b.png) ![c](/media/c.png)");
+        assert_eq!(
+            image_sources(&html),
+            vec!["https://example.com/a.png", "/media/c.png"]
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::media;
+use crate::{
+    encrypted, nojs_html, parse_post, post_page_html, static_page_html, strip_frontmatter_field,
+    EncryptedPostData, Post, PostKind, STATIC_PAGES,
+};
+
+/// Written into the output directory so later runs only redo what changed
+const MANIFEST_FILE: &str = ".export-manifest.json";
+
+/// Files served from `templates/wasm/` for the live preview
+const WASM_FILES: &[&str] = &["nonograph_wasm.js", "nonograph_wasm_bg.wasm"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Hash of the templates, render options and version the outputs were
+    /// built with; when it changes every source is rendered again
+    build: String,
+    sources: BTreeMap<String, SourceEntry>,
+    /// Archived media copied into `media/`
+    #[serde(default)]
+    media: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SourceEntry {
+    mtime_ns: u64,
+    sha256: String,
+    /// Paths relative to the output directory
+    outputs: Vec<String>,
+    /// Names of the archived media the outputs use
+    #[serde(default)]
+    media: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub rendered: usize,
+    pub unchanged: usize,
+    /// Password-protected or unreadable sources, which are never exported
+    pub skipped: usize,
+    /// Sources deleted since the last run whose outputs were removed
+    pub removed: usize,
+}
+
+impl ExportSummary {
+    /// Counts a source the previous run's outputs still cover. One that had
+    /// no outputs was skipped then and still is.
+    fn count_reused(&mut self, entry: &SourceEntry) {
+        if entry.outputs.is_empty() {
+            self.skipped += 1;
+        } else {
+            self.unchanged += 1;
+        }
+    }
+}
+
+/// Renders every post and static page in `content_dir` into `outdir` as
+/// `<id>/index.html`, `nojs/<id>/index.html` and a raw `<id>.md`, laid out
+/// like the server's URLs, with the archived media they use under `media/`.
+/// Sources whose mtime or content hash match the previous run are left alone.
+pub fn export_static(
+    content_dir: &Path,
+    outdir: &Path,
+    config: &Config,
+) -> Result<ExportSummary, String> {
+    fs::create_dir_all(outdir)
+        .map_err(|e| format!("Failed to create {}: {}", outdir.display(), e))?;
+
+    let manifest_path = outdir.join(MANIFEST_FILE);
+    let mut previous: Manifest = fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let build = build_hash(config);
+    let same_build = previous.build == build;
+
+    let mut manifest = Manifest {
+        build,
+        ..Manifest::default()
+    };
+    let mut summary = ExportSummary::default();
+
+    let entries = fs::read_dir(content_dir)
+        .map_err(|e| format!("Failed to read {}: {}", content_dir.display(), e))?;
+    let mut sources: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    sources.sort();
+
+    for path in sources {
+        let (Some(id), Some(key)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.file_name().and_then(|name| name.to_str()),
+        ) else {
+            continue;
+        };
+        let old = previous.sources.remove(key);
+        let mtime_ns = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64);
+
+        if let Some(old) = old.as_ref().filter(|old| {
+            same_build && old.mtime_ns == mtime_ns && outputs_exist(outdir, &old.outputs)
+        }) {
+            summary.count_reused(old);
+            manifest.sources.insert(key.to_string(), old.clone());
+            continue;
+        }
+
+        let file_content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let sha256 = hex_digest(file_content.as_bytes());
+
+        if let Some(old) = old
+            .as_ref()
+            .filter(|old| same_build && old.sha256 == sha256 && outputs_exist(outdir, &old.outputs))
+        {
+            summary.count_reused(old);
+            manifest.sources.insert(
+                key.to_string(),
+                SourceEntry {
+                    mtime_ns,
+                    ..old.clone()
+                },
+            );
+            continue;
+        }
+
+        let files = render_source(id, &file_content, config)?;
+        if files.is_empty() {
+            summary.skipped += 1;
+        } else {
+            summary.rendered += 1;
+        }
+
+        for (relative, data) in &files {
+            write_if_changed(&outdir.join(relative), data)?;
+        }
+        let media = files
+            .iter()
+            .flat_map(|(_, data)| media::referenced_files(&String::from_utf8_lossy(data)))
+            .collect();
+        let outputs: Vec<String> = files.into_iter().map(|(relative, _)| relative).collect();
+        if let Some(old) = old {
+            let stale: Vec<String> = old
+                .outputs
+                .into_iter()
+                .filter(|output| !outputs.contains(output))
+                .collect();
+            remove_outputs(outdir, &stale);
+        }
+
+        manifest.sources.insert(
+            key.to_string(),
+            SourceEntry {
+                mtime_ns,
+                sha256,
+                outputs,
+                media,
+            },
+        );
+    }
+
+    // Whatever is left was deleted from content/ since the last run
+    for entry in previous.sources.into_values() {
+        remove_outputs(outdir, &entry.outputs);
+        summary.removed += 1;
+    }
+
+    let wanted = manifest
+        .sources
+        .values()
+        .flat_map(|entry| entry.media.iter().cloned())
+        .collect();
+    manifest.media = copy_media(outdir, config, wanted, &previous.media);
+    copy_assets(outdir, config)?;
+
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialise manifest: {}", e))?;
+    fs::write(&manifest_path, json)
+        .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+
+    Ok(summary)
+}
+
+/// Renders one source into its output files. Password-protected and
+/// unparseable sources produce nothing.
+fn render_source(
+    id: &str,
+    file_content: &str,
+    config: &Config,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let Some(post) = parse_post(id, file_content, config) else {
+        eprintln!("Skipping {}: invalid file format", id);
+        return Ok(Vec::new());
+    };
+    if post.password_hash.is_some() {
+        return Ok(Vec::new());
+    }
+
+    let html = if STATIC_PAGES.contains(&id) {
+        static_page_html(
+            id,
+            post.title.clone(),
+            post.author.clone(),
+            post.created_at,
+            &post.raw_content,
+            config,
+        )
+    } else {
+        post_page_html(&post)
+    }
+    .map_err(|e| format!("Failed to render {}: {}", id, e))?;
+
+    let mut files = vec![
+        (format!("{}/index.html", id), html.clone().into_bytes()),
+        (
+            format!("nojs/{}/index.html", id),
+            nojs_html(id, &html).into_bytes(),
+        ),
+        (
+            format!("{}.md", id),
+            strip_frontmatter_field(file_content, "password").into_bytes(),
+        ),
+    ];
+    if let Some(json) = encrypted_post_json(&post) {
+        files.push((format!("api/v1/encrypted/{}", id), json.into_bytes()));
+    }
+
+    Ok(files)
+}
+
+/// The document the encrypted post page fetches to decrypt in the browser
+fn encrypted_post_json(post: &Post) -> Option<String> {
+    if post.kind != PostKind::Encrypted {
+        return None;
+    }
+    let payload = encrypted::EncryptedPayload::from_storage(&post.raw_content)?;
+    serde_json::to_string(&EncryptedPostData {
+        id: post.id.clone(),
+        created_at: post
+            .created_at
+            .format("%Y-%m-%dT00:00:00+00:00")
+            .to_string(),
+        payload,
+    })
+    .ok()
+}
+
+/// Copies the live preview's WebAssembly build and settings, when present.
+fn copy_assets(outdir: &Path, config: &Config) -> Result<(), String> {
+    for file in WASM_FILES {
+        if let Ok(data) = fs::read(Path::new("templates/wasm").join(file)) {
+            write_if_changed(&outdir.join("wasm").join(file), &data)?;
+        }
+    }
+
+    let options = serde_json::to_string(&config.render_options())
+        .map_err(|e| format!("Failed to serialise render options: {}", e))?;
+    write_if_changed(&outdir.join("wasm/config.json"), options.as_bytes())
+}
+
+/// Copies the archived media in `wanted` into `media/` and removes what
+/// earlier runs copied but nothing uses any more. Returns what was copied.
+/// Files are named after their hash, so one already there is never stale.
+fn copy_media(
+    outdir: &Path,
+    config: &Config,
+    wanted: BTreeSet<String>,
+    previous: &BTreeSet<String>,
+) -> BTreeSet<String> {
+    let media_dir = Path::new(&config.archiver.media_dir);
+    let copied: BTreeSet<String> = wanted
+        .into_iter()
+        .filter(|name| {
+            let target = outdir.join("media").join(name);
+            if target.is_file() {
+                return true;
+            }
+            let result = fs::create_dir_all(outdir.join("media"))
+                .and_then(|_| fs::copy(media_dir.join(name), &target));
+            if let Err(e) = &result {
+                eprintln!("Skipping media {}: {}", name, e);
+            }
+            result.is_ok()
+        })
+        .collect();
+
+    let stale: Vec<String> = previous
+        .difference(&copied)
+        .map(|name| format!("media/{}", name))
+        .collect();
+    remove_outputs(outdir, &stale);
+    copied
+}
+
+fn build_hash(config: &Config) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(serde_json::to_string(&config.render_options()).unwrap_or_default());
+
+    let mut templates: Vec<_> = fs::read_dir("templates")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    templates.sort();
+    for path in templates {
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&path).unwrap_or_default());
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn outputs_exist(outdir: &Path, outputs: &[String]) -> bool {
+    outputs.iter().all(|output| outdir.join(output).is_file())
+}
+
+/// Writes `data` unless the file already holds exactly that, so unchanged
+/// outputs keep their mtime for rsync and friends.
+fn write_if_changed(path: &Path, data: &[u8]) -> Result<(), String> {
+    if fs::read(path).is_ok_and(|existing| existing == data) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Deletes outputs along with any directories they leave empty.
+fn remove_outputs(outdir: &Path, outputs: &[String]) {
+    for output in outputs {
+        let path = outdir.join(output);
+        let _ = fs::remove_file(&path);
+
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|parent| *parent != outdir) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_static_incremental() {
+        let content = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        let config = Config::default();
+        let post = content.path().join("hello-01-01-2025.md");
+
+        fs::write(
+            content.path().join("about.md"),
+            "---\ntitle: About\ndate: 2025-01-01\n---\n\nAbout page\n",
+        )
+        .unwrap();
+        fs::write(
+            &post,
+            "---\ntitle: Hello\ndate: 2025-01-01\n---\n\nFirst *draft*\n",
+        )
+        .unwrap();
+        fs::write(
+            content.path().join("locked-01-01-2025.md"),
+            "---\ntitle: Locked\ndate: 2025-01-01\npassword: $argon2id$v=19$abc\n---\n\nSecret\n",
+        )
+        .unwrap();
+
+        let summary = export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                rendered: 2,
+                skipped: 1,
+                ..Default::default()
+            }
+        );
+
+        let html = fs::read_to_string(out.path().join("hello-01-01-2025/index.html")).unwrap();
+        assert!(html.contains("<em>draft</em>"));
+        let nojs = fs::read_to_string(out.path().join("nojs/hello-01-01-2025/index.html")).unwrap();
+        assert!(!nojs.contains("<script"));
+        assert!(out.path().join("about/index.html").is_file());
+        assert!(out.path().join("hello-01-01-2025.md").is_file());
+        assert!(!out.path().join("locked-01-01-2025").exists());
+        assert!(out.path().join("wasm/config.json").is_file());
+
+        // Nothing changed, and the locked post is still left out
+        let summary = export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                unchanged: 2,
+                skipped: 1,
+                ..Default::default()
+            }
+        );
+
+        // A newer mtime with identical content is caught by the hash
+        File::options()
+            .write(true)
+            .open(&post)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let summary = export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(summary.unchanged, 2);
+        assert_eq!(summary.skipped, 1);
+
+        fs::write(
+            &post,
+            "---\ntitle: Hello\ndate: 2025-01-01\n---\n\nFinal *copy*\n",
+        )
+        .unwrap();
+        let summary = export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(summary.rendered, 1);
+        let html = fs::read_to_string(out.path().join("hello-01-01-2025/index.html")).unwrap();
+        assert!(html.contains("<em>copy</em>"));
+
+        fs::remove_file(&post).unwrap();
+        let summary = export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(summary.removed, 1);
+        assert!(!out.path().join("hello-01-01-2025").exists());
+        assert!(!out.path().join("nojs/hello-01-01-2025").exists());
+        assert!(!out.path().join("hello-01-01-2025.md").exists());
+    }
+
+    #[test]
+    fn test_export_static_copies_archived_media() {
+        let content = tempdir().unwrap();
+        let media_dir = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        let mut config = Config::default();
+        config.archiver.media_dir = media_dir.path().to_string_lossy().into_owned();
+
+        let png = format!("{}.png", hex_digest(b"png"));
+        let unused = format!("{}.png", hex_digest(b"unused"));
+        fs::write(media_dir.path().join(&png), b"png").unwrap();
+        fs::write(media_dir.path().join(&unused), b"unused").unwrap();
+        let post = content.path().join("pic-01-01-2025.md");
+        fs::write(
+            &post,
+            format!(
+                "---\ntitle: Pic\ndate: 2025-01-01\n---\n\n![p](/media/{})\n",
+                png
+            ),
+        )
+        .unwrap();
+
+        export_static(content.path(), out.path(), &config).unwrap();
+        assert_eq!(
+            fs::read(out.path().join("media").join(&png)).unwrap(),
+            b"png"
+        );
+        assert!(!out.path().join("media").join(&unused).exists());
+
+        // Still there when the post is unchanged, gone with the post
+        export_static(content.path(), out.path(), &config).unwrap();
+        assert!(out.path().join("media").join(&png).is_file());
+        fs::remove_file(&post).unwrap();
+        export_static(content.path(), out.path(), &config).unwrap();
+        assert!(!out.path().join("media").exists());
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::redirect::Policy;
+use rocket::tokio::time::timeout;
+use serde::de::DeserializeOwned;
+use url::{Origin, Url};
+
+use nonograph_markup::{is_ip_blocked, is_safe_url};
+
+use crate::config::Export;
+use crate::media;
+
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Proxy schemes where the proxy looks up host names itself
+const REMOTE_DNS_PROXY_SCHEMES: &[&str] = &["socks5h", "http", "https"];
+
+/// Cap on JSON and HTML documents, which are read whole into memory
+const MAX_DOCUMENT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Upper bound on image downloads per export, so one post can't keep the
+/// server busy
+const MAX_EXPORT_IMAGES: usize = 50;
+
+/// Image types exports may embed. SVG is left out because it can carry script.
+const EMBEDDABLE_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// What the archiver may copy to local storage: the embeddable images plus
+/// the video formats browsers play
+pub const ARCHIVABLE_MEDIA_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "video/mp4",
+    "video/webm",
+];
+
+/// Outbound HTTP client shared by everything that fetches remote content
+/// (the archiver, media downloads). Every hostname is resolved up front, each
+/// resolved address is checked against `is_ip_blocked`, and the connection is
+/// pinned to the address that passed the check so a second DNS lookup can't
+/// swap in an internal host. Redirects are followed by hand so every hop goes
+/// through the same checks.
+///
+/// Through a proxy, names are resolved by the proxy, so only the URL itself
+/// can be checked. Proxies that would have names resolved here instead
+/// (`socks5://`, `socks4://`) are refused, as those lookups skip the checks.
+/// A trusted origin set by the operator, such as a self-hosted Telegraph
+/// API, skips the checks altogether.
+pub struct SafeFetcher {
+    max_redirects: usize,
+    connect_timeout: Duration,
+    /// Limit on the whole request, headers and body
+    timeout: Option<Duration>,
+    /// Limit on waiting for the response headers and for each body chunk
+    read_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    trusted_origin: Option<Origin>,
+}
+
+impl SafeFetcher {
+    pub fn new() -> Self {
+        Self {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            connect_timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            timeout: Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
+            read_timeout: None,
+            user_agent: None,
+            proxy: None,
+            trusted_origin: None,
+        }
+    }
+
+    /// Replaces the overall request timeout with separate connect and read
+    /// timeouts, so large downloads over slow links can finish as long as
+    /// data keeps arriving.
+    pub fn with_timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        self.connect_timeout = connect;
+        self.read_timeout = Some(read);
+        self.timeout = None;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Sends every request through `proxy`, e.g. `socks5h://127.0.0.1:9050`.
+    pub fn with_proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Lets requests to `url`'s origin through without the address checks.
+    pub fn trusting(mut self, url: &Url) -> Self {
+        self.trusted_origin = Some(url.origin());
+        self
+    }
+
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response, String> {
+        let mut current = Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+
+        for _ in 0..=self.max_redirects {
+            let client = self.client_for(&current).await?;
+            let request = client.get(current.clone()).send();
+            let response = match self.read_timeout {
+                Some(limit) => timeout(limit, request)
+                    .await
+                    .map_err(|_| format!("Timed out waiting for {}", current))?,
+                None => request.await,
+            }
+            .map_err(|e| format!("Request to {} failed: {}", current, e))?;
+
+            if !response.status().is_redirection() {
+                return Ok(response);
+            }
+
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| format!("Redirect from {} has no Location header", current))?;
+            current = current
+                .join(location)
+                .map_err(|e| format!("Invalid redirect target {}: {}", location, e))?;
+        }
+
+        Err(format!(
+            "Too many redirects (more than {})",
+            self.max_redirects
+        ))
+    }
+
+    /// Fetches and parses a JSON document.
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, String> {
+        let response = self.get(url).await?;
+        let body = self.read_body(url, response, MAX_DOCUMENT_BYTES).await?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON from {}: {}", url, e))
+    }
+
+    /// Fetches a text document such as an HTML page.
+    pub async fn get_text(&self, url: &str) -> Result<String, String> {
+        let response = self.get(url).await?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+        let body = self.read_body(url, response, MAX_DOCUMENT_BYTES).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Downloads an image for embedding. Fails for anything that isn't one
+    /// of the embeddable image types or is larger than `max_bytes`.
+    pub async fn get_image(
+        &self,
+        url: &str,
+        max_bytes: usize,
+    ) -> Result<(String, Vec<u8>), String> {
+        self.get_media(url, EMBEDDABLE_IMAGE_TYPES, max_bytes).await
+    }
+
+    /// Downloads a file whose `Content-Type` is one of `allowed_types`,
+    /// giving up once it passes `max_bytes`.
+    pub async fn get_media(
+        &self,
+        url: &str,
+        allowed_types: &[&str],
+        max_bytes: usize,
+    ) -> Result<(String, Vec<u8>), String> {
+        let response = self.get(url).await?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+
+        let media_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !allowed_types.contains(&media_type.as_str()) {
+            return Err(format!("{} has an unsupported type ({})", url, media_type));
+        }
+
+        let data = self.read_body(url, response, max_bytes).await?;
+        Ok((media_type, data))
+    }
+
+    /// Reads a response body, failing once it passes `max_bytes`.
+    async fn read_body(
+        &self,
+        url: &str,
+        mut response: reqwest::Response,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, String> {
+        let too_large = || format!("{} is larger than {} bytes", url, max_bytes);
+        if response
+            .content_length()
+            .is_some_and(|length| length > max_bytes as u64)
+        {
+            return Err(too_large());
+        }
+
+        let mut data = Vec::new();
+        loop {
+            let next = response.chunk();
+            let chunk = match self.read_timeout {
+                Some(limit) => timeout(limit, next)
+                    .await
+                    .map_err(|_| format!("Timed out reading {}", url))?,
+                None => next.await,
+            }
+            .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+
+            let Some(chunk) = chunk else {
+                return Ok(data);
+            };
+            if data.len() + chunk.len() > max_bytes {
+                return Err(too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+    }
+
+    async fn client_for(&self, url: &Url) -> Result<reqwest::Client, String> {
+        let trusted = self.trusted_origin.as_ref() == Some(&url.origin());
+
+        let mut builder = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .connect_timeout(self.connect_timeout);
+        if let Some(limit) = self.timeout {
+            builder = builder.timeout(limit);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        match &self.proxy {
+            Some(proxy) => {
+                let scheme = proxy.split_once("://").map(|(scheme, _)| scheme);
+                if !scheme.is_some_and(|scheme| {
+                    REMOTE_DNS_PROXY_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str())
+                }) {
+                    return Err(format!(
+                        "Proxy {} would resolve names locally; use socks5h:// instead",
+                        proxy
+                    ));
+                }
+                // The proxy resolves the name, so only literal hosts can be checked
+                if !trusted && !is_safe_url(url.as_str()) {
+                    return Err(format!("URL {} is not allowed", url));
+                }
+                let proxy = reqwest::Proxy::all(proxy.as_str())
+                    .map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?;
+                builder = builder.proxy(proxy);
+            }
+            None => {
+                builder = builder.no_proxy();
+                if !trusted {
+                    let addr = resolve_checked(url).await?;
+                    if let Some(url::Host::Domain(domain)) = url.host() {
+                        builder = builder.resolve(domain, addr);
+                    }
+                }
+            }
+        }
+
+        builder
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))
+    }
+}
+
+/// An image downloaded for inclusion in an export
+pub struct EmbeddedImage {
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Collects images for an export, keyed by URL. Archived media is read from
+/// `media_dir`; remote images are downloaded when the instance allows it.
+/// Images that can't be had are left out and stay links.
+pub async fn fetch_images(
+    urls: Vec<String>,
+    export: &Export,
+    media_dir: &str,
+) -> BTreeMap<String, EmbeddedImage> {
+    let mut images = BTreeMap::new();
+    let max_bytes = export.max_image_size_kb * 1024;
+
+    let fetcher = SafeFetcher::new();
+    for url in urls.into_iter().take(MAX_EXPORT_IMAGES) {
+        if images.contains_key(&url) {
+            continue;
+        }
+        let image = match media::local_path(media_dir, &url) {
+            Some(path) => read_local_image(&path, max_bytes).await,
+            None if export.embed_remote_images => fetcher.get_image(&url, max_bytes).await,
+            None => continue,
+        };
+        match image {
+            Ok((media_type, data)) => {
+                images.insert(url, EmbeddedImage { media_type, data });
+            }
+            Err(e) => eprintln!("Export skipped image: {}", e),
+        }
+    }
+    images
+}
+
+/// Reads an archived image for an export, with the same limits as a download
+async fn read_local_image(path: &Path, max_bytes: usize) -> Result<(String, Vec<u8>), String> {
+    let media_type = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(media::content_type_for)
+        .map(|content_type| content_type.to_string())
+        .filter(|media_type| EMBEDDABLE_IMAGE_TYPES.contains(&media_type.as_str()))
+        .ok_or_else(|| format!("{} is not an embeddable image", path.display()))?;
+
+    let size = rocket::tokio::fs::metadata(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .len();
+    if size > max_bytes as u64 {
+        return Err(format!(
+            "{} is larger than {} bytes",
+            path.display(),
+            max_bytes
+        ));
+    }
+
+    let data = rocket::tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok((media_type, data))
+}
+
+/// Resolves the host of `url` and returns an address that is safe to connect
+/// to. Fails if the scheme isn't http(s) or if *any* resolved address is in a
+/// blocked range, since a mixed answer is a common DNS rebinding trick.
+pub async fn resolve_checked(url: &Url) -> Result<SocketAddr, String> {
+    match url.scheme() {
+        "http" | "https" => {}
+        scheme => return Err(format!("Scheme {} is not allowed", scheme)),
+    }
+
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| format!("No port for {}", url))?;
+
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(url::Host::Ipv4(v4)) => vec![SocketAddr::new(IpAddr::V4(v4), port)],
+        Some(url::Host::Ipv6(v6)) => vec![SocketAddr::new(IpAddr::V6(v6), port)],
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            if domain.eq_ignore_ascii_case("localhost") {
+                return Err(format!("Host {} is not allowed", domain));
+            }
+            rocket::tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|e| format!("Failed to resolve {}: {}", domain, e))?
+                .collect()
+        }
+        None => return Err(format!("No host in {}", url)),
+    };
+
+    if addrs.is_empty() {
+        return Err(format!("No addresses found for {}", url));
+    }
+
+    if let Some(blocked) = addrs.iter().find(|addr| is_ip_blocked(addr.ip())) {
+        return Err(format!(
+            "Host {} resolves to blocked address {}",
+            url.host_str().unwrap_or(""),
+            blocked.ip()
+        ));
+    }
+
+    Ok(addrs[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rocket::async_test]
+    async fn test_resolve_checked_blocks_internal_hosts() {
+        let blocked = [
+            "http://127.0.0.1/",
+            "http://10.0.0.5/",
+            "http://[::1]/",
+            "http://localhost:8009/",
+            "http://100.64.0.1/",
+            "http://224.0.0.1/",
+            "http://192.0.2.10/",
+            "ftp://93.184.216.34/",
+        ];
+
+        for url in blocked {
+            let parsed = Url::parse(url).unwrap();
+            assert!(
+                resolve_checked(&parsed).await.is_err(),
+                "{} should have been rejected",
+                url
+            );
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_resolve_checked_allows_public_literals() {
+        let parsed = Url::parse("https://93.184.216.34/page").unwrap();
+        let addr = resolve_checked(&parsed).await.unwrap();
+        assert_eq!(addr.port(), 443);
+        assert_eq!(addr.ip().to_string(), "93.184.216.34");
+    }
+
+    #[rocket::async_test]
+    async fn test_get_rejects_internal_targets_before_connecting() {
+        let fetcher = SafeFetcher::new();
+        let err = fetcher.get("http://127.0.0.1:1/").await.unwrap_err();
+        assert!(err.contains("blocked"), "unexpected error: {}", err);
+    }
+
+    #[rocket::async_test]
+    async fn test_proxies_that_resolve_locally_are_refused() {
+        // localtest.me resolves to 127.0.0.1, which socks5:// would look up here
+        let fetcher = SafeFetcher::new().with_proxy("socks5://127.0.0.1:1");
+        let err = fetcher.get("http://localtest.me/").await.unwrap_err();
+        assert!(err.contains("socks5h"), "unexpected error: {}", err);
+
+        let fetcher = SafeFetcher::new().with_proxy("socks5h://127.0.0.1:1");
+        let err = fetcher.get("http://localtest.me/").await.unwrap_err();
+        assert!(!err.contains("socks5h"), "unexpected error: {}", err);
+    }
+
+    #[rocket::async_test]
+    async fn test_fetch_images_reads_archived_media() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = "a".repeat(64) + ".png";
+        let mp4 = "b".repeat(64) + ".mp4";
+        std::fs::write(dir.path().join(&png), b"png bytes").unwrap();
+        std::fs::write(dir.path().join(&mp4), b"mp4 bytes").unwrap();
+
+        // Remote images stay off, archived ones are local so always embed
+        let urls = vec![
+            format!("/media/{}", png),
+            format!("/media/{}", mp4),
+            "/media/missing.png".to_string(),
+            "https://example.com/a.png".to_string(),
+        ];
+        let images = fetch_images(urls, &Export::default(), &dir.path().to_string_lossy()).await;
+        assert_eq!(images.len(), 1);
+        let image = &images[&format!("/media/{}", png)];
+        assert_eq!(image.media_type, "image/png");
+        assert_eq!(image.data, b"png bytes");
+
+        let small = Export {
+            max_image_size_kb: 0,
+            ..Export::default()
+        };
+        let images = fetch_images(
+            vec![format!("/media/{}", png)],
+            &small,
+            &dir.path().to_string_lossy(),
+        )
+        .await;
+        assert!(images.is_empty());
+    }
+}
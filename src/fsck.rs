@@ -0,0 +1,332 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use nonograph_markup::sanitize_text;
+use serde::Serialize;
+
+use crate::{
+    encrypted, frontmatter_field, parse_legacy_frontmatter, parse_yaml_frontmatter, save, Post,
+    PostKind, STATIC_PAGES,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    /// Old `Month DD, YYYY | author` header, as older archivers wrote
+    LegacyFrontmatter,
+    /// No header at all, so the first lines are eaten as one
+    MissingFrontmatter,
+    /// `---\r\n` isn't recognised as YAML frontmatter
+    CrlfLineEndings,
+    /// YAML frontmatter without a closing `---`; the post 404s
+    UnclosedFrontmatter,
+    /// Missing or unparseable `date:`, so the post shows today's date
+    InvalidDate,
+    CorruptEncryptedPayload,
+    /// The file name isn't `<slug>-MM-DD-YYYY[-n]` or a static page
+    NonstandardId,
+    NotMarkdown,
+    NotUtf8,
+}
+
+impl Problem {
+    pub fn fixable(self) -> bool {
+        matches!(
+            self,
+            Problem::LegacyFrontmatter | Problem::MissingFrontmatter | Problem::CrlfLineEndings
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Issue {
+    pub file: String,
+    pub problem: Problem,
+    pub fixable: bool,
+    pub fixed: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FsckReport {
+    pub scanned: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl FsckReport {
+    pub fn unresolved(&self) -> usize {
+        self.issues.iter().filter(|issue| !issue.fixed).count()
+    }
+}
+
+/// Scans every file in `<base_dir>/content` and classifies what's wrong
+/// with it. With `fix`, headers are rewritten as YAML frontmatter; nothing
+/// is ever renamed or deleted.
+pub fn fsck(base_dir: &Path, fix: bool) -> Result<FsckReport, String> {
+    let content_dir = base_dir.join("content");
+    let entries = fs::read_dir(&content_dir)
+        .map_err(|e| format!("Failed to read {}: {}", content_dir.display(), e))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut report = FsckReport::default();
+    for path in paths {
+        report.scanned += 1;
+        let file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let Some(id) = file.strip_suffix(".md") else {
+            report.issues.push(issue(&file, Problem::NotMarkdown));
+            continue;
+        };
+        if !is_standard_id(id) && !STATIC_PAGES.contains(&id) {
+            report.issues.push(issue(&file, Problem::NonstandardId));
+        }
+
+        let Ok(file_content) = fs::read_to_string(&path) else {
+            report.issues.push(issue(&file, Problem::NotUtf8));
+            continue;
+        };
+
+        let Some(problem) = check_content(&file_content) else {
+            continue;
+        };
+        let mut found = issue(&file, problem);
+        if fix && problem.fixable() {
+            let modified = fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now());
+            repair(base_dir, id, &file_content, problem, modified)?;
+            found.fixed = true;
+        }
+        report.issues.push(found);
+    }
+
+    Ok(report)
+}
+
+fn issue(file: &str, problem: Problem) -> Issue {
+    Issue {
+        file: file.to_string(),
+        problem,
+        fixable: problem.fixable(),
+        fixed: false,
+    }
+}
+
+/// The first problem with a file's contents, if any.
+fn check_content(file_content: &str) -> Option<Problem> {
+    if file_content.starts_with("---\r\n") {
+        return Some(Problem::CrlfLineEndings);
+    }
+    if !file_content.starts_with("---\n") {
+        return Some(if is_legacy_header(file_content) {
+            Problem::LegacyFrontmatter
+        } else {
+            Problem::MissingFrontmatter
+        });
+    }
+
+    let Some((_, _, _, raw_content)) = parse_yaml_frontmatter(file_content) else {
+        return Some(Problem::UnclosedFrontmatter);
+    };
+
+    let date = frontmatter_field(file_content, "date").unwrap_or_default();
+    if NaiveDate::parse_from_str(&date, "%Y-%m-%d").is_err() {
+        return Some(Problem::InvalidDate);
+    }
+
+    if frontmatter_field(file_content, "kind").as_deref() == Some("encrypted")
+        && encrypted::EncryptedPayload::from_storage(&raw_content).is_none()
+    {
+        return Some(Problem::CorruptEncryptedPayload);
+    }
+
+    None
+}
+
+/// A well-formed legacy header: a `%B %d, %Y` line with an optional
+/// `| author`, a blank line and a `# Title` line.
+fn is_legacy_header(file_content: &str) -> bool {
+    let mut lines = file_content.lines();
+    let date = lines.next().unwrap_or_default().split(" | ").next();
+
+    date.is_some_and(|date| NaiveDate::parse_from_str(date.trim(), "%B %d, %Y").is_ok())
+        && lines.next().is_some_and(|line| line.trim().is_empty())
+        && lines.next().is_some_and(|line| line.starts_with("# "))
+}
+
+/// Rewrites a file with YAML frontmatter through the normal save path.
+fn repair(
+    base_dir: &Path,
+    id: &str,
+    file_content: &str,
+    problem: Problem,
+    modified: DateTime<Utc>,
+) -> Result<(), String> {
+    let (title, author, created_at, raw_content) = match problem {
+        Problem::LegacyFrontmatter => parse_legacy_frontmatter(file_content)
+            .ok_or_else(|| format!("Failed to parse {}.md", id))?,
+        Problem::MissingFrontmatter => {
+            let title = file_content
+                .lines()
+                .find_map(|line| line.strip_prefix("# "))
+                .map(sanitize_text)
+                .unwrap_or_else(|| "Untitled".to_string());
+            (title, String::new(), modified, file_content.to_string())
+        }
+        Problem::CrlfLineEndings => {
+            let normalized = file_content.replace("\r\n", "\n");
+            return fs::write(
+                base_dir.join("content").join(format!("{}.md", id)),
+                normalized,
+            )
+            .map_err(|e| format!("Failed to write {}.md: {}", id, e));
+        }
+        _ => return Ok(()),
+    };
+
+    let post = Post {
+        id: id.to_string(),
+        title,
+        author,
+        content: String::new(),
+        raw_content,
+        created_at,
+        password_hash: None,
+        kind: PostKind::Markdown,
+    };
+    save::save_post_to_file_in_dir(&post, &base_dir.to_string_lossy())
+}
+
+/// Whether `id` is shaped like the ids `generate_post_id` hands out:
+/// `<slug>-MM-DD-YYYY` with an optional `-n` for repeated titles.
+fn is_standard_id(id: &str) -> bool {
+    let parts: Vec<&str> = id.split('-').collect();
+    let is_digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let is_slug = |parts: &[&str]| {
+        !parts.is_empty()
+            && parts.iter().all(|part| {
+                !part.is_empty()
+                    && part
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            })
+    };
+    let is_dated = |parts: &[&str]| {
+        parts.len() >= 4 && {
+            let (slug, date) = parts.split_at(parts.len() - 3);
+            is_slug(slug) && is_digits(date[0], 2) && is_digits(date[1], 2) && is_digits(date[2], 4)
+        }
+    };
+
+    if is_dated(&parts) {
+        return true;
+    }
+    match parts.split_last() {
+        Some((counter, rest)) => {
+            (1..=3).any(|len| is_digits(counter, len))
+                && !counter.starts_with('0')
+                && is_dated(rest)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_is_standard_id() {
+        assert!(is_standard_id("hello-world-10-18-2026"));
+        assert!(is_standard_id("na-x7k2-10-18-2026-12"));
+        assert!(is_standard_id("2024-recap-01-01-2025"));
+        assert!(!is_standard_id("Hello-World-10-18"));
+        assert!(!is_standard_id("hello-10-18-2026-0"));
+        assert!(!is_standard_id("10-18-2026"));
+        assert!(!is_standard_id("hello--10-18-2026"));
+    }
+
+    #[test]
+    fn test_check_content() {
+        assert_eq!(
+            check_content("---\ntitle: Fine\ndate: 2025-01-01\n---\n\nBody\n"),
+            None
+        );
+        assert_eq!(
+            check_content("January 05, 2025 | Ann\n\n# Old\nBody\n"),
+            Some(Problem::LegacyFrontmatter)
+        );
+        assert_eq!(
+            check_content("Just some text\nwith no header\n"),
+            Some(Problem::MissingFrontmatter)
+        );
+        assert_eq!(
+            check_content("---\r\ntitle: Windows\r\n---\r\n\r\nBody\r\n"),
+            Some(Problem::CrlfLineEndings)
+        );
+        assert_eq!(
+            check_content("---\ntitle: Open\ndate: 2025-01-01\n\nBody\n"),
+            Some(Problem::UnclosedFrontmatter)
+        );
+        assert_eq!(
+            check_content("---\ntitle: Undated\n---\n\nBody\n"),
+            Some(Problem::InvalidDate)
+        );
+        assert_eq!(
+            check_content("---\ntitle: E\ndate: 2025-01-01\nkind: encrypted\n---\n\nnot json\n"),
+            Some(Problem::CorruptEncryptedPayload)
+        );
+    }
+
+    #[test]
+    fn test_fsck_fix() {
+        let dir = tempdir().unwrap();
+        let content = dir.path().join("content");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(
+            content.join("old-post-01-05-2025.md"),
+            "January 05, 2025 | Ann\n\n# Old Post\nBody text\n",
+        )
+        .unwrap();
+        fs::write(content.join("Telegraph-Page-01-05.md"), "# Stray\nBody\n").unwrap();
+        fs::write(content.join("notes.txt"), "scratch").unwrap();
+
+        let report = fsck(dir.path(), true).unwrap();
+        assert_eq!(report.scanned, 3);
+        let problems: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.file.as_str(), issue.problem, issue.fixed))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("Telegraph-Page-01-05.md", Problem::NonstandardId, false),
+                ("Telegraph-Page-01-05.md", Problem::MissingFrontmatter, true),
+                ("notes.txt", Problem::NotMarkdown, false),
+                ("old-post-01-05-2025.md", Problem::LegacyFrontmatter, true),
+            ]
+        );
+
+        let migrated = fs::read_to_string(content.join("old-post-01-05-2025.md")).unwrap();
+        assert!(migrated.starts_with("---\ntitle: Old Post\ndate: 2025-01-05\nauthor: Ann\n"));
+        assert!(migrated.ends_with("---\n\nBody text\n"));
+        let stray = fs::read_to_string(content.join("Telegraph-Page-01-05.md")).unwrap();
+        assert!(stray.starts_with("---\ntitle: Stray\n"));
+        assert!(stray.ends_with("---\n\n# Stray\nBody\n"));
+
+        // Only the problems that can't be fixed are left
+        let report = fsck(dir.path(), false).unwrap();
+        assert_eq!(report.unresolved(), 2);
+    }
+}
@@ -0,0 +1,364 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+
+use nonograph_markup::render_gemtext;
+use percent_encoding::percent_decode_str;
+use rocket::tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::{TcpListener, TcpStream};
+use rocket::tokio::time::timeout;
+use tokio_rustls::rustls::{self, Certificate, PrivateKey};
+use tokio_rustls::TlsAcceptor;
+use url::Url;
+
+use crate::config::Config;
+use crate::{
+    is_unlocked, load_post, load_static_page, PostKind, PostStorage, ENCRYPTED_NOTICE_TEXT,
+};
+
+/// Requests are a URL of at most 1024 bytes followed by CRLF
+const MAX_REQUEST_LEN: usize = 1024 + 2;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+const GEMTEXT: &str = "text/gemini; charset=utf-8";
+
+/// Serves posts over Gemini until the process exits. Posts come from the
+/// same cache and `content/` files as the web routes.
+pub async fn serve(config: Config, storage: PostStorage) -> Result<(), String> {
+    let tls = load_tls_config(&config.gemini.cert_path, &config.gemini.key_path)?;
+    let acceptor = TlsAcceptor::from(Arc::new(tls));
+    let listener = TcpListener::bind((config.gemini.address.as_str(), config.gemini.port))
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to bind Gemini listener on {}:{}: {}",
+                config.gemini.address, config.gemini.port, e
+            )
+        })?;
+    let config = Arc::new(config);
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Gemini accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let config = Arc::clone(&config);
+        let storage = Arc::clone(&storage);
+        rocket::tokio::spawn(async move {
+            if let Err(e) = handle_connection(acceptor, stream, &config, &storage).await {
+                eprintln!("Gemini request failed: {}", e);
+            }
+        });
+    }
+}
+
+pub fn load_tls_config(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig, String> {
+    let certs = read_pem(cert_path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path));
+    }
+
+    let key = read_pem(key_path)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", key_path))?;
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid Gemini certificate or key: {}", e))
+}
+
+fn read_pem(path: &str) -> Result<Vec<rustls_pemfile::Item>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| format!("Failed to parse {}: {}", path, e))
+}
+
+async fn handle_connection(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    config: &Config,
+    storage: &PostStorage,
+) -> Result<(), String> {
+    let deadline = Duration::from_secs(REQUEST_TIMEOUT_SECS);
+
+    let mut tls = timeout(deadline, acceptor.accept(stream))
+        .await
+        .map_err(|_| "TLS handshake timed out".to_string())?
+        .map_err(|e| format!("TLS handshake failed: {}", e))?;
+
+    let response = match timeout(deadline, read_request(&mut tls, MAX_REQUEST_LEN)).await {
+        Ok(Ok(request)) => respond(&request, config, storage).await,
+        Ok(Err(e)) => header(59, &e),
+        Err(_) => header(59, "Request timed out"),
+    };
+
+    tls.write_all(response.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write response: {}", e))?;
+    let _ = tls.shutdown().await;
+    Ok(())
+}
+
+/// Reads a CRLF-terminated request line of at most `max_len` bytes, including
+/// the CRLF. Gopher requests use the same framing.
+pub async fn read_request<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<String, String> {
+    let mut request = Vec::with_capacity(max_len);
+    let mut byte = [0u8; 1];
+
+    while !request.ends_with(b"\r\n") {
+        if request.len() >= max_len {
+            return Err("Request too long".to_string());
+        }
+        match reader.read(&mut byte).await {
+            Ok(0) => return Err("Connection closed before end of request".to_string()),
+            Ok(_) => request.push(byte[0]),
+            Err(e) => return Err(format!("Failed to read request: {}", e)),
+        }
+    }
+
+    request.truncate(request.len() - 2);
+    String::from_utf8(request).map_err(|_| "Request is not valid UTF-8".to_string())
+}
+
+fn header(status: u8, meta: &str) -> String {
+    format!("{} {}\r\n", status, meta)
+}
+
+fn success(body: &str) -> String {
+    format!("{}{}", header(20, GEMTEXT), body)
+}
+
+/// Builds the full response (header and body) for one request line.
+async fn respond(request: &str, config: &Config, storage: &PostStorage) -> String {
+    let url = match Url::parse(request) {
+        Ok(url) => url,
+        Err(_) => return header(59, "Invalid URL"),
+    };
+
+    if url.scheme() != "gemini" {
+        return header(53, "Proxy request refused");
+    }
+    let host_matches = url
+        .host_str()
+        .is_some_and(|host| host.eq_ignore_ascii_case(&config.gemini.hostname));
+    let port_matches = url.port().is_none_or(|port| port == config.gemini.port);
+    if !host_matches || !port_matches {
+        return header(53, "Proxy request refused");
+    }
+
+    let post_id = url.path().trim_start_matches('/');
+    if post_id.is_empty() {
+        return success(&about_page());
+    }
+    if post_id.contains('/') {
+        return header(51, "Not found");
+    }
+
+    let post = match load_post(post_id, storage, config) {
+        Some(post) => post,
+        None => return header(51, "Not found"),
+    };
+
+    if post.password_hash.is_some() {
+        let password = url
+            .query()
+            .map(|q| percent_decode_str(q).decode_utf8_lossy().into_owned());
+        match is_unlocked(post.password_hash.as_deref(), password.as_deref()).await {
+            Ok(true) => {}
+            Ok(false) if password.is_some() => return header(11, "Wrong password, try again"),
+            Ok(false) => return header(11, "Password"),
+            Err(_) => return header(44, "5"),
+        }
+    }
+
+    let author = if post.author.is_empty() {
+        "Anonymous"
+    } else {
+        post.author.as_str()
+    };
+    let body = match post.kind {
+        PostKind::Markdown => render_gemtext(&post.raw_content),
+        PostKind::Encrypted => ENCRYPTED_NOTICE_TEXT.to_string(),
+    };
+
+    success(&format!(
+        "# {}\n{} · {}\n\n{}\n=> / Home\n",
+        post.title,
+        author,
+        post.created_at.format("%B %d, %Y"),
+        body
+    ))
+}
+
+/// The Gemini home page is the about page, rendered as gemtext.
+fn about_page() -> String {
+    match load_static_page("about") {
+        Some((title, _, _, raw_content)) => {
+            format!("# {}\n\n{}", title, render_gemtext(&raw_content))
+        }
+        None => "# nonograph\n\nAnonymous publishing for the privacy-conscious web.\n".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{password, Post, PostCache};
+    use chrono::Utc;
+    use std::sync::Mutex;
+
+    fn storage_with(posts: Vec<Post>) -> PostStorage {
+        let mut cache = PostCache::new(1);
+        for post in posts {
+            cache.insert(post.id.clone(), post);
+        }
+        Arc::new(Mutex::new(cache))
+    }
+
+    fn post(id: &str, password_hash: Option<String>) -> Post {
+        Post {
+            id: id.to_string(),
+            title: "Gemini Test".to_string(),
+            author: String::new(),
+            content: String::new(),
+            raw_content: "Hello **world**, see [this](https://example.com).".to_string(),
+            created_at: Utc::now(),
+            password_hash,
+            kind: PostKind::Markdown,
+        }
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.gemini.hostname = "example.org".to_string();
+        config
+    }
+
+    #[rocket::async_test]
+    async fn test_respond_serves_posts_as_gemtext() {
+        let storage = storage_with(vec![post("gemini-test-01-01-2025", None)]);
+        let response = respond(
+            "gemini://example.org/gemini-test-01-01-2025",
+            &config(),
+            &storage,
+        )
+        .await;
+
+        assert!(
+            response.starts_with("20 text/gemini; charset=utf-8\r\n# Gemini Test\nAnonymous · ")
+        );
+        assert!(response.contains("\nHello world, see this.\n=> https://example.com this\n"));
+        assert!(response.ends_with("=> / Home\n"));
+    }
+
+    #[rocket::async_test]
+    async fn test_respond_rejects_bad_requests() {
+        let storage = storage_with(vec![]);
+        let config = config();
+
+        assert_eq!(
+            respond("not a url", &config, &storage).await,
+            "59 Invalid URL\r\n"
+        );
+        assert_eq!(
+            respond("https://example.org/", &config, &storage).await,
+            "53 Proxy request refused\r\n"
+        );
+        assert_eq!(
+            respond("gemini://other.example/", &config, &storage).await,
+            "53 Proxy request refused\r\n"
+        );
+        assert_eq!(
+            respond("gemini://example.org/missing-01-01-2025", &config, &storage).await,
+            "51 Not found\r\n"
+        );
+        assert!(respond("gemini://EXAMPLE.org:1965/", &config, &storage)
+            .await
+            .starts_with("20 "));
+    }
+
+    #[rocket::async_test]
+    #[serial_test::serial(argon2)]
+    async fn test_respond_prompts_for_passwords() {
+        let hash = password::hash_password("open sesame").unwrap();
+        let storage = storage_with(vec![post("locked-01-01-2025", Some(hash))]);
+        let config = config();
+
+        assert_eq!(
+            respond("gemini://example.org/locked-01-01-2025", &config, &storage).await,
+            "11 Password\r\n"
+        );
+        assert_eq!(
+            respond(
+                "gemini://example.org/locked-01-01-2025?nope",
+                &config,
+                &storage
+            )
+            .await,
+            "11 Wrong password, try again\r\n"
+        );
+        assert!(respond(
+            "gemini://example.org/locked-01-01-2025?open%20sesame",
+            &config,
+            &storage
+        )
+        .await
+        .starts_with("20 "));
+    }
+
+    #[rocket::async_test]
+    async fn test_read_request() {
+        let mut ok: &[u8] = b"gemini://example.org/\r\nignored";
+        assert_eq!(
+            read_request(&mut ok, MAX_REQUEST_LEN).await.unwrap(),
+            "gemini://example.org/"
+        );
+
+        let long = format!("gemini://example.org/{}\r\n", "a".repeat(1100));
+        assert!(read_request(&mut long.as_bytes(), MAX_REQUEST_LEN)
+            .await
+            .is_err());
+
+        let mut unterminated: &[u8] = b"gemini://example.org/";
+        assert!(read_request(&mut unterminated, MAX_REQUEST_LEN)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_load_tls_config_errors() {
+        assert!(load_tls_config("/nonexistent/cert.pem", "/nonexistent/key.pem").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+        let path = empty.to_str().unwrap();
+        assert_eq!(
+            load_tls_config(path, path).unwrap_err(),
+            format!("No certificates found in {}", path)
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use nonograph_markup::render_plain_text;
+use rocket::tokio::io::AsyncWriteExt;
+use rocket::tokio::net::{TcpListener, TcpStream};
+use rocket::tokio::time::timeout;
+
+use crate::config::Config;
+use crate::gemini::read_request;
+use crate::{
+    load_post, load_static_page, PostKind, PostStorage, ENCRYPTED_NOTICE_TEXT, STATIC_PAGES,
+};
+
+/// Selectors are short paths; anything longer than this is not one of ours
+const MAX_SELECTOR_LEN: usize = 1024 + 2;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+const PASSWORD_NOTICE: &str =
+    "This post is password protected. It can only be unlocked on the web.\n";
+
+/// Serves the static pages and posts over Gopher until the process exits.
+/// Posts come from the same cache and `content/` files as the web routes.
+pub async fn serve(config: Config, storage: PostStorage) -> Result<(), String> {
+    let listener = TcpListener::bind((config.gopher.address.as_str(), config.gopher.port))
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to bind Gopher listener on {}:{}: {}",
+                config.gopher.address, config.gopher.port, e
+            )
+        })?;
+    let config = Arc::new(config);
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Gopher accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let config = Arc::clone(&config);
+        let storage = Arc::clone(&storage);
+        rocket::tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &config, &storage).await {
+                eprintln!("Gopher request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    config: &Config,
+    storage: &PostStorage,
+) -> Result<(), String> {
+    let deadline = Duration::from_secs(REQUEST_TIMEOUT_SECS);
+
+    let response = match timeout(deadline, read_request(&mut stream, MAX_SELECTOR_LEN)).await {
+        Ok(Ok(selector)) => respond(&selector, config, storage),
+        Ok(Err(e)) => error(&e),
+        Err(_) => error("Request timed out"),
+    };
+
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write response: {}", e))?;
+    let _ = stream.shutdown().await;
+    Ok(())
+}
+
+/// Builds the full response for one selector line.
+fn respond(request: &str, config: &Config, storage: &PostStorage) -> String {
+    // Search clients send "selector<TAB>query"; there is nothing to search
+    let selector = request.split('\t').next().unwrap_or_default();
+    let path = selector.trim_start_matches('/');
+
+    if path.is_empty() {
+        return menu(config);
+    }
+    if path.contains('/') {
+        return error("Not found");
+    }
+
+    if STATIC_PAGES.contains(&path) {
+        return match load_static_page(path) {
+            Some((title, _, _, raw_content)) => {
+                text(&page(&title, None, &raw_content, config.gopher.line_width))
+            }
+            None => error("Not found"),
+        };
+    }
+
+    let post = match load_post(path, storage, config) {
+        Some(post) => post,
+        None => return error("Not found"),
+    };
+
+    let author = if post.author.is_empty() {
+        "Anonymous"
+    } else {
+        post.author.as_str()
+    };
+    let byline = format!("{} · {}", author, post.created_at.format("%B %d, %Y"));
+
+    let body = if post.password_hash.is_some() {
+        page(&post.title, Some(&byline), "", config.gopher.line_width) + PASSWORD_NOTICE
+    } else if post.kind == PostKind::Encrypted {
+        page(&post.title, Some(&byline), "", config.gopher.line_width) + ENCRYPTED_NOTICE_TEXT
+    } else {
+        page(
+            &post.title,
+            Some(&byline),
+            &post.raw_content,
+            config.gopher.line_width,
+        )
+    };
+    text(&body)
+}
+
+/// Title, optional byline and the content as wrapped plain text.
+fn page(title: &str, byline: Option<&str>, raw_content: &str, width: usize) -> String {
+    let mut out = format!("{}\n{}\n", title, "=".repeat(title.chars().count()));
+    if let Some(byline) = byline {
+        out.push_str(byline);
+        out.push('\n');
+    }
+    out.push('\n');
+    if !raw_content.is_empty() {
+        out.push_str(&render_plain_text(raw_content, width));
+    }
+    out
+}
+
+/// The landing gophermap: a short introduction followed by the static pages.
+fn menu(config: &Config) -> String {
+    let host = &config.gopher.hostname;
+    let port = config.gopher.port;
+    let mut out = String::new();
+
+    out.push_str(&info("nonograph"));
+    out.push_str(&info("Anonymous publishing for the privacy-conscious web."));
+    out.push_str(&info(""));
+
+    for name in STATIC_PAGES {
+        if let Some((title, _, _, _)) = load_static_page(name) {
+            out.push_str(&format!("0{}\t/{}\t{}\t{}\r\n", title, name, host, port));
+        }
+    }
+
+    out.push_str(&info(""));
+    out.push_str(&info(&format!(
+        "Posts are at gopher://{}:{}/0/<post-id>",
+        host, port
+    )));
+    out.push_str(".\r\n");
+    out
+}
+
+fn info(line: &str) -> String {
+    format!("i{}\t\terror.host\t1\r\n", line)
+}
+
+fn error(message: &str) -> String {
+    format!("3{}\t\terror.host\t1\r\n.\r\n", message)
+}
+
+/// Wraps a text document for the wire: CRLF line endings, lines starting
+/// with "." doubled, and the closing "." line.
+fn text(body: &str) -> String {
+    let mut out = String::with_capacity(body.len() + 16);
+    for line in body.lines() {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out.push_str(".\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{password, Post, PostCache};
+    use chrono::Utc;
+    use std::sync::Mutex;
+
+    fn storage_with(posts: Vec<Post>) -> PostStorage {
+        let mut cache = PostCache::new(1);
+        for post in posts {
+            cache.insert(post.id.clone(), post);
+        }
+        Arc::new(Mutex::new(cache))
+    }
+
+    fn post(id: &str, password_hash: Option<String>) -> Post {
+        Post {
+            id: id.to_string(),
+            title: "Gopher Test".to_string(),
+            author: String::new(),
+            content: String::new(),
+            raw_content: "Hello **world**, see [this](https://example.com).\n\n.dot".to_string(),
+            created_at: Utc::now(),
+            password_hash,
+            kind: PostKind::Markdown,
+        }
+    }
+
+    #[test]
+    fn test_respond_serves_posts_as_text() {
+        let storage = storage_with(vec![post("gopher-test-01-01-2025", None)]);
+        let response = respond("/gopher-test-01-01-2025", &Config::default(), &storage);
+
+        assert!(response.starts_with("Gopher Test\r\n===========\r\nAnonymous · "));
+        assert!(response.contains("\r\nHello world, see this [1].\r\n\r\n..dot\r\n"));
+        assert!(response.ends_with("[1] https://example.com\r\n.\r\n"));
+
+        // Selectors without a leading slash and with a search query still work
+        assert_eq!(
+            respond(
+                "gopher-test-01-01-2025\tquery",
+                &Config::default(),
+                &storage
+            ),
+            response
+        );
+    }
+
+    #[test]
+    fn test_respond_withholds_locked_posts() {
+        let hash = password::hash_password("open sesame").unwrap();
+        let mut encrypted = post("sealed-01-01-2025", None);
+        encrypted.kind = PostKind::Encrypted;
+        let storage = storage_with(vec![post("locked-01-01-2025", Some(hash)), encrypted]);
+
+        let locked = respond("/locked-01-01-2025", &Config::default(), &storage);
+        assert!(locked.contains("password protected"));
+        assert!(!locked.contains("Hello"));
+
+        let sealed = respond("/sealed-01-01-2025", &Config::default(), &storage);
+        assert!(sealed.contains("end-to-end encrypted"));
+        assert!(!sealed.contains("Hello"));
+    }
+
+    #[test]
+    fn test_respond_menu_and_errors() {
+        let storage = storage_with(vec![]);
+        let mut config = Config::default();
+        config.gopher.hostname = "example.org".to_string();
+
+        let menu = respond("", &config, &storage);
+        assert!(menu.starts_with("inonograph\t\terror.host\t1\r\n"));
+        assert!(menu.contains("\t/about\texample.org\t70\r\n"));
+        assert!(menu.ends_with(".\r\n"));
+        assert_eq!(respond("/", &config, &storage), menu);
+
+        assert_eq!(
+            respond("/missing-01-01-2025", &config, &storage),
+            "3Not found\t\terror.host\t1\r\n.\r\n"
+        );
+        assert_eq!(
+            respond("/a/b", &config, &storage),
+            "3Not found\t\terror.host\t1\r\n.\r\n"
+        );
+    }
+}
@@ -0,0 +1,768 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::{parse_document, Attribute, ExpandedName, QualName};
+
+use crate::nodes::{self, Node, NodeElement};
+
+/// Never part of an article: scripts, page furniture and form controls
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "canvas", "form", "button", "input",
+    "select", "textarea", "nav", "header", "footer", "aside", "head",
+];
+
+/// Tags whose whitespace is significant next to their siblings
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "cite", "code", "em", "i", "img", "kbd", "mark", "q", "s", "small", "span",
+    "strong", "sub", "sup", "time", "u",
+];
+
+/// Class and id fragments of comments, sidebars, share buttons and the like
+const UNLIKELY_NAMES: &[&str] = &[
+    "advert",
+    "banner",
+    "comment",
+    "cookie",
+    "footer",
+    "footnote",
+    "menu",
+    "meta",
+    "nav",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "subscribe",
+];
+
+/// Class and id fragments of the main text
+const LIKELY_NAMES: &[&str] = &[
+    "article", "blog", "body", "content", "entry", "main", "post", "story", "text",
+];
+
+/// What survives cleaning pasted HTML: the tags the node converter has
+/// markup for and the containers rich text arrives in
+const PASTE_TAGS: &[&str] = &[
+    "a",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "div",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "span",
+    "strong",
+    "u",
+    "ul",
+];
+
+/// Paragraphs shorter than this don't count towards their container's score
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+/// The main text of an HTML page and what the page says about it.
+#[derive(Debug, Default)]
+pub struct Article {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    /// From `<link rel="canonical">` or `og:url`
+    pub url: Option<String>,
+    pub content: Vec<Node>,
+}
+
+/// Finds the main content of a page the way Readability does, by scoring
+/// the containers of its paragraphs, and converts it to nodes.
+pub fn extract_article(html: &str) -> Article {
+    let mut dom = Dom::parse(html);
+    dom.drop_unlikely = true;
+
+    let title = dom
+        .meta("og:title")
+        .or_else(|| dom.find(0, "title").map(|title| dom.text(title)))
+        .or_else(|| dom.find(0, "h1").map(|h1| dom.text(h1)))
+        .map(|title| collapse_whitespace(&title).trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let mut content = dom.to_nodes(dom.main_content(), false);
+
+    // Pages usually repeat their title as the first heading
+    let first = content
+        .iter()
+        .position(|node| !matches!(node, Node::Text(text) if text.trim().is_empty()));
+    if let (Some(index), Some(title)) = (first, &title) {
+        if let Node::Element(element) = &content[index] {
+            if matches!(element.tag.as_str(), "h1" | "h2") && node_text(&content[index]) == *title {
+                content.remove(index);
+            }
+        }
+    }
+
+    Article {
+        title,
+        author: dom
+            .meta("author")
+            .or_else(|| dom.meta("article:author"))
+            .filter(|author| !author.starts_with("http")),
+        published: dom
+            .meta("article:published_time")
+            .or_else(|| dom.meta("date"))
+            .and_then(|date| parse_date(&date)),
+        url: dom
+            .link("canonical")
+            .or_else(|| dom.meta("og:url"))
+            .filter(|url| url.starts_with("https://") || url.starts_with("http://")),
+        content,
+    }
+}
+
+/// Every node in the `<body>` of an HTML fragment or document, without
+/// looking for the main content.
+pub fn body_nodes(html: &str) -> Vec<Node> {
+    let dom = Dom::parse(html);
+    let body = dom.find(0, "body").unwrap_or(0);
+    dom.to_nodes(body, false)
+}
+
+/// Converts rich text pasted into the editor to markup. The HTML is
+/// cleaned with ammonia first, so only formatting the converter understands
+/// and links with safe schemes get through.
+pub fn paste_to_markup(html: &str) -> String {
+    let cleaned = ammonia::Builder::default()
+        .tags(PASTE_TAGS.iter().copied().collect())
+        .tag_attributes(HashMap::from([
+            ("a", HashSet::from(["href"])),
+            ("img", HashSet::from(["src"])),
+            // Read for Google Docs formatting, never passed on
+            ("b", HashSet::from(["style"])),
+            ("span", HashSet::from(["style"])),
+        ]))
+        .generic_attributes(HashSet::new())
+        .link_rel(None)
+        .clean(html)
+        .to_string();
+
+    nodes::to_markdown(&body_nodes(&cleaned), &|url| url.to_string())
+        .trim()
+        .to_string()
+}
+
+/// `YYYY-MM-DD` at the start of a date, as found in meta tags.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)
+        .map(|datetime| DateTime::<Utc>::from_naive_utc_and_offset(datetime, Utc))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+    collapsed
+}
+
+fn node_text(node: &Node) -> String {
+    match node {
+        Node::Text(text) => text.clone(),
+        Node::Element(element) => element
+            .children
+            .iter()
+            .flatten()
+            .map(node_text)
+            .collect::<String>()
+            .trim()
+            .to_string(),
+    }
+}
+
+enum Data {
+    Document,
+    Element {
+        name: QualName,
+        attrs: Vec<Attribute>,
+        template_contents: Option<usize>,
+    },
+    Text(String),
+    Comment,
+}
+
+struct DomNode {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    data: Data,
+}
+
+/// A parsed HTML document. Nodes live in one vector and refer to each
+/// other by index; the document itself is node 0.
+struct Dom {
+    nodes: Vec<DomNode>,
+    /// Also drop elements whose class or id suggests comments, sidebars
+    /// and the like. Only done when picking an article out of a whole page.
+    drop_unlikely: bool,
+}
+
+impl Dom {
+    fn parse(html: &str) -> Dom {
+        let dom = Dom {
+            nodes: vec![DomNode {
+                parent: None,
+                children: Vec::new(),
+                data: Data::Document,
+            }],
+            drop_unlikely: false,
+        };
+        parse_document(dom, Default::default()).one(html)
+    }
+
+    fn push(&mut self, data: Data) -> usize {
+        self.nodes.push(DomNode {
+            parent: None,
+            children: Vec::new(),
+            data,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    fn append_node(&mut self, parent: usize, child: usize) {
+        self.detach(child);
+        self.nodes[child].parent = Some(parent);
+        self.nodes[parent].children.push(child);
+    }
+
+    fn append_text(&mut self, parent: usize, text: &str) {
+        if let Some(&last) = self.nodes[parent].children.last() {
+            if let Data::Text(existing) = &mut self.nodes[last].data {
+                existing.push_str(text);
+                return;
+            }
+        }
+        let child = self.push(Data::Text(text.to_string()));
+        self.append_node(parent, child);
+    }
+
+    fn tag(&self, id: usize) -> Option<&str> {
+        match &self.nodes[id].data {
+            Data::Element { name, .. } => Some(&name.local),
+            _ => None,
+        }
+    }
+
+    fn attr(&self, id: usize, name: &str) -> Option<&str> {
+        match &self.nodes[id].data {
+            Data::Element { attrs, .. } => attrs
+                .iter()
+                .find(|attr| &*attr.name.local == name)
+                .map(|attr| &*attr.value),
+            _ => None,
+        }
+    }
+
+    /// Every node below `id`, in document order.
+    fn descendants(&self, id: usize) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = self.nodes[id].children.iter().rev().copied().collect();
+        while let Some(next) = stack.pop() {
+            found.push(next);
+            stack.extend(self.nodes[next].children.iter().rev());
+        }
+        found
+    }
+
+    fn find(&self, id: usize, tag: &str) -> Option<usize> {
+        self.descendants(id)
+            .into_iter()
+            .find(|&node| self.tag(node) == Some(tag))
+    }
+
+    fn text(&self, id: usize) -> String {
+        self.descendants(id)
+            .into_iter()
+            .filter_map(|node| match &self.nodes[node].data {
+                Data::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `content` of `<meta name=...>` or `<meta property=...>`.
+    fn meta(&self, key: &str) -> Option<String> {
+        self.descendants(0)
+            .into_iter()
+            .filter(|&node| self.tag(node) == Some("meta"))
+            .find(|&node| {
+                self.attr(node, "name") == Some(key) || self.attr(node, "property") == Some(key)
+            })
+            .and_then(|node| self.attr(node, "content"))
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    }
+
+    /// `href` of `<link rel=...>`.
+    fn link(&self, rel: &str) -> Option<String> {
+        self.descendants(0)
+            .into_iter()
+            .filter(|&node| self.tag(node) == Some("link"))
+            .find(|&node| self.attr(node, "rel") == Some(rel))
+            .and_then(|node| self.attr(node, "href"))
+            .map(|href| href.trim().to_string())
+    }
+
+    /// Whether an element and everything in it is left out of the content.
+    fn is_dropped(&self, id: usize) -> bool {
+        let Some(tag) = self.tag(id) else {
+            return false;
+        };
+        if DROPPED_TAGS.contains(&tag)
+            || self.attr(id, "hidden").is_some()
+            || self.attr(id, "aria-hidden") == Some("true")
+        {
+            return true;
+        }
+        let names = self.names(id);
+        self.drop_unlikely
+            && tag != "body"
+            && UNLIKELY_NAMES.iter().any(|name| names.contains(name))
+            && !LIKELY_NAMES.iter().any(|name| names.contains(name))
+    }
+
+    /// Class and id, lowercased.
+    fn names(&self, id: usize) -> String {
+        format!(
+            "{} {}",
+            self.attr(id, "class").unwrap_or_default(),
+            self.attr(id, "id").unwrap_or_default()
+        )
+        .to_lowercase()
+    }
+
+    /// The element holding the page's main text. Each paragraph adds to the
+    /// score of its parent and, by half, its grandparent; the best-scoring
+    /// container after discounting links wins.
+    fn main_content(&self) -> usize {
+        let body = self.find(0, "body").unwrap_or(0);
+
+        let mut dropped = vec![false; self.nodes.len()];
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for id in self.descendants(body) {
+            let parent = self.nodes[id].parent;
+            dropped[id] = self.is_dropped(id) || parent.is_some_and(|parent| dropped[parent]);
+            if dropped[id] || !matches!(self.tag(id), Some("p" | "pre" | "td" | "blockquote")) {
+                continue;
+            }
+
+            let text = self.text(id);
+            let len = text.trim().chars().count();
+            if len < MIN_PARAGRAPH_LEN {
+                continue;
+            }
+            let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+
+            let grandparent = parent.and_then(|parent| self.nodes[parent].parent);
+            for (ancestor, share) in [(parent, 1.0), (grandparent, 0.5)] {
+                if let Some(ancestor) = ancestor.filter(|&ancestor| self.tag(ancestor).is_some()) {
+                    *scores
+                        .entry(ancestor)
+                        .or_insert_with(|| self.initial_score(ancestor)) += score * share;
+                }
+            }
+        }
+
+        scores
+            .into_iter()
+            .map(|(id, score)| (id, score * (1.0 - self.link_density(id))))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+            .map(|(id, _)| id)
+            .unwrap_or(body)
+    }
+
+    fn initial_score(&self, id: usize) -> f64 {
+        let tag_score = match self.tag(id).unwrap_or_default() {
+            "article" | "main" => 10.0,
+            "div" => 5.0,
+            "pre" | "td" | "blockquote" => 3.0,
+            "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+            _ => 0.0,
+        };
+
+        let names = self.names(id);
+        let mut name_score = 0.0;
+        if UNLIKELY_NAMES.iter().any(|name| names.contains(name)) {
+            name_score -= 25.0;
+        }
+        if LIKELY_NAMES.iter().any(|name| names.contains(name)) {
+            name_score += 25.0;
+        }
+
+        tag_score + name_score
+    }
+
+    /// Share of an element's text that is inside links.
+    fn link_density(&self, id: usize) -> f64 {
+        let total = self.text(id).chars().count();
+        if total == 0 {
+            return 0.0;
+        }
+        let linked: usize = self
+            .descendants(id)
+            .into_iter()
+            .filter(|&node| self.tag(node) == Some("a"))
+            .map(|link| self.text(link).chars().count())
+            .sum();
+        linked as f64 / total as f64
+    }
+
+    /// The children of `id` as nodes. Whitespace is collapsed outside
+    /// `<pre>`, and whitespace that only separates blocks is dropped.
+    fn to_nodes(&self, id: usize, preformatted: bool) -> Vec<Node> {
+        let inline = self.tag(id).is_some_and(|tag| INLINE_TAGS.contains(&tag));
+
+        let siblings = &self.nodes[id].children;
+        let is_block = |sibling: Option<&usize>| match sibling {
+            Some(&sibling) => self
+                .tag(sibling)
+                .is_some_and(|tag| !INLINE_TAGS.contains(&tag)),
+            None => !inline,
+        };
+
+        let mut nodes = Vec::new();
+        for (index, &child) in siblings.iter().enumerate() {
+            match &self.nodes[child].data {
+                Data::Text(text) if preformatted => nodes.push(Node::Text(text.clone())),
+                Data::Text(text) => {
+                    let text = collapse_whitespace(text);
+                    let separates_blocks = is_block(index.checked_sub(1).map(|i| &siblings[i]))
+                        || is_block(siblings.get(index + 1));
+                    if !text.trim().is_empty() || !separates_blocks {
+                        nodes.push(Node::Text(text));
+                    }
+                }
+                Data::Element { name, .. } if !self.is_dropped(child) => {
+                    let tag = self.styled_tag(child, &name.local);
+                    let children = self.to_nodes(child, preformatted || tag == "pre");
+                    // A paragraph inside a list item would split the list
+                    if tag == "p" && self.tag(id) == Some("li") {
+                        nodes.extend(children);
+                        continue;
+                    }
+
+                    let mut attrs = HashMap::new();
+                    if let Some(href) = self.attr(child, "href") {
+                        attrs.insert("href".to_string(), href.to_string());
+                    }
+                    // Lazy-loaded images keep the real source in data-src
+                    if let Some(src) = self
+                        .attr(child, "src")
+                        .or_else(|| self.attr(child, "data-src"))
+                    {
+                        attrs.insert("src".to_string(), src.to_string());
+                    }
+                    nodes.push(Node::Element(NodeElement {
+                        tag,
+                        attrs: Some(attrs),
+                        children: Some(children),
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        // Leading and trailing spaces in a block would end up in the markup
+        if !inline && !preformatted {
+            if let Some(Node::Text(text)) = nodes.first_mut() {
+                *text = text.trim_start().to_string();
+            }
+            if let Some(Node::Text(text)) = nodes.last_mut() {
+                *text = text.trim_end().to_string();
+            }
+        }
+        nodes
+    }
+
+    /// Google Docs marks bold and italics with inline styles, and wraps
+    /// whole documents in `<b style="font-weight:normal">`.
+    fn styled_tag(&self, id: usize, tag: &str) -> String {
+        let style = self
+            .attr(id, "style")
+            .unwrap_or_default()
+            .to_lowercase()
+            .replace(' ', "");
+        let weight = style
+            .split(';')
+            .find_map(|declaration| declaration.strip_prefix("font-weight:"));
+
+        match tag {
+            "b" | "strong" if matches!(weight, Some("normal" | "400")) => "span",
+            "span" if matches!(weight, Some("bold" | "700" | "800" | "900")) => "strong",
+            "span" if style.contains("font-style:italic") => "em",
+            _ => tag,
+        }
+        .to_string()
+    }
+}
+
+impl TreeSink for Dom {
+    type Handle = usize;
+    type Output = Self;
+
+    fn finish(self) -> Self {
+        self
+    }
+
+    fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> ExpandedName<'a> {
+        match &self.nodes[*target].data {
+            Data::Element { name, .. } => name.expanded(),
+            _ => panic!("not an element"),
+        }
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> usize {
+        let template_contents = flags.template.then(|| self.push(Data::Document));
+        self.push(Data::Element {
+            name,
+            attrs,
+            template_contents,
+        })
+    }
+
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        self.push(Data::Comment)
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.push(Data::Comment)
+    }
+
+    fn append(&mut self, parent: &usize, child: NodeOrText<usize>) {
+        match child {
+            NodeOrText::AppendNode(node) => self.append_node(*parent, node),
+            NodeOrText::AppendText(text) => self.append_text(*parent, &text),
+        }
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &usize,
+        prev_element: &usize,
+        child: NodeOrText<usize>,
+    ) {
+        if self.nodes[*element].parent.is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        _name: StrTendril,
+        _public_id: StrTendril,
+        _system_id: StrTendril,
+    ) {
+    }
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        match &self.nodes[*target].data {
+            Data::Element {
+                template_contents: Some(contents),
+                ..
+            } => *contents,
+            _ => panic!("not a template"),
+        }
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+    fn append_before_sibling(&mut self, sibling: &usize, new_node: NodeOrText<usize>) {
+        let Some(parent) = self.nodes[*sibling].parent else {
+            return;
+        };
+        let child = match new_node {
+            NodeOrText::AppendNode(node) => {
+                self.detach(node);
+                node
+            }
+            NodeOrText::AppendText(text) => {
+                let position = self.nodes[parent]
+                    .children
+                    .iter()
+                    .position(|&child| child == *sibling)
+                    .unwrap_or(0);
+                if position > 0 {
+                    let previous = self.nodes[parent].children[position - 1];
+                    if let Data::Text(existing) = &mut self.nodes[previous].data {
+                        existing.push_str(&text);
+                        return;
+                    }
+                }
+                self.push(Data::Text(text.to_string()))
+            }
+        };
+
+        let position = self.nodes[parent]
+            .children
+            .iter()
+            .position(|&child| child == *sibling)
+            .unwrap_or(0);
+        self.nodes[parent].children.insert(position, child);
+        self.nodes[child].parent = Some(parent);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &usize, attrs: Vec<Attribute>) {
+        if let Data::Element {
+            attrs: existing, ..
+        } = &mut self.nodes[*target].data
+        {
+            for attr in attrs {
+                if !existing.iter().any(|known| known.name == attr.name) {
+                    existing.push(attr);
+                }
+            }
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &usize) {
+        self.detach(*target);
+    }
+
+    fn reparent_children(&mut self, node: &usize, new_parent: &usize) {
+        for child in std::mem::take(&mut self.nodes[*node].children) {
+            self.nodes[child].parent = Some(*new_parent);
+            self.nodes[*new_parent].children.push(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodes::to_markdown;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <title>Site name | A Long Walk</title>
+  <meta property="og:title" content="A Long Walk">
+  <meta name="author" content="Ann">
+  <meta property="article:published_time" content="2019-04-02T10:00:00Z">
+  <link rel="canonical" href="https://example.com/walk">
+</head>
+<body>
+  <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+  <div class="sidebar"><p>Subscribe to the newsletter, it is great, really, honestly.</p></div>
+  <article class="post">
+    <h1>A Long Walk</h1>
+    <p>We set out at <em>dawn</em>, walked along the river, and reached the hills by noon.</p>
+    <figure><img data-src="/img/hills.jpg"><figcaption>The hills</figcaption></figure>
+    <p>Later, tired but happy, we took the
+       <a href="https://example.com/train">train</a> home.</p>
+    <div class="comments"><p>Great post, thanks for sharing, loved it!</p></div>
+  </article>
+  <footer>© Example</footer>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_extract_article() {
+        let article = extract_article(PAGE);
+        assert_eq!(article.title.as_deref(), Some("A Long Walk"));
+        assert_eq!(article.author.as_deref(), Some("Ann"));
+        assert_eq!(article.url.as_deref(), Some("https://example.com/walk"));
+        assert_eq!(
+            article.published.unwrap().format("%Y-%m-%d").to_string(),
+            "2019-04-02"
+        );
+
+        assert_eq!(
+            to_markdown(&article.content, &|url| url.to_string()),
+            "We set out at *dawn*, walked along the river, and reached the hills by noon.\n\n\
+             ![The hills](/img/hills.jpg)\n\
+             Later, tired but happy, we took the [train](https://example.com/train) home.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_article_without_metadata() {
+        let article = extract_article(
+            "<title>Notes</title><div><p>Only a short one.</p><pre>  keep\n    this</pre></div>",
+        );
+        assert_eq!(article.title.as_deref(), Some("Notes"));
+        assert!(article.author.is_none() && article.published.is_none() && article.url.is_none());
+        assert_eq!(
+            to_markdown(&article.content, &|url| url.to_string()),
+            "Only a short one.\n\n```\n  keep\n    this\n```\n\n"
+        );
+    }
+
+    #[test]
+    fn test_paste_to_markup() {
+        // Roughly what Google Docs puts on the clipboard
+        let docs = r#"<meta charset="utf-8"><b style="font-weight:normal;" id="docs-internal-guid-1">
+            <p dir="ltr"><span style="font-weight:400;">Plain, </span><span style="font-weight:700;">bold</span>
+            <span style="font-style:italic;">and italic</span></p>
+            <ul><li dir="ltr"><p dir="ltr"><span>One</span></p></li><li><p><span>Two</span></p></li></ul></b>"#;
+        assert_eq!(
+            paste_to_markup(docs),
+            "Plain, **bold** *and italic*\n\n- One\n- Two"
+        );
+
+        let hostile = r#"<p onclick="x()">Hi <a href="javascript:alert(1)">there</a><script>alert(2)</script>
+            <img src="https://example.com/a.png" onerror="x()"></p>"#;
+        assert_eq!(
+            paste_to_markup(hostile),
+            "Hi there ![](https://example.com/a.png)"
+        );
+    }
+}
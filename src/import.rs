@@ -0,0 +1,800 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use nonograph_markup::sanitize_text;
+use quick_xml::events::{BytesRef, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+use url::Url;
+
+use crate::bulk::archived_sources;
+use crate::html::{self, extract_article};
+use crate::nodes::{self, Node};
+use crate::{allocate_post_id, docx, save, Post, PostKind};
+
+/// Files picked up when importing a directory
+const HTML_EXTENSIONS: &[&str] = &["html", "htm"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Imported(String),
+    /// Already imported; holds the existing post's URL
+    Skipped(String),
+    Failed(String),
+}
+
+/// Imports an HTML file, or every HTML file under a directory, as posts in
+/// `<base_dir>/content`. Each page's main content is extracted and converted
+/// like an archived Telegraph page. Pages whose source was imported before
+/// are skipped.
+pub fn import_html(path: &Path, base_dir: &Path) -> Result<Vec<(PathBuf, Outcome)>, String> {
+    let files = html_files(path)?;
+    let mut imported = archived_sources(&base_dir.join("content"));
+
+    Ok(files
+        .into_iter()
+        .map(|file| {
+            let outcome =
+                import_file(&file, base_dir, &mut imported).unwrap_or_else(Outcome::Failed);
+            (file, outcome)
+        })
+        .collect())
+}
+
+fn import_file(
+    file: &Path,
+    base_dir: &Path,
+    imported: &mut HashMap<String, String>,
+) -> Result<Outcome, String> {
+    let data = fs::read(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let article = extract_article(&String::from_utf8_lossy(&data));
+
+    // Where the page was published, or else the file it came from
+    let source = match &article.url {
+        Some(url) => url.clone(),
+        None => file_url(file)?,
+    };
+    if let Some(post) = imported.get(&source) {
+        return Ok(Outcome::Skipped(post.clone()));
+    }
+
+    // Relative links only make sense against the page's own URL. Without
+    // one they'd lead nowhere once published, so they're dropped.
+    let page_url = article.url.as_deref().and_then(|url| Url::parse(url).ok());
+    let mut content = article.content;
+    if page_url.is_none() {
+        let dropped = drop_relative_links(&mut content);
+        if dropped > 0 {
+            eprintln!(
+                "warning: {} has no canonical URL, dropped {} relative link(s)",
+                file.display(),
+                dropped
+            );
+        }
+    }
+    let resolve_url = |link: &str| match &page_url {
+        Some(page_url) => page_url
+            .join(link)
+            .map(String::from)
+            .unwrap_or_else(|_| link.to_string()),
+        None => link.to_string(),
+    };
+    let raw_content = nodes::to_markdown(&content, &resolve_url);
+
+    let title = article.title.unwrap_or_else(|| file_stem(file));
+    let created_at = article
+        .published
+        .or_else(|| modified(file))
+        .unwrap_or_else(Utc::now);
+
+    save_import(
+        &title,
+        article.author.as_deref().unwrap_or_default(),
+        created_at,
+        raw_content,
+        source,
+        base_dir,
+        imported,
+    )
+}
+
+/// Removes `href` and `src` values that aren't absolute URLs and returns how
+/// many went. Links keep their text; images without a source are left out.
+fn drop_relative_links(nodes: &mut [Node]) -> usize {
+    let mut dropped = 0;
+    for node in nodes {
+        if let Node::Element(element) = node {
+            if let Some(attrs) = element.attrs.as_mut() {
+                for name in ["href", "src"] {
+                    if attrs
+                        .get(name)
+                        .is_some_and(|link| Url::parse(link).is_err())
+                    {
+                        attrs.remove(name);
+                        dropped += 1;
+                    }
+                }
+            }
+            if let Some(children) = element.children.as_mut() {
+                dropped += drop_relative_links(children);
+            }
+        }
+    }
+    dropped
+}
+
+/// Imports a Word document as a post in `<base_dir>/content`, unless it was
+/// imported before. Alongside the outcome come warnings about anything the
+/// markup couldn't represent.
+pub fn import_docx(file: &Path, base_dir: &Path) -> Result<(Outcome, Vec<String>), String> {
+    let source = file_url(file)?;
+    let mut imported = archived_sources(&base_dir.join("content"));
+    if let Some(post) = imported.get(&source) {
+        return Ok((Outcome::Skipped(post.clone()), Vec::new()));
+    }
+
+    let data = fs::read(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let document = docx::convert(&data)?;
+    let outcome = save_import(
+        &document.title.unwrap_or_else(|| file_stem(file)),
+        document.author.as_deref().unwrap_or_default(),
+        document
+            .created
+            .or_else(|| modified(file))
+            .unwrap_or_else(Utc::now),
+        document.markup,
+        source,
+        base_dir,
+        &mut imported,
+    )?;
+    Ok((outcome, document.warnings))
+}
+
+fn file_url(file: &Path) -> Result<String, String> {
+    fs::canonicalize(file)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok())
+        .map(String::from)
+        .ok_or_else(|| format!("Failed to resolve {}", file.display()))
+}
+
+fn file_stem(file: &Path) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn modified(file: &Path) -> Option<DateTime<Utc>> {
+    fs::metadata(file)
+        .and_then(|meta| meta.modified())
+        .map(DateTime::<Utc>::from)
+        .ok()
+}
+
+/// Saves converted content as a new post that records its `source`.
+fn save_import(
+    title: &str,
+    author: &str,
+    created_at: DateTime<Utc>,
+    raw_content: String,
+    source: String,
+    base_dir: &Path,
+    imported: &mut HashMap<String, String>,
+) -> Result<Outcome, String> {
+    if raw_content.trim().is_empty() {
+        return Err("No content found".to_string());
+    }
+
+    let base = base_dir.to_string_lossy();
+    let post = Post {
+        id: allocate_post_id(title, |id| save::post_file_exists_in_dir(id, &base))?,
+        title: sanitize_text(title),
+        author: sanitize_text(author),
+        content: String::new(),
+        raw_content,
+        created_at,
+        password_hash: None,
+        kind: PostKind::Markdown,
+    };
+
+    let fields = [
+        ("source", source.clone()),
+        ("imported", Utc::now().format("%Y-%m-%d").to_string()),
+    ];
+    save::save_post_with_fields_in_dir(&post, &fields, &base)?;
+
+    let url = format!("/{}", post.id);
+    imported.insert(source, url.clone());
+    Ok(Outcome::Imported(url))
+}
+
+/// `path` itself, or the HTML files under it in a stable order.
+fn html_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let entries =
+        fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(html_files(&path)?);
+        } else if path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| HTML_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Blog export formats `import --format` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// WordPress eXtended RSS, from Tools → Export
+    Wxr,
+    /// Ghost's JSON export, from Settings → Labs
+    Ghost,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<ExportFormat> {
+        match name {
+            "wxr" => Some(ExportFormat::Wxr),
+            "ghost" => Some(ExportFormat::Ghost),
+            _ => None,
+        }
+    }
+}
+
+/// A published post or page read from a blog export.
+#[derive(Debug, PartialEq)]
+struct ExportedPost {
+    permalink: String,
+    title: String,
+    author: String,
+    published: Option<DateTime<Utc>>,
+    html: String,
+}
+
+/// Imports every published post and page in a blog export. `site_url` is
+/// where the old blog lived; relative links and Ghost's `__GHOST_URL__`
+/// placeholders are resolved against it. Results are keyed by each post's
+/// old permalink, in export order.
+pub fn import_export(
+    format: ExportFormat,
+    export: &str,
+    site_url: Option<&Url>,
+    base_dir: &Path,
+) -> Result<Vec<(String, Outcome)>, String> {
+    let posts = match format {
+        ExportFormat::Wxr => parse_wxr(export)?,
+        ExportFormat::Ghost => parse_ghost(export, site_url)?,
+    };
+    let mut imported = archived_sources(&base_dir.join("content"));
+
+    Ok(posts
+        .into_iter()
+        .map(|post| {
+            let outcome = match imported.get(&post.permalink) {
+                Some(existing) => Outcome::Skipped(existing.clone()),
+                None => import_exported(&post, site_url, base_dir, &mut imported)
+                    .unwrap_or_else(Outcome::Failed),
+            };
+            (post.permalink, outcome)
+        })
+        .collect())
+}
+
+fn import_exported(
+    post: &ExportedPost,
+    site_url: Option<&Url>,
+    base_dir: &Path,
+    imported: &mut HashMap<String, String>,
+) -> Result<Outcome, String> {
+    let base = Url::parse(&post.permalink)
+        .ok()
+        .or_else(|| site_url.cloned());
+    let resolve_url = |link: &str| match &base {
+        Some(base) => base
+            .join(link)
+            .map(String::from)
+            .unwrap_or_else(|_| link.to_string()),
+        None => link.to_string(),
+    };
+    let raw_content = nodes::to_markdown(&html::body_nodes(&autop(&post.html)), &resolve_url);
+
+    save_import(
+        &post.title,
+        &post.author,
+        post.published.unwrap_or_else(Utc::now),
+        raw_content,
+        post.permalink.clone(),
+        base_dir,
+        imported,
+    )
+}
+
+/// Posts and pages with `publish` status from a WordPress export. Authors
+/// are listed once at the top and referred to by login in each item.
+fn parse_wxr(xml: &str) -> Result<Vec<ExportedPost>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut open: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut display_names = HashMap::new();
+    let mut items = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid WXR at byte {}: {}", reader.error_position(), e))?;
+        match event {
+            Event::Start(element) => {
+                let name = element.name().as_ref().to_string();
+                if name == "item" || name == "wp:author" {
+                    fields.clear();
+                }
+                open.push(name);
+                text.clear();
+            }
+            Event::Text(content) => text.push_str(&content.xml10_content()),
+            Event::CData(content) => text.push_str(&content.xml10_content()),
+            Event::GeneralRef(reference) => text.push_str(&resolve_reference(&reference)?),
+            Event::End(_) => {
+                let name = open.pop().unwrap_or_default();
+                match (open.last().map(String::as_str), name.as_str()) {
+                    (_, "item") => items.push(std::mem::take(&mut fields)),
+                    (_, "wp:author") => {
+                        let mut get = |key: &str| fields.remove(key).unwrap_or_default();
+                        display_names.insert(get("wp:author_login"), get("wp:author_display_name"));
+                    }
+                    (Some("item" | "wp:author"), _) => {
+                        fields.insert(name, text.trim().to_string());
+                    }
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(items
+        .into_iter()
+        .filter(|item| {
+            item.get("wp:status").map(String::as_str) == Some("publish")
+                && matches!(
+                    item.get("wp:post_type").map(String::as_str),
+                    Some("post" | "page")
+                )
+        })
+        .map(|mut item| {
+            let mut get = |key: &str| item.remove(key).unwrap_or_default();
+            let login = get("dc:creator");
+            let permalink = match get("link") {
+                link if link.is_empty() => get("guid"),
+                link => link,
+            };
+            let published = parse_export_date(&get("wp:post_date_gmt"))
+                .or_else(|| parse_export_date(&get("wp:post_date")))
+                .or_else(|| parse_export_date(&get("pubDate")));
+            ExportedPost {
+                permalink,
+                title: get("title"),
+                author: display_names
+                    .get(&login)
+                    .filter(|name| !name.is_empty())
+                    .cloned()
+                    .unwrap_or(login),
+                published,
+                html: get("content:encoded"),
+            }
+        })
+        .collect())
+}
+
+/// `&amp;` and friends, which the XML reader hands over unresolved.
+pub fn resolve_reference(reference: &BytesRef) -> Result<String, String> {
+    if let Some(c) = reference
+        .resolve_char_ref()
+        .map_err(|e| format!("Invalid character reference: {}", e))?
+    {
+        return Ok(c.to_string());
+    }
+    let name = reference.xml10_content();
+    let resolved = match name.as_ref() {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        _ => return Err(format!("Unknown entity &{};", name)),
+    };
+    Ok(resolved.to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GhostExport {
+    /// `{"db": [{"meta": ..., "data": ...}]}`, as downloaded
+    Wrapped {
+        db: Vec<GhostDb>,
+    },
+    Bare(GhostDb),
+}
+
+#[derive(Deserialize)]
+struct GhostDb {
+    data: GhostData,
+}
+
+#[derive(Deserialize)]
+struct GhostData {
+    #[serde(default)]
+    posts: Vec<GhostPost>,
+    #[serde(default)]
+    users: Vec<GhostUser>,
+    /// Ghost 1.22+; older exports put `author_id` on the post
+    #[serde(default)]
+    posts_authors: Vec<GhostPostAuthor>,
+}
+
+/// Ids are strings in current exports and numbers in old ones
+#[derive(Deserialize)]
+struct GhostPost {
+    id: serde_json::Value,
+    title: String,
+    slug: String,
+    html: Option<String>,
+    status: String,
+    published_at: Option<String>,
+    author_id: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GhostUser {
+    id: serde_json::Value,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GhostPostAuthor {
+    post_id: serde_json::Value,
+    author_id: serde_json::Value,
+    #[serde(default)]
+    sort_order: u32,
+}
+
+/// Published posts and pages from a Ghost export. Ghost doesn't record the
+/// site's address, so permalinks are `/<slug>/` unless `site_url` is given.
+fn parse_ghost(json: &str, site_url: Option<&Url>) -> Result<Vec<ExportedPost>, String> {
+    let export: GhostExport =
+        serde_json::from_str(json).map_err(|e| format!("Invalid Ghost export: {}", e))?;
+    let data = match export {
+        GhostExport::Wrapped { db } => {
+            db.into_iter()
+                .next()
+                .ok_or("Ghost export has no data")?
+                .data
+        }
+        GhostExport::Bare(db) => db.data,
+    };
+
+    let site = site_url.map(|url| url.as_str().trim_end_matches('/').to_string());
+    let site = site.as_deref().unwrap_or_default();
+
+    Ok(data
+        .posts
+        .iter()
+        .filter(|post| post.status == "published")
+        .map(|post| {
+            let primary_author = data
+                .posts_authors
+                .iter()
+                .filter(|link| link.post_id == post.id)
+                .min_by_key(|link| link.sort_order)
+                .map(|link| &link.author_id)
+                .or(post.author_id.as_ref());
+            let author = data
+                .users
+                .iter()
+                .find(|user| Some(&user.id) == primary_author)
+                .map(|user| user.name.clone())
+                .unwrap_or_default();
+
+            ExportedPost {
+                permalink: format!("{}/{}/", site, post.slug),
+                title: post.title.clone(),
+                author,
+                published: post.published_at.as_deref().and_then(parse_export_date),
+                html: post
+                    .html
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace("__GHOST_URL__", site),
+            }
+        })
+        .collect())
+}
+
+/// Dates as blog exports write them: RFC 3339, RFC 2822 or WordPress's
+/// `YYYY-MM-DD HH:MM:SS`.
+fn parse_export_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|date| date.and_utc())
+        })
+}
+
+/// WordPress keeps classic-editor posts without `<p>` tags, with blank
+/// lines between paragraphs, and adds the tags when displaying them.
+fn autop(html: &str) -> String {
+    if html.contains("<p>") || html.contains("<p ") {
+        return html.to_string();
+    }
+
+    html.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", paragraph.replace('\n', "<br>")))
+        .collect()
+}
+
+/// A table of old permalinks and the posts they became, for redirects.
+pub fn mapping_report(results: &[(String, Outcome)]) -> String {
+    let width = results
+        .iter()
+        .map(|(permalink, _)| permalink.len())
+        .max()
+        .unwrap_or(0)
+        .max("OLD PERMALINK".len());
+
+    let mut report = format!("{:<width$}  {:<8}  NEW POST\n", "OLD PERMALINK", "STATUS");
+    for (permalink, outcome) in results {
+        let (status, detail) = match outcome {
+            Outcome::Imported(post) => ("imported", post),
+            Outcome::Skipped(post) => ("skipped", post),
+            Outcome::Failed(error) => ("failed", error),
+        };
+        report.push_str(&format!(
+            "{:<width$}  {:<8}  {}\n",
+            permalink, status, detail
+        ));
+    }
+
+    report
+}
+
+/// `old,new` lines for every permalink that has a post, ready for a
+/// redirect map.
+pub fn mapping_csv(results: &[(String, Outcome)]) -> String {
+    let quote = |field: &str| {
+        if field.contains([',', '"', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+
+    let mut csv = String::from("old_permalink,new_post\n");
+    for (permalink, outcome) in results {
+        if let Outcome::Imported(post) | Outcome::Skipped(post) = outcome {
+            csv.push_str(&format!("{},{}\n", quote(permalink), quote(post)));
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_import_html_directory() {
+        let dir = tempdir().unwrap();
+        let pages = dir.path().join("site");
+        fs::create_dir_all(pages.join("2019")).unwrap();
+        fs::write(
+            pages.join("2019/walk.html"),
+            r#"<html><head><title>A Walk</title>
+            <meta name="author" content="Ann">
+            <meta property="article:published_time" content="2019-04-02">
+            <link rel="canonical" href="https://example.com/2019/walk"></head>
+            <body><main><p>We walked, and walked, and <a href="../about">then</a> stopped.</p>
+            <img src="hills.jpg"></main></body></html>"#,
+        )
+        .unwrap();
+        // No canonical URL to resolve the relative links against
+        fs::write(
+            pages.join("notes.htm"),
+            r#"<p>Plain <a href="todo.html">notes</a>, <a href="https://example.com/">kept</a></p><img src="pic.png">"#,
+        )
+        .unwrap();
+        fs::write(pages.join("empty.html"), "<nav>Menu</nav>").unwrap();
+        fs::write(pages.join("style.css"), "p {}").unwrap();
+
+        let results = import_html(&pages, dir.path()).unwrap();
+        let today = Utc::now().format("%m-%d-%Y");
+        assert_eq!(
+            results,
+            vec![
+                (
+                    pages.join("2019/walk.html"),
+                    Outcome::Imported(format!("/a-walk-{}", today))
+                ),
+                (
+                    pages.join("empty.html"),
+                    Outcome::Failed("No content found".to_string())
+                ),
+                (
+                    pages.join("notes.htm"),
+                    Outcome::Imported(format!("/notes-{}", today))
+                ),
+            ]
+        );
+
+        let post =
+            fs::read_to_string(dir.path().join(format!("content/a-walk-{}.md", today))).unwrap();
+        assert!(post.starts_with("---\ntitle: A Walk\ndate: 2019-04-02\nauthor: Ann\n"));
+        assert!(post.contains("\nsource: https://example.com/2019/walk\n"));
+        assert!(post.ends_with(
+            "We walked, and walked, and [then](https://example.com/about) stopped.\n\n\
+             ![](https://example.com/2019/hills.jpg)"
+        ));
+
+        let notes =
+            fs::read_to_string(dir.path().join(format!("content/notes-{}.md", today))).unwrap();
+        assert!(notes.contains("\nsource: file://"));
+        assert!(notes.ends_with("---\n\nPlain notes, [kept](https://example.com/)\n\n"));
+
+        // Importing again finds both pages already there
+        let results = import_html(&pages, dir.path()).unwrap();
+        assert_eq!(results[0].1, Outcome::Skipped(format!("/a-walk-{}", today)));
+        assert_eq!(results[2].1, Outcome::Skipped(format!("/notes-{}", today)));
+    }
+
+    const WXR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/"
+     xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+  <title>Old Blog</title>
+  <wp:author>
+    <wp:author_login><![CDATA[ann]]></wp:author_login>
+    <wp:author_display_name><![CDATA[Ann Smith]]></wp:author_display_name>
+  </wp:author>
+  <item>
+    <title>Fish &amp; Chips</title>
+    <link>https://old.example.com/2015/03/fish-and-chips/</link>
+    <dc:creator><![CDATA[ann]]></dc:creator>
+    <content:encoded><![CDATA[First paragraph with <a href="/menu/">a link</a>.
+
+Second
+line break]]></content:encoded>
+    <wp:post_date_gmt><![CDATA[2015-03-04 18:30:00]]></wp:post_date_gmt>
+    <wp:status><![CDATA[publish]]></wp:status>
+    <wp:post_type><![CDATA[post]]></wp:post_type>
+    <wp:postmeta><wp:meta_key>_edit_last</wp:meta_key></wp:postmeta>
+  </item>
+  <item>
+    <title>Unfinished</title>
+    <link>https://old.example.com/?p=9</link>
+    <dc:creator>ann</dc:creator>
+    <content:encoded><![CDATA[<p>Draft</p>]]></content:encoded>
+    <wp:status>draft</wp:status>
+    <wp:post_type>post</wp:post_type>
+  </item>
+  <item>
+    <title>logo.png</title>
+    <wp:status>inherit</wp:status>
+    <wp:post_type>attachment</wp:post_type>
+  </item>
+</channel>
+</rss>"#;
+
+    #[test]
+    fn test_parse_wxr() {
+        assert_eq!(
+            parse_wxr(WXR).unwrap(),
+            vec![ExportedPost {
+                permalink: "https://old.example.com/2015/03/fish-and-chips/".to_string(),
+                title: "Fish & Chips".to_string(),
+                author: "Ann Smith".to_string(),
+                published: parse_export_date("2015-03-04T18:30:00Z"),
+                html: "First paragraph with <a href=\"/menu/\">a link</a>.\n\nSecond\nline break"
+                    .to_string(),
+            }]
+        );
+        assert!(parse_wxr("<rss><channel><item></channel></rss>").is_err());
+    }
+
+    #[test]
+    fn test_import_wxr_with_mapping() {
+        let dir = tempdir().unwrap();
+        let results = import_export(ExportFormat::Wxr, WXR, None, dir.path()).unwrap();
+        let post = format!("/fish-chips-{}", Utc::now().format("%m-%d-%Y"));
+        let permalink = "https://old.example.com/2015/03/fish-and-chips/";
+        assert_eq!(
+            results,
+            vec![(permalink.to_string(), Outcome::Imported(post.clone()))]
+        );
+
+        let saved = fs::read_to_string(dir.path().join(format!("content{}.md", post))).unwrap();
+        assert!(
+            saved.starts_with("---\ntitle: Fish & Chips\ndate: 2015-03-04\nauthor: Ann Smith\n")
+        );
+        assert!(saved.ends_with(
+            "---\n\nFirst paragraph with [a link](https://old.example.com/menu/).\n\nSecond\nline break\n\n"
+        ));
+
+        // A second run maps the permalink to the post it already became
+        let results = import_export(ExportFormat::Wxr, WXR, None, dir.path()).unwrap();
+        assert_eq!(results[0].1, Outcome::Skipped(post.clone()));
+        assert_eq!(
+            mapping_csv(&results),
+            format!("old_permalink,new_post\n{},{}\n", permalink, post)
+        );
+        assert!(mapping_report(&results).contains(&format!("{}  skipped   {}", permalink, post)));
+    }
+
+    #[test]
+    fn test_parse_ghost() {
+        let export = r#"{"db": [{"meta": {"version": "5.0.0"}, "data": {
+            "posts": [
+                {"id": "a1", "title": "Hello Ghost", "slug": "hello-ghost", "status": "published",
+                 "html": "<p><img src=\"__GHOST_URL__/content/images/cat.jpg\"></p>",
+                 "published_at": "2021-06-01T09:00:00.000Z"},
+                {"id": "a2", "title": "Later", "slug": "later", "status": "draft", "html": "<p>x</p>",
+                 "published_at": null}
+            ],
+            "users": [{"id": "u1", "name": "Bo"}, {"id": "u2", "name": "Cy"}],
+            "posts_authors": [
+                {"post_id": "a1", "author_id": "u2", "sort_order": 1},
+                {"post_id": "a1", "author_id": "u1", "sort_order": 0}
+            ]
+        }}]}"#;
+
+        let site = Url::parse("https://blog.example.com/").unwrap();
+        assert_eq!(
+            parse_ghost(export, Some(&site)).unwrap(),
+            vec![ExportedPost {
+                permalink: "https://blog.example.com/hello-ghost/".to_string(),
+                title: "Hello Ghost".to_string(),
+                author: "Bo".to_string(),
+                published: parse_export_date("2021-06-01T09:00:00Z"),
+                html: "<p><img src=\"https://blog.example.com/content/images/cat.jpg\"></p>"
+                    .to_string(),
+            }]
+        );
+
+        // Old exports: numeric ids, author on the post, no wrapper
+        let old = r#"{"data": {"posts": [{"id": 1, "title": "Old", "slug": "old", "status": "published",
+            "html": "<p>Old</p>", "published_at": "2014-01-01 10:00:00", "author_id": 1}],
+            "users": [{"id": 1, "name": "Di"}]}}"#;
+        let posts = parse_ghost(old, None).unwrap();
+        assert_eq!(posts[0].permalink, "/old/");
+        assert_eq!(posts[0].author, "Di");
+        assert_eq!(
+            posts[0].published,
+            parse_export_date("2014-01-01T10:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::archiver::TelegraphArchiver;
+use crate::bulk::archived_sources;
+
+/// Jobs kept for their status pages; the oldest are forgotten first
+const MAX_JOBS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Archived(String),
+    /// Already archived; holds the existing post's URL
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub url: String,
+    pub status: JobStatus,
+}
+
+/// Archive jobs started from the `/archive` page. They only live in memory,
+/// so a restart forgets the jobs but not the posts they made.
+#[derive(Default)]
+pub struct ArchiveJobs {
+    jobs: Mutex<VecDeque<(String, Job)>>,
+}
+
+impl ArchiveJobs {
+    /// Records a running job for `url` and returns its id. Ids are random,
+    /// so only whoever started a job can find its status page.
+    pub fn start(&self, url: &str) -> String {
+        let id = format!("{:032x}", rand::thread_rng().gen::<u128>());
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.len() == MAX_JOBS {
+            jobs.pop_front();
+        }
+        jobs.push_back((
+            id.clone(),
+            Job {
+                url: url.to_string(),
+                status: JobStatus::Running,
+            },
+        ));
+        id
+    }
+
+    pub fn finish(&self, id: &str, status: JobStatus) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some((_, job)) = jobs.iter_mut().find(|(job_id, _)| job_id == id) {
+            job.status = status;
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter()
+            .find(|(job_id, _)| job_id == id)
+            .map(|(_, job)| job.clone())
+    }
+}
+
+/// Archives `url` into `content_dir` in the background and records how it
+/// went under job `id`.
+pub fn spawn(
+    jobs: Arc<ArchiveJobs>,
+    id: String,
+    archiver: TelegraphArchiver,
+    url: String,
+    content_dir: PathBuf,
+) {
+    rocket::tokio::spawn(async move {
+        // Reads every post, so it stays off the async workers
+        let archived = rocket::tokio::task::spawn_blocking(move || archived_sources(&content_dir))
+            .await
+            .unwrap_or_default();
+        let existing = archiver
+            .canonical_url(&url)
+            .ok()
+            .and_then(|source| archived.get(&source).cloned());
+        let status = match existing {
+            Some(post) => JobStatus::Skipped(post),
+            None => match archiver.archive_url(&url).await {
+                Ok(post) => JobStatus::Archived(post),
+                Err(e) => JobStatus::Failed(e.to_string()),
+            },
+        };
+        jobs.finish(&id, status);
+    });
+}
+
+/// Compares digests so the time taken doesn't depend on where the tokens
+/// differ. An empty `expected` token never matches.
+pub fn token_matches(expected: &str, given: &str) -> bool {
+    !expected.is_empty() && Sha256::digest(expected) == Sha256::digest(given)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs() {
+        let jobs = ArchiveJobs::default();
+        let id = jobs.start("https://telegra.ph/A-01-01");
+        assert_eq!(id.len(), 32);
+        assert_eq!(jobs.get(&id).unwrap().status, JobStatus::Running);
+
+        jobs.finish(&id, JobStatus::Archived("/a-10-18-2026".to_string()));
+        let job = jobs.get(&id).unwrap();
+        assert_eq!(job.url, "https://telegra.ph/A-01-01");
+        assert_eq!(job.status, JobStatus::Archived("/a-10-18-2026".to_string()));
+        assert!(jobs.get("unknown").is_none());
+
+        for _ in 0..MAX_JOBS {
+            jobs.start("https://telegra.ph/B-01-01");
+        }
+        assert!(jobs.get(&id).is_none());
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3cre"));
+        assert!(!token_matches("", ""));
+    }
+}
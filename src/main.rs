@@ -2,9 +2,27 @@
 extern crate rocket;
 
 mod archiver;
+mod backup;
+mod bulk;
 mod config;
+mod docx;
+mod download;
+mod encrypted;
+mod epub;
+mod export;
+mod fetch;
+mod fsck;
+mod gemini;
+mod gopher;
+mod html;
+mod import;
+mod jobs;
+mod media;
+mod mirror;
+mod negotiate;
+mod nodes;
 mod nojs;
-mod parser;
+mod password;
 mod save;
 mod template;
 
@@ -14,20 +32,33 @@ use std::thread;
 
 use chrono::{DateTime, Utc};
 use deunicode::deunicode;
+use nonograph_markup::{
+    html_attr_escape, render_markdown_with_options, render_plain_text, sanitize_text,
+};
 use rand::{thread_rng, Rng};
 use rocket::{
     fairing::{Fairing, Info, Kind},
-    http::{Header, Status},
+    http::{ContentType, Header, Status},
     request::{FromRequest, Outcome},
     response::content,
+    serde::json::Json,
     Request, Response, State,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use negotiate::{Negotiated, PostFormat};
 use std::sync::{Arc, Mutex};
 use template::TemplateEngine;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum PostKind {
+    Markdown,
+    /// Ciphertext produced in the browser; `raw_content` holds the stored
+    /// payload and nothing is rendered server-side
+    Encrypted,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Post {
     id: String,
@@ -36,6 +67,8 @@ struct Post {
     content: String,
     raw_content: String,
     created_at: DateTime<Utc>,
+    password_hash: Option<String>,
+    kind: PostKind,
 }
 
 impl Post {
@@ -45,6 +78,7 @@ impl Post {
             + self.author.len()
             + self.content.len()
             + self.raw_content.len()
+            + self.password_hash.as_ref().map_or(0, |h| h.len())
             + 64 // Rough estimate for DateTime and struct overhead
     }
 }
@@ -211,9 +245,32 @@ struct NewPost {
     title: String,
     content: String,
     alias: String,
+    password: Option<String>,
     csrf_token: String,
 }
 
+#[derive(FromForm)]
+struct UnlockForm {
+    #[field(validate = len(..=password::PASSWORD_MAX_LENGTH))]
+    password: String,
+}
+
+async fn hash_form_password(password: &Option<String>) -> Result<Option<String>, String> {
+    match password {
+        Some(password) if !password.is_empty() => {
+            if password.len() > password::PASSWORD_MAX_LENGTH {
+                return Err("password_too_long".to_string());
+            }
+            match password::hash_password_bounded(password).await {
+                Ok(hash) => Ok(Some(hash)),
+                Err(e) if e == password::PASSWORD_BUSY => Err(e),
+                Err(_) => Err("password_hash_failed".to_string()),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
 struct OnionLocationFairing {
     onion_url: String,
 }
@@ -268,6 +325,13 @@ impl<'r> FromRequest<'r> for CsrfProtected {
 }
 
 fn generate_post_id(title: &str, storage: &PostStorage) -> Result<String, String> {
+    let posts = storage.lock().unwrap();
+    allocate_post_id(title, |post_id| posts.contains_key(post_id))
+}
+
+/// Builds a `<slug>-MM-DD-YYYY` id for today, adding `-n` until `taken`
+/// says the id is free.
+fn allocate_post_id(title: &str, taken: impl Fn(&str) -> bool) -> Result<String, String> {
     let now = Utc::now();
     let date_str = now.format("%m-%d-%Y").to_string();
 
@@ -321,7 +385,6 @@ fn generate_post_id(title: &str, storage: &PostStorage) -> Result<String, String
             .collect();
 
         let fallback_slug = format!("na-{}", chars);
-        let posts = storage.lock().unwrap();
 
         for i in 0..1000 {
             let post_id = if i == 0 {
@@ -330,7 +393,7 @@ fn generate_post_id(title: &str, storage: &PostStorage) -> Result<String, String
                 format!("{}-{}-{}", fallback_slug, date_str, i)
             };
 
-            if !posts.contains_key(&post_id) {
+            if !taken(&post_id) {
                 return Ok(post_id);
             }
         }
@@ -340,8 +403,6 @@ fn generate_post_id(title: &str, storage: &PostStorage) -> Result<String, String
         );
     }
 
-    let posts = storage.lock().unwrap();
-
     // Try to find an available slot (0-999)
     for i in 0..1000 {
         let post_id = if i == 0 {
@@ -350,7 +411,7 @@ fn generate_post_id(title: &str, storage: &PostStorage) -> Result<String, String
             format!("{}-{}-{}", final_slug, date_str, i)
         };
 
-        if !posts.contains_key(&post_id) {
+        if !taken(&post_id) {
             return Ok(post_id);
         }
     }
@@ -431,7 +492,7 @@ fn is_valid_csrf_token(token: &str) -> bool {
 }
 
 #[post("/create", data = "<form>")]
-fn create_post(
+async fn create_post(
     _csrf: CsrfProtected,
     form: rocket::form::Form<NewPost>,
     storage: &State<PostStorage>,
@@ -455,20 +516,27 @@ fn create_post(
         return Ok(rocket::response::Redirect::to(error_url));
     }
 
+    let password_hash = match hash_form_password(&form.password).await {
+        Ok(hash) => hash,
+        Err(error) => return Ok(rocket::response::Redirect::to(format!("/?error={}", error))),
+    };
+
     let post_id = match generate_post_id(&form.title, storage) {
         Ok(id) => id,
         Err(_) => return Ok(rocket::response::Redirect::to("/?error=no_available_slots")),
     };
 
-    let rendered_content = parser::render_markdown_with_config(&form.content, &config);
+    let rendered_content = render_markdown_with_options(&form.content, &config.render_options());
 
     let post = Post {
         id: post_id.clone(),
-        title: parser::sanitize_text(&form.title),
-        author: parser::sanitize_text(&form.alias),
+        title: sanitize_text(&form.title),
+        author: sanitize_text(&form.alias),
         content: rendered_content,
         raw_content: form.content.clone(),
         created_at: Utc::now(),
+        password_hash,
+        kind: PostKind::Markdown,
     };
 
     let post_for_file = post.clone();
@@ -504,11 +572,11 @@ fn parse_yaml_frontmatter(file_content: &str) -> Option<(String, String, DateTim
             continue;
         }
         if let Some(value) = line.strip_prefix("title:") {
-            title = parser::sanitize_text(value.trim());
+            title = sanitize_text(value.trim());
         } else if let Some(value) = line.strip_prefix("date:") {
             date_str = value.trim().to_string();
         } else if let Some(value) = line.strip_prefix("author:") {
-            author = parser::sanitize_text(value.trim());
+            author = sanitize_text(value.trim());
         }
     }
 
@@ -530,7 +598,7 @@ fn parse_legacy_frontmatter(file_content: &str) -> Option<(String, String, DateT
     let (date_str, author) = if let Some(pipe_pos) = lines[0].find(" | ") {
         (
             lines[0][..pipe_pos].to_string(),
-            parser::sanitize_text(&lines[0][(pipe_pos + 3)..]),
+            sanitize_text(&lines[0][(pipe_pos + 3)..]),
         )
     } else {
         (lines[0].to_string(), "".to_string())
@@ -542,142 +610,419 @@ fn parse_legacy_frontmatter(file_content: &str) -> Option<(String, String, DateT
         .map(|datetime| DateTime::<Utc>::from_naive_utc_and_offset(datetime, Utc))
         .unwrap_or_else(|| Utc::now());
 
-    let title = parser::sanitize_text(lines[2].strip_prefix("# ").unwrap_or("Untitled"));
+    let title = sanitize_text(lines[2].strip_prefix("# ").unwrap_or("Untitled"));
     let raw_content = lines[3].to_string();
 
     Some((title, author, created_at, raw_content))
 }
 
-#[get("/<post_id>")]
-fn view_post(
-    post_id: &str,
-    storage: &State<PostStorage>,
-    config: &State<Config>,
-) -> Result<
-    rocket::Either<content::RawHtml<String>, content::RawText<String>>,
+type PostResponse = Result<
+    rocket::Either<content::RawHtml<String>, (ContentType, String)>,
     (
         Status,
         rocket::Either<content::RawText<String>, content::RawHtml<String>>,
     ),
-> {
-    let is_raw_request = post_id.ends_with(".md");
-    let actual_post_id = if is_raw_request {
-        post_id.strip_suffix(".md").unwrap()
+>;
+
+fn frontmatter_field(file_content: &str, key: &str) -> Option<String> {
+    let after_open = file_content.strip_prefix("---\n")?;
+    let closing_pos = after_open.find("\n---\n")?;
+    let prefix = format!("{}:", key);
+
+    after_open[..closing_pos]
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(prefix.as_str()))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn strip_frontmatter_field(file_content: &str, key: &str) -> String {
+    let after_open = match file_content.strip_prefix("---\n") {
+        Some(rest) => rest,
+        None => return file_content.to_string(),
+    };
+    let closing_pos = match after_open.find("\n---\n") {
+        Some(pos) => pos,
+        None => return file_content.to_string(),
+    };
+
+    let prefix = format!("{}:", key);
+    let mut result = String::from("---\n");
+    for line in after_open[..closing_pos].lines() {
+        if !line.trim().starts_with(prefix.as_str()) {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    result.push_str(&after_open[(closing_pos + 1)..]);
+    result
+}
+
+fn locked_response(action: &str, wrong_password: bool) -> PostResponse {
+    let engine = TemplateEngine::new("templates");
+    let mut context = HashMap::new();
+    context.insert("action".to_string(), action.to_string());
+    let error = if wrong_password {
+        "Incorrect password."
     } else {
-        &post_id
+        ""
     };
+    context.insert("error".to_string(), error.to_string());
 
-    if is_raw_request {
-        let file_path = format!("content/{}.md", actual_post_id);
-        return match std::fs::read_to_string(&file_path) {
-            Ok(raw_bytes) => Ok(rocket::Either::Right(content::RawText(raw_bytes))),
-            Err(_) => Err((
-                Status::NotFound,
-                rocket::Either::Left(content::RawText("Page not found".to_string())),
+    let html = match engine.render("locked", &context) {
+        Ok(html) => html,
+        Err(e) => format!("Template error: {}", e),
+    };
+    Err((
+        Status::Unauthorized,
+        rocket::Either::Right(content::RawHtml(html)),
+    ))
+}
+
+/// Checks `password` against the post's hash. Argon2 is slow on purpose,
+/// so verifying runs on the bounded blocking path, and overlong passwords
+/// are refused without hashing them. Fails while that path is saturated.
+async fn is_unlocked(password_hash: Option<&str>, password: Option<&str>) -> Result<bool, String> {
+    match (password_hash, password) {
+        (None, _) => Ok(true),
+        (Some(_), Some(password)) if password.len() > password::PASSWORD_MAX_LENGTH => Ok(false),
+        (Some(hash), Some(password)) => password::verify_password_bounded(password, hash).await,
+        (Some(_), None) => Ok(false),
+    }
+}
+
+/// The response for a post `password` doesn't open, if it doesn't: the
+/// password form, or a 503 while too many passwords are being checked.
+async fn unlock_failure(
+    password_hash: Option<&str>,
+    password: Option<&str>,
+    action: &str,
+) -> Option<PostResponse> {
+    match is_unlocked(password_hash, password).await {
+        Ok(true) => None,
+        Ok(false) => Some(locked_response(action, password.is_some())),
+        Err(_) => Some(Err((
+            Status::ServiceUnavailable,
+            rocket::Either::Left(content::RawText(
+                "Too many password checks right now, try again shortly".to_string(),
             )),
-        };
+        ))),
+    }
+}
+
+/// A file served for download rather than display
+#[derive(Responder)]
+struct Attachment {
+    data: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+impl Attachment {
+    fn new(filename: &str, content_type: ContentType, data: Vec<u8>) -> Self {
+        Attachment {
+            data,
+            content_type,
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        }
+    }
+}
+
+type ExportResponse = rocket::Either<PostResponse, Attachment>;
+
+#[get("/<post_id>?<download>")]
+async fn view_post(
+    post_id: &str,
+    download: Option<&str>,
+    format: PostFormat,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> Negotiated<ExportResponse> {
+    post_or_export(post_id, None, download.is_some(), format, storage, config).await
+}
+
+#[post("/<post_id>?<download>", data = "<form>")]
+async fn unlock_post(
+    post_id: &str,
+    download: Option<&str>,
+    form: rocket::form::Form<UnlockForm>,
+    format: PostFormat,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> Negotiated<ExportResponse> {
+    post_or_export(
+        post_id,
+        Some(&form.password),
+        download.is_some(),
+        format,
+        storage,
+        config,
+    )
+    .await
+}
+
+/// Sends `/<id>.epub` and `/<id>.html?download` to the exporters and
+/// everything else to `render_post`.
+async fn post_or_export(
+    post_id: &str,
+    password: Option<&str>,
+    download: bool,
+    format: PostFormat,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> Negotiated<ExportResponse> {
+    let fixed = |response| Negotiated {
+        response,
+        negotiated: false,
+    };
+    if let Some(actual_post_id) = post_id.strip_suffix(".epub") {
+        return fixed(export_epub(actual_post_id, password, storage, config).await);
+    }
+    if let Some(actual_post_id) = post_id.strip_suffix(".html").filter(|_| download) {
+        return fixed(export_html(actual_post_id, password, storage, config).await);
+    }
+    Negotiated {
+        response: rocket::Either::Left(
+            render_post(post_id, password, format, storage, config, "").await,
+        ),
+        negotiated: PostFormat::from_suffix(post_id).1.is_none(),
+    }
+}
+
+/// Loads a post for one of the exports. Encrypted posts can't be exported
+/// since the server never sees their text; locked posts ask for the password
+/// with a form that posts back to `action`.
+async fn exportable_post(
+    post_id: &str,
+    password: Option<&str>,
+    action: &str,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> Result<Post, Box<PostResponse>> {
+    let post = match load_post(post_id, storage, config) {
+        Some(post) if post.kind == PostKind::Markdown => post,
+        _ => {
+            return Err(Box::new(Err((
+                Status::NotFound,
+                rocket::Either::Right(content::RawHtml(NOT_FOUND_HTML.to_string())),
+            ))))
+        }
+    };
+    if let Some(response) = unlock_failure(post.password_hash.as_deref(), password, action).await {
+        return Err(Box::new(response));
+    }
+    Ok(post)
+}
+
+/// `/<id>.epub`: the post as an EPUB 3 book.
+async fn export_epub(
+    post_id: &str,
+    password: Option<&str>,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> ExportResponse {
+    let action = format!("/{}.epub", post_id);
+    let post = match exportable_post(post_id, password, &action, storage, config).await {
+        Ok(post) => post,
+        Err(response) => return rocket::Either::Left(*response),
+    };
+
+    let generator = std::fs::read_to_string(format!("content/{}.md", post_id))
+        .ok()
+        .and_then(|file_content| frontmatter_field(&file_content, "generator"))
+        .unwrap_or_else(|| format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+    let images = fetch::fetch_images(
+        epub::image_sources(&post.content),
+        &config.export,
+        &config.archiver.media_dir,
+    )
+    .await;
+
+    match epub::build_epub(&post, &generator, &images) {
+        Ok(data) => rocket::Either::Right(Attachment::new(
+            &format!("{}.epub", post_id),
+            ContentType::new("application", "epub+zip"),
+            data,
+        )),
+        Err(e) => {
+            eprintln!("{}", e);
+            rocket::Either::Left(Err((
+                Status::InternalServerError,
+                rocket::Either::Left(content::RawText(e)),
+            )))
+        }
     }
+}
+
+/// `/<id>.html?download`: the post page as one self-contained HTML file.
+async fn export_html(
+    post_id: &str,
+    password: Option<&str>,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> ExportResponse {
+    let action = format!("/{}.html?download", post_id);
+    let post = match exportable_post(post_id, password, &action, storage, config).await {
+        Ok(post) => post,
+        Err(response) => return rocket::Either::Left(*response),
+    };
+
+    let page = match render_post(post_id, password, PostFormat::Html, storage, config, "").await {
+        Ok(rocket::Either::Left(content::RawHtml(html))) => html,
+        other => return rocket::Either::Left(other),
+    };
+    let images = fetch::fetch_images(
+        epub::image_sources(&post.content),
+        &config.export,
+        &config.archiver.media_dir,
+    )
+    .await;
+
+    rocket::Either::Right(Attachment::new(
+        &format!("{}.html", post_id),
+        ContentType::HTML,
+        download::single_file_html(&page, &images, &config.public_base_url()).into_bytes(),
+    ))
+}
 
+/// Looks a post up in the cache, falling back to its file in `content/` and
+/// caching what it finds. Shared by the web routes and the other protocol
+/// listeners.
+fn load_post(post_id: &str, storage: &PostStorage, config: &Config) -> Option<Post> {
     // Try to load from memory first with minimal lock time
     let post_from_memory = {
         let mut posts = storage.lock().unwrap();
         // Use the non-cloning get_ref for better performance
-        if let Some(post_ref) = posts.get_ref(actual_post_id) {
+        if let Some(post_ref) = posts.get_ref(post_id) {
             Some(post_ref.clone()) // Only clone when we actually found it
         } else {
             None
         }
     };
 
-    let post = match post_from_memory {
+    match post_from_memory {
         Some(post) => Some(post),
         None => {
-            if save::post_file_exists(actual_post_id) {
-                if let Ok(file_content) =
-                    std::fs::read_to_string(format!("content/{}.md", actual_post_id))
-                {
-                    let parsed = if file_content.starts_with("---\n") {
-                        parse_yaml_frontmatter(&file_content)
-                    } else {
-                        parse_legacy_frontmatter(&file_content)
-                    };
-
-                    if let Some((title, author, created_at, raw_content)) = parsed {
-                        let new_post = Post {
-                            id: actual_post_id.to_string(),
-                            title,
-                            author,
-                            content: parser::render_markdown_with_config(&raw_content, &config),
-                            raw_content,
-                            created_at,
-                        };
-
-                        {
-                            let mut posts_write = storage.lock().unwrap();
-                            posts_write.insert(actual_post_id.to_string(), new_post.clone());
-                        }
+            if save::post_file_exists(post_id) {
+                let file_content =
+                    std::fs::read_to_string(format!("content/{}.md", post_id)).ok()?;
+                let new_post = parse_post(post_id, &file_content, config)?;
 
-                        Some(new_post)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
+                {
+                    let mut posts_write = storage.lock().unwrap();
+                    posts_write.insert(post_id.to_string(), new_post.clone());
                 }
+
+                Some(new_post)
             } else {
                 None
             }
         }
-    };
+    }
+}
 
-    match post {
-        Some(post) => {
-            let engine = TemplateEngine::new("templates");
-            let mut context = HashMap::new();
+/// Parses a stored post file and renders its markup.
+fn parse_post(post_id: &str, file_content: &str, config: &Config) -> Option<Post> {
+    let parsed = if file_content.starts_with("---\n") {
+        parse_yaml_frontmatter(file_content)
+    } else {
+        parse_legacy_frontmatter(file_content)
+    };
+    let (title, author, created_at, raw_content) = parsed?;
 
-            let rendered_content = post.content.clone();
+    let kind = if frontmatter_field(file_content, "kind").as_deref() == Some("encrypted") {
+        PostKind::Encrypted
+    } else {
+        PostKind::Markdown
+    };
+    let content = match kind {
+        PostKind::Markdown => render_markdown_with_options(&raw_content, &config.render_options()),
+        PostKind::Encrypted => String::new(),
+    };
 
-            context.insert("title".to_string(), post.title.clone());
-            context.insert("content".to_string(), rendered_content);
-            context.insert("raw_content".to_string(), post.raw_content.clone());
-            let author = if post.author.is_empty() {
-                "Anonymous".to_string()
-            } else {
-                post.author.clone()
-            };
-            context.insert("author".to_string(), author);
+    Some(Post {
+        id: post_id.to_string(),
+        title,
+        author,
+        content,
+        raw_content,
+        created_at,
+        password_hash: frontmatter_field(file_content, "password"),
+        kind,
+    })
+}
 
-            let author_display = if post.author.is_empty() {
-                "Anonymous · ".to_string()
-            } else {
-                format!("{} · ", post.author)
-            };
-            context.insert("author_display".to_string(), author_display);
+/// Renders a post in the requested format. A `.txt`, `.json` or `.md`
+/// suffix on the id overrides the negotiated `format`.
+async fn render_post(
+    post_id: &str,
+    password: Option<&str>,
+    format: PostFormat,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+    route_prefix: &str,
+) -> PostResponse {
+    let (actual_post_id, suffix_format) = PostFormat::from_suffix(post_id);
+    let format = suffix_format.unwrap_or(format);
+
+    if format == PostFormat::Markdown {
+        // `.md` links open in the browser, so they keep text/plain
+        let content_type = if suffix_format.is_some() {
+            ContentType::Plain
+        } else {
+            ContentType::new("text", "markdown").with_params(("charset", "utf-8"))
+        };
+        let file_path = format!("content/{}.md", actual_post_id);
+        return match std::fs::read_to_string(&file_path) {
+            Ok(raw_bytes) => {
+                let password_hash = frontmatter_field(&raw_bytes, "password");
+                let action = format!("{}/{}", route_prefix, post_id);
+                if let Some(response) =
+                    unlock_failure(password_hash.as_deref(), password, &action).await
+                {
+                    return response;
+                }
+                // Never hand out the hash itself, even to readers who know the password
+                Ok(rocket::Either::Right((
+                    content_type,
+                    strip_frontmatter_field(&raw_bytes, "password"),
+                )))
+            }
+            Err(_) => Err((
+                Status::NotFound,
+                rocket::Either::Left(content::RawText("Page not found".to_string())),
+            )),
+        };
+    }
 
-            context.insert(
-                "created_at".to_string(),
-                post.created_at.format("%B %d, %Y").to_string(),
-            );
-            context.insert(
-                "created_at_iso".to_string(),
-                post.created_at
-                    .format("%Y-%m-%dT00:00:00+00:00")
-                    .to_string(),
-            );
-            context.insert("post_id".to_string(), actual_post_id.to_string());
+    let post = load_post(actual_post_id, storage, config);
 
-            // OpenGraph variables
-            context.insert("url".to_string(), format!("/{}", actual_post_id));
+    match post {
+        Some(post) => {
+            let action = format!("{}/{}", route_prefix, actual_post_id);
+            if let Some(response) =
+                unlock_failure(post.password_hash.as_deref(), password, &action).await
+            {
+                return response;
+            }
 
-            let description = if post.raw_content.chars().count() > 160 {
-                let truncated: String = post.raw_content.chars().take(160).collect();
-                format!("{}...", parser::html_attr_escape(&truncated))
-            } else {
-                post.raw_content.clone()
-            };
-            context.insert("description".to_string(), description);
+            match format {
+                PostFormat::Text => {
+                    return Ok(rocket::Either::Right((
+                        ContentType::Plain,
+                        post_plain_text(&post),
+                    )))
+                }
+                PostFormat::Json => {
+                    return Ok(rocket::Either::Right((ContentType::JSON, post_json(&post))))
+                }
+                PostFormat::Html | PostFormat::Markdown => {}
+            }
 
-            match engine.render("post", &context) {
+            match post_page_html(&post) {
                 Ok(html) => Ok(rocket::Either::Left(content::RawHtml(html))),
                 Err(e) => Ok(rocket::Either::Left(content::RawHtml(format!(
                     "Template error: {}",
@@ -685,13 +1030,129 @@ fn view_post(
                 )))),
             }
         }
-        None => Err((
+        None if format == PostFormat::Html => Err((
             Status::NotFound,
             rocket::Either::Right(content::RawHtml(NOT_FOUND_HTML.to_string())),
         )),
+        None => Err((
+            Status::NotFound,
+            rocket::Either::Left(content::RawText("Page not found".to_string())),
+        )),
     }
 }
 
+/// Renders a post through post.html. Shared by the web routes and the
+/// static export.
+fn post_page_html(post: &Post) -> Result<String, String> {
+    let engine = TemplateEngine::new("templates");
+    let mut context = HashMap::new();
+
+    let is_encrypted = post.kind == PostKind::Encrypted;
+    let rendered_content = if is_encrypted {
+        ENCRYPTED_NOTICE_HTML.to_string()
+    } else {
+        post.content.clone()
+    };
+
+    context.insert("title".to_string(), post.title.clone());
+    context.insert("content".to_string(), rendered_content);
+    context.insert("encrypted".to_string(), is_encrypted.to_string());
+    context.insert("raw_content".to_string(), post.raw_content.clone());
+    let author = if post.author.is_empty() {
+        "Anonymous".to_string()
+    } else {
+        post.author.clone()
+    };
+    context.insert("author".to_string(), author);
+
+    let author_display = if post.author.is_empty() {
+        "Anonymous · ".to_string()
+    } else {
+        format!("{} · ", post.author)
+    };
+    context.insert("author_display".to_string(), author_display);
+
+    context.insert(
+        "created_at".to_string(),
+        post.created_at.format("%B %d, %Y").to_string(),
+    );
+    context.insert(
+        "created_at_iso".to_string(),
+        post.created_at
+            .format("%Y-%m-%dT00:00:00+00:00")
+            .to_string(),
+    );
+    context.insert("post_id".to_string(), post.id.clone());
+
+    // OpenGraph variables
+    context.insert("url".to_string(), format!("/{}", post.id));
+
+    // Protected posts never leak their text into link previews
+    let description = if post.password_hash.is_some() || is_encrypted {
+        String::new()
+    } else if post.raw_content.chars().count() > 160 {
+        let truncated: String = post.raw_content.chars().take(160).collect();
+        format!("{}...", html_attr_escape(&truncated))
+    } else {
+        post.raw_content.clone()
+    };
+    context.insert("description".to_string(), description);
+
+    engine.render("post", &context)
+}
+
+/// Column plain-text posts are wrapped at
+const PLAIN_TEXT_WIDTH: usize = 80;
+
+/// The post as wrapped plain text for terminals: title, byline, body and a
+/// numbered list of the links it contains.
+fn post_plain_text(post: &Post) -> String {
+    let author = if post.author.is_empty() {
+        "Anonymous"
+    } else {
+        post.author.as_str()
+    };
+    let body = match post.kind {
+        PostKind::Markdown => render_plain_text(&post.raw_content, PLAIN_TEXT_WIDTH),
+        PostKind::Encrypted => ENCRYPTED_NOTICE_TEXT.to_string(),
+    };
+
+    format!(
+        "{}\n{}\n{} · {}\n\n{}",
+        post.title,
+        "=".repeat(post.title.chars().count()),
+        author,
+        post.created_at.format("%B %d, %Y"),
+        body
+    )
+}
+
+#[derive(Serialize)]
+struct PostJson<'a> {
+    id: &'a str,
+    title: &'a str,
+    author: &'a str,
+    created_at: String,
+    encrypted: bool,
+    /// The markup as written, or the ciphertext payload for encrypted posts
+    content: &'a str,
+    html: Option<&'a str>,
+}
+
+fn post_json(post: &Post) -> String {
+    let is_encrypted = post.kind == PostKind::Encrypted;
+    let json = PostJson {
+        id: &post.id,
+        title: &post.title,
+        author: &post.author,
+        created_at: post.created_at.to_rfc3339(),
+        encrypted: is_encrypted,
+        content: &post.raw_content,
+        html: (!is_encrypted).then_some(post.content.as_str()),
+    };
+    serde_json::to_string(&json).unwrap_or_default()
+}
+
 #[get("/markup")]
 fn markup_page(
     config: &State<Config>,
@@ -744,35 +1205,61 @@ fn nojs_index(config: &State<Config>) -> content::RawHtml<String> {
 }
 
 #[get("/nojs/<post_id>")]
-fn nojs_view_post(
+async fn nojs_view_post(
     post_id: &str,
     storage: &State<PostStorage>,
     config: &State<Config>,
-) -> Result<
-    rocket::Either<content::RawHtml<String>, content::RawText<String>>,
-    (
-        Status,
-        rocket::Either<content::RawText<String>, content::RawHtml<String>>,
-    ),
-> {
-    match view_post(post_id, storage, config) {
-        Ok(rocket::Either::Left(content::RawHtml(html))) => {
-            let clean_html = nojs::strip_javascript(&html);
-            let fixed_html = clean_html
-                .replace(
-                    &format!(r#"href="/nojs/{}"#, post_id),
-                    &format!(r#"href="/{}"#, post_id),
-                )
-                .replace(r#"target="_blank">nojs</a>"#, r#"target="_blank">js</a>"#);
-            Ok(rocket::Either::Left(content::RawHtml(fixed_html)))
-        }
+) -> PostResponse {
+    nojs_post_response(
+        post_id,
+        render_post(post_id, None, PostFormat::Html, storage, config, "/nojs").await,
+    )
+}
+
+#[post("/nojs/<post_id>", data = "<form>")]
+async fn nojs_unlock_post(
+    post_id: &str,
+    form: rocket::form::Form<UnlockForm>,
+    storage: &State<PostStorage>,
+    config: &State<Config>,
+) -> PostResponse {
+    nojs_post_response(
+        post_id,
+        render_post(
+            post_id,
+            Some(&form.password),
+            PostFormat::Html,
+            storage,
+            config,
+            "/nojs",
+        )
+        .await,
+    )
+}
+
+fn nojs_post_response(post_id: &str, response: PostResponse) -> PostResponse {
+    match response {
+        Ok(rocket::Either::Left(content::RawHtml(html))) => Ok(rocket::Either::Left(
+            content::RawHtml(nojs_html(post_id, &html)),
+        )),
         Ok(rocket::Either::Right(raw_text)) => Ok(rocket::Either::Right(raw_text)),
         Err(error) => Err(error),
     }
 }
 
+/// Strips the scripts from a rendered post and points its nojs link back at
+/// the regular page.
+fn nojs_html(post_id: &str, html: &str) -> String {
+    nojs::strip_javascript(html)
+        .replace(
+            &format!(r#"href="/nojs/{}"#, post_id),
+            &format!(r#"href="/{}"#, post_id),
+        )
+        .replace(r#"target="_blank">nojs</a>"#, r#"target="_blank">js</a>"#)
+}
+
 #[post("/nojs/create", data = "<form>")]
-fn nojs_create_post(
+async fn nojs_create_post(
     _csrf: CsrfProtected,
     form: rocket::form::Form<NewPost>,
     storage: &State<PostStorage>,
@@ -796,6 +1283,16 @@ fn nojs_create_post(
         return Ok(rocket::response::Redirect::to(error_url));
     }
 
+    let password_hash = match hash_form_password(&form.password).await {
+        Ok(hash) => hash,
+        Err(error) => {
+            return Ok(rocket::response::Redirect::to(format!(
+                "/nojs/?error={}",
+                error
+            )))
+        }
+    };
+
     let post_id = match generate_post_id(&form.title, storage) {
         Ok(id) => id,
         Err(_) => {
@@ -805,30 +1302,439 @@ fn nojs_create_post(
         }
     };
 
-    let rendered_content = parser::render_markdown_with_config(&form.content, &config);
+    let rendered_content = render_markdown_with_options(&form.content, &config.render_options());
+
+    let post = Post {
+        id: post_id.clone(),
+        title: sanitize_text(&form.title),
+        author: sanitize_text(&form.alias),
+        content: rendered_content,
+        raw_content: form.content.clone(),
+        created_at: Utc::now(),
+        password_hash,
+        kind: PostKind::Markdown,
+    };
+
+    let post_for_file = post.clone();
+    {
+        let mut posts = storage.lock().unwrap();
+        posts.insert(post_id.clone(), post); // Move post here
+    }
+
+    if let Ok(tx) = file_queue.lock() {
+        if let Err(_) = tx.send(post_for_file) {
+            eprintln!("Failed to queue post for background save: {}", post_id);
+        }
+    }
+
+    Ok(rocket::response::Redirect::to(format!("/nojs/{}", post_id)))
+}
+
+const ENCRYPTED_NOTICE_TEXT: &str = "This post is end-to-end encrypted. It can only be read in a web browser with the full link, including everything after the #.\n";
+const ENCRYPTED_NOTICE_HTML: &str = r#"<p class="encrypted-notice">This post is end-to-end encrypted. It can only be read with JavaScript enabled and the full link, including everything after the <code>#</code>.</p>"#;
+
+#[derive(Serialize)]
+struct ApiError {
+    error: String,
+}
+
+fn api_error(status: Status, error: &str) -> (Status, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: error.to_string(),
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct NewEncryptedPost {
+    payload: encrypted::EncryptedPayload,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(Serialize)]
+struct EncryptedPostCreated {
+    id: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct EncryptedPostData {
+    id: String,
+    created_at: String,
+    payload: encrypted::EncryptedPayload,
+}
+
+#[post("/api/v1/encrypted", format = "json", data = "<body>")]
+fn create_encrypted_post(
+    body: Json<NewEncryptedPost>,
+    storage: &State<PostStorage>,
+    file_queue: &State<FileSaveQueue>,
+    config: &State<Config>,
+) -> Result<Json<EncryptedPostCreated>, (Status, Json<ApiError>)> {
+    if config.security.csrf_protection_enabled && !is_valid_csrf_token(&body.csrf_token) {
+        return Err(api_error(Status::Forbidden, "csrf_token_invalid"));
+    }
+
+    // base64 grows the ciphertext by a third, and AES-GCM adds a 16-byte tag
+    let max_ciphertext_len = (config.limits.content_max_length * 4) * 4 / 3 + 1024;
+    if let Err(error) = body.payload.validate(max_ciphertext_len) {
+        return Err(api_error(Status::BadRequest, &error));
+    }
+
+    // Encrypted posts carry no title, so they always get a random slug
+    let post_id = match generate_post_id("", storage) {
+        Ok(id) => id,
+        Err(_) => return Err(api_error(Status::Conflict, "no_available_slots")),
+    };
+
+    let post = Post {
+        id: post_id.clone(),
+        title: "Encrypted post".to_string(),
+        author: String::new(),
+        content: String::new(),
+        raw_content: body.payload.to_storage(),
+        created_at: Utc::now(),
+        password_hash: None,
+        kind: PostKind::Encrypted,
+    };
+
+    let post_for_file = post.clone();
+    {
+        let mut posts = storage.lock().unwrap();
+        posts.insert(post_id.clone(), post);
+    }
+
+    if let Ok(tx) = file_queue.lock() {
+        if tx.send(post_for_file).is_err() {
+            eprintln!("Failed to queue post for background save: {}", post_id);
+        }
+    }
+
+    Ok(Json(EncryptedPostCreated {
+        url: format!("/{}", post_id),
+        id: post_id,
+    }))
+}
+
+#[get("/api/v1/encrypted/<post_id>")]
+fn encrypted_post_data(
+    post_id: &str,
+    storage: &State<PostStorage>,
+) -> Result<Json<EncryptedPostData>, (Status, Json<ApiError>)> {
+    let cached = {
+        let mut posts = storage.lock().unwrap();
+        posts.get_ref(post_id).cloned()
+    };
+
+    let (raw_content, created_at) = match cached {
+        Some(post) if post.kind == PostKind::Encrypted => (post.raw_content, post.created_at),
+        Some(_) => return Err(api_error(Status::NotFound, "not_found")),
+        None => {
+            let file_content = std::fs::read_to_string(format!("content/{}.md", post_id))
+                .map_err(|_| api_error(Status::NotFound, "not_found"))?;
+            if frontmatter_field(&file_content, "kind").as_deref() != Some("encrypted") {
+                return Err(api_error(Status::NotFound, "not_found"));
+            }
+            let (_, _, created_at, raw_content) = parse_yaml_frontmatter(&file_content)
+                .ok_or_else(|| api_error(Status::NotFound, "not_found"))?;
+            (raw_content, created_at)
+        }
+    };
+
+    let payload = encrypted::EncryptedPayload::from_storage(&raw_content)
+        .ok_or_else(|| api_error(Status::InternalServerError, "corrupt_payload"))?;
+
+    Ok(Json(EncryptedPostData {
+        id: post_id.to_string(),
+        created_at: created_at.format("%Y-%m-%dT00:00:00+00:00").to_string(),
+        payload,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ConvertHtml {
+    html: String,
+}
+
+#[derive(Serialize)]
+struct ConvertedMarkup {
+    markup: String,
+}
+
+/// Turns HTML pasted into the editor into markup, so formatting copied from
+/// web pages and documents survives.
+#[post("/api/v1/convert/html", format = "json", data = "<body>")]
+fn convert_html(body: Json<ConvertHtml>) -> Json<ConvertedMarkup> {
+    Json(ConvertedMarkup {
+        markup: html::paste_to_markup(&body.html),
+    })
+}
+
+#[derive(FromForm)]
+struct DocxUpload<'r> {
+    file: rocket::fs::TempFile<'r>,
+    /// Overrides the document's own title
+    #[field(default = "")]
+    title: String,
+    #[field(default = "")]
+    alias: String,
+    password: Option<String>,
+    #[field(default = "")]
+    csrf_token: String,
+}
+
+#[derive(Serialize)]
+struct DocxImported {
+    id: String,
+    url: String,
+    warnings: Vec<String>,
+}
+
+#[post("/api/v1/import/docx", data = "<form>")]
+async fn import_docx(
+    form: rocket::form::Form<DocxUpload<'_>>,
+    storage: &State<PostStorage>,
+    file_queue: &State<FileSaveQueue>,
+    config: &State<Config>,
+) -> Result<Json<DocxImported>, (Status, Json<ApiError>)> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    if config.security.csrf_protection_enabled && !is_valid_csrf_token(&form.csrf_token) {
+        return Err(api_error(Status::Forbidden, "csrf_token_invalid"));
+    }
+
+    let mut data = Vec::new();
+    let read = match form.file.open().await {
+        Ok(mut file) => file.read_to_end(&mut data).await,
+        Err(e) => Err(e),
+    };
+    if read.is_err() {
+        return Err(api_error(Status::BadRequest, "upload_failed"));
+    }
+    // Parsing is CPU-bound, so it stays off the async workers
+    let document = rocket::tokio::task::spawn_blocking(move || docx::convert(&data))
+        .await
+        .map_err(|_| api_error(Status::InternalServerError, "import_failed"))?
+        .map_err(|_| api_error(Status::BadRequest, "invalid_docx"))?;
+
+    let title = [
+        Some(form.title.trim().to_string()),
+        document.title,
+        form.file.name().map(str::to_string),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|title| !title.is_empty())
+    .unwrap_or_default();
+    let alias = match form.alias.trim() {
+        "" => document.author.unwrap_or_default(),
+        alias => alias.to_string(),
+    };
+    let alias_to_validate = if alias.is_empty() {
+        None
+    } else {
+        Some(alias.as_str())
+    };
+    if let Err(error) = config.validate_post(&title, &document.markup, alias_to_validate) {
+        return Err(api_error(Status::BadRequest, &error));
+    }
+
+    let password_hash = hash_form_password(&form.password)
+        .await
+        .map_err(|error| api_error(Status::BadRequest, &error))?;
+    let post_id = match generate_post_id(&title, storage) {
+        Ok(id) => id,
+        Err(_) => return Err(api_error(Status::Conflict, "no_available_slots")),
+    };
+
+    let post = Post {
+        id: post_id.clone(),
+        title: sanitize_text(&title),
+        author: sanitize_text(&alias),
+        content: render_markdown_with_options(&document.markup, &config.render_options()),
+        raw_content: document.markup,
+        created_at: Utc::now(),
+        password_hash,
+        kind: PostKind::Markdown,
+    };
+
+    let post_for_file = post.clone();
+    {
+        let mut posts = storage.lock().unwrap();
+        posts.insert(post_id.clone(), post);
+    }
+
+    if let Ok(tx) = file_queue.lock() {
+        if tx.send(post_for_file).is_err() {
+            eprintln!("Failed to queue post for background save: {}", post_id);
+        }
+    }
+
+    Ok(Json(DocxImported {
+        url: format!("/{}", post_id),
+        id: post_id,
+        warnings: document.warnings,
+    }))
+}
+
+#[derive(FromForm)]
+struct ArchiveForm {
+    url: String,
+    token: String,
+    with_media: bool,
+}
+
+fn render_archive_form(url: &str, error: &str) -> content::RawHtml<String> {
+    let engine = TemplateEngine::new("templates");
+    let mut context = HashMap::new();
+    context.insert("url".to_string(), url.to_string());
+    context.insert("error".to_string(), error.to_string());
+
+    content::RawHtml(match engine.render("archive", &context) {
+        Ok(html) => html,
+        Err(e) => format!("Template error: {}", e),
+    })
+}
+
+/// Lets operators archive Telegraph pages from the browser. Off unless
+/// enabled with a token in `[archiver]`.
+#[get("/archive")]
+fn archive_page(config: &State<Config>) -> Option<content::RawHtml<String>> {
+    if !config.archiver.web_archiving_enabled() {
+        return None;
+    }
+    Some(render_archive_form("", ""))
+}
+
+#[post("/archive", data = "<form>")]
+fn start_archive(
+    form: rocket::form::Form<ArchiveForm>,
+    config: &State<Config>,
+    jobs: &State<Arc<jobs::ArchiveJobs>>,
+) -> Option<Result<rocket::response::Redirect, (Status, content::RawHtml<String>)>> {
+    if !config.archiver.web_archiving_enabled() {
+        return None;
+    }
+    if !jobs::token_matches(&config.archiver.web_token, &form.token) {
+        return Some(Err((
+            Status::Forbidden,
+            render_archive_form(&form.url, "Incorrect token."),
+        )));
+    }
+
+    let mut archiver = archiver::TelegraphArchiver::new(&config.archiver);
+    if form.with_media {
+        archiver = archiver.with_media(media::MediaStore::new(&config.archiver));
+    }
+    if let Err(e) = archiver.canonical_url(&form.url) {
+        return Some(Err((
+            Status::BadRequest,
+            render_archive_form(&form.url, &format!("{}.", e)),
+        )));
+    }
+
+    let id = jobs.start(&form.url);
+    jobs::spawn(
+        Arc::clone(jobs),
+        id.clone(),
+        archiver,
+        form.url.clone(),
+        std::path::PathBuf::from("content"),
+    );
+    Some(Ok(rocket::response::Redirect::to(format!(
+        "/archive/{}",
+        id
+    ))))
+}
+
+#[get("/archive/<id>")]
+fn archive_job(
+    id: &str,
+    config: &State<Config>,
+    jobs: &State<Arc<jobs::ArchiveJobs>>,
+) -> Option<content::RawHtml<String>> {
+    if !config.archiver.web_archiving_enabled() {
+        return None;
+    }
+    let job = jobs.get(id)?;
+
+    let link = |post: &str| {
+        let url = html_attr_escape(&format!("{}{}", config.public_base_url(), post));
+        format!("<a href=\"{}\">{}</a>", url, url)
+    };
+    let (title, refresh, status) = match &job.status {
+        jobs::JobStatus::Running => (
+            "Archiving…",
+            "3",
+            "<p>This page updates itself until the archive is ready.</p>".to_string(),
+        ),
+        jobs::JobStatus::Archived(post) => ("Archived", "", format!("<p>{}</p>", link(post))),
+        jobs::JobStatus::Skipped(post) => {
+            ("Already archived", "", format!("<p>{}</p>", link(post)))
+        }
+        jobs::JobStatus::Failed(error) => (
+            "Archiving failed",
+            "",
+            format!("<p class=\"error\">{}</p>", html_attr_escape(error)),
+        ),
+    };
+
+    let engine = TemplateEngine::new("templates");
+    let mut context = HashMap::new();
+    context.insert("title".to_string(), title.to_string());
+    context.insert("refresh".to_string(), refresh.to_string());
+    context.insert("url".to_string(), job.url);
+    context.insert("content".to_string(), status);
+
+    Some(content::RawHtml(
+        match engine.render("archive_job", &context) {
+            Ok(html) => html,
+            Err(e) => format!("Template error: {}", e),
+        },
+    ))
+}
+
+#[get("/writemark.js")]
+fn writemark_js() -> Option<(rocket::http::ContentType, String)> {
+    std::fs::read_to_string("templates/writemark.js")
+        .ok()
+        .map(|script| (rocket::http::ContentType::JavaScript, script))
+}
 
-    let post = Post {
-        id: post_id.clone(),
-        title: parser::sanitize_text(&form.title),
-        author: parser::sanitize_text(&form.alias),
-        content: rendered_content,
-        raw_content: form.content.clone(),
-        created_at: Utc::now(),
+/// The markup renderer compiled to WebAssembly (`make wasm`). Missing until
+/// it has been built, in which case the editor keeps its built-in preview.
+#[get("/wasm/<file>")]
+fn markup_wasm(file: &str) -> Option<(rocket::http::ContentType, Vec<u8>)> {
+    let content_type = match file {
+        "nonograph_wasm.js" => rocket::http::ContentType::JavaScript,
+        "nonograph_wasm_bg.wasm" => rocket::http::ContentType::WASM,
+        _ => return None,
     };
 
-    let post_for_file = post.clone();
-    {
-        let mut posts = storage.lock().unwrap();
-        posts.insert(post_id.clone(), post); // Move post here
-    }
+    std::fs::read(format!("templates/wasm/{}", file))
+        .ok()
+        .map(|bytes| (content_type, bytes))
+}
 
-    if let Ok(tx) = file_queue.lock() {
-        if let Err(_) = tx.send(post_for_file) {
-            eprintln!("Failed to queue post for background save: {}", post_id);
-        }
-    }
+/// The instance's rendering settings, handed to the WebAssembly renderer so
+/// the preview matches the published post.
+#[get("/wasm/config.json")]
+fn markup_wasm_config(config: &State<Config>) -> Json<nonograph_markup::RenderOptions> {
+    Json(config.render_options())
+}
 
-    Ok(rocket::response::Redirect::to(format!("/nojs/{}", post_id)))
+/// Images and videos copied in by `archive --with-media`
+#[get("/media/<file>")]
+async fn archived_media(file: &str, config: &State<Config>) -> Option<media::MediaFile> {
+    let content_type = media::content_type_for(file)?;
+    media::MediaFile::open(
+        &std::path::Path::new(&config.archiver.media_dir).join(file),
+        content_type,
+    )
+    .await
 }
 
 const NOT_FOUND_HTML: &str = r#"<!doctype html>
@@ -855,6 +1761,21 @@ const NOT_FOUND_HTML: &str = r#"<!doctype html>
 </body>
 </html>"#;
 
+/// Pages in `content/` that have their own routes rather than being posts,
+/// in menu order
+const STATIC_PAGES: &[&str] = &["about", "markup", "legal", "api"];
+
+/// Reads one of the pages in `content/` (about, markup, ...) and returns its
+/// title, author, date and raw markup.
+fn load_static_page(page_name: &str) -> Option<(String, String, DateTime<Utc>, String)> {
+    let file_content = std::fs::read_to_string(format!("content/{}.md", page_name)).ok()?;
+    if file_content.starts_with("---\n") {
+        parse_yaml_frontmatter(&file_content)
+    } else {
+        parse_legacy_frontmatter(&file_content)
+    }
+}
+
 fn serve_static_page(
     page_name: &str,
     config: &State<Config>,
@@ -870,27 +1791,7 @@ fn serve_static_page(
             };
 
             if let Some((title, author, created_at, raw_content)) = parsed {
-                let rendered_content = parser::render_markdown_with_config(&raw_content, &config);
-
-                let engine = TemplateEngine::new("templates");
-                let mut context = HashMap::new();
-                context.insert("title".to_string(), title);
-                context.insert("content".to_string(), rendered_content);
-                context.insert(
-                    "created_at".to_string(),
-                    created_at.format("%B %d, %Y").to_string(),
-                );
-                context.insert("author".to_string(), author);
-                context.insert("author_display".to_string(), String::new());
-                context.insert(
-                    "created_at_iso".to_string(),
-                    created_at.format("%Y-%m-%dT00:00:00+00:00").to_string(),
-                );
-                context.insert("url".to_string(), format!("/{}", page_name));
-                context.insert("description".to_string(), String::new());
-                context.insert("post_id".to_string(), page_name.to_string());
-
-                match engine.render("post", &context) {
+                match static_page_html(page_name, title, author, created_at, &raw_content, config) {
                     Ok(html) => Ok(content::RawHtml(html)),
                     Err(e) => Ok(content::RawHtml(format!("Template error: {}", e))),
                 }
@@ -908,6 +1809,39 @@ fn serve_static_page(
     }
 }
 
+/// Renders one of the pages in `content/` through post.html.
+fn static_page_html(
+    page_name: &str,
+    title: String,
+    author: String,
+    created_at: DateTime<Utc>,
+    raw_content: &str,
+    config: &Config,
+) -> Result<String, String> {
+    let rendered_content = render_markdown_with_options(raw_content, &config.render_options());
+
+    let engine = TemplateEngine::new("templates");
+    let mut context = HashMap::new();
+    context.insert("title".to_string(), title);
+    context.insert("content".to_string(), rendered_content);
+    context.insert(
+        "created_at".to_string(),
+        created_at.format("%B %d, %Y").to_string(),
+    );
+    context.insert("author".to_string(), author);
+    context.insert("author_display".to_string(), String::new());
+    context.insert(
+        "created_at_iso".to_string(),
+        created_at.format("%Y-%m-%dT00:00:00+00:00").to_string(),
+    );
+    context.insert("url".to_string(), format!("/{}", page_name));
+    context.insert("description".to_string(), String::new());
+    context.insert("post_id".to_string(), page_name.to_string());
+    context.insert("encrypted".to_string(), "false".to_string());
+
+    engine.render("post", &context)
+}
+
 fn start_cache_purge_worker(storage: PostStorage, interval_mins: u64) {
     thread::spawn(move || loop {
         thread::sleep(std::time::Duration::from_secs(interval_mins * 60));
@@ -935,18 +1869,76 @@ async fn main() -> Result<(), rocket::Error> {
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() > 1 && args[1] == "archive" {
-        if args.len() < 3 {
-            eprintln!("Usage: cargo run archive <telegraph_url>");
-            std::process::exit(1);
+        let mut with_media = false;
+        let mut url_file = None;
+        let mut access_token = None;
+        let mut url = None;
+        let mut rest = args[2..].iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--with-media" => with_media = true,
+                "--file" => url_file = rest.next(),
+                "--account" => access_token = rest.next(),
+                _ => url = Some(arg),
+            }
+        }
+
+        let config = Config::load_with_logging();
+        let mut archiver = archiver::TelegraphArchiver::new(&config.archiver);
+        if with_media {
+            archiver = archiver.with_media(media::MediaStore::new(&config.archiver));
+        }
+
+        let urls = if let Some(path) = url_file {
+            match std::fs::read_to_string(path) {
+                Ok(list) => Some(bulk::read_url_list(&list)),
+                Err(e) => {
+                    eprintln!("Error reading {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(token) = access_token {
+            match archiver.fetch_account_pages(token).await {
+                Ok(urls) => Some(urls),
+                Err(e) => {
+                    eprintln!("Error listing account pages: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(urls) = urls {
+            let results = bulk::archive_all(
+                &archiver,
+                urls,
+                std::path::Path::new("content"),
+                std::path::Path::new(bulk::PROGRESS_FILE),
+            )
+            .await;
+            print!("{}", bulk::summary_table(&results));
+
+            if results
+                .iter()
+                .any(|(_, outcome)| matches!(outcome, bulk::Outcome::Failed { .. }))
+            {
+                std::process::exit(1);
+            }
+            return Ok(());
         }
 
-        let url = &args[2];
-        let archiver = archiver::TelegraphArchiver::new();
+        let Some(url) = url else {
+            eprintln!("Usage: nonograph archive [--with-media] <telegraph_url>");
+            eprintln!("       nonograph archive [--with-media] --file <urls.txt>");
+            eprintln!("       nonograph archive [--with-media] --account <access_token>");
+            std::process::exit(1);
+        };
 
         match archiver.archive_url(url).await {
             Ok(nonograph_url) => {
                 println!("Successfully archived Telegraph page!");
-                println!("View at: http://localhost:8009{}", nonograph_url);
+                println!("View at: {}{}", config.public_base_url(), nonograph_url);
             }
             Err(e) => {
                 eprintln!("Error archiving page: {}", e);
@@ -957,6 +1949,301 @@ async fn main() -> Result<(), rocket::Error> {
         return Ok(());
     }
 
+    if args.len() > 1 && args[1] == "mirror" {
+        let config = Config::load_with_logging();
+        let results = if args.get(2).map(String::as_str) == Some("--sync") {
+            mirror::sync(&config.mirror, std::path::Path::new(".")).await
+        } else if args.len() > 3 {
+            match mirror::mirror_posts(
+                &args[2],
+                &args[3..],
+                &config.mirror.proxy,
+                std::path::Path::new("."),
+            )
+            .await
+            {
+                Ok(results) => results,
+                Err(e) => {
+                    eprintln!("Error mirroring from {}: {}", args[2], e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            eprintln!("Usage: nonograph mirror <base-url> <id...>");
+            eprintln!("       nonograph mirror --sync");
+            std::process::exit(1);
+        };
+
+        let mut failed = 0;
+        for (post, outcome) in &results {
+            match outcome {
+                mirror::Outcome::Mirrored(local) => println!("{}: mirrored as {}", post, local),
+                mirror::Outcome::Skipped(local) => {
+                    println!("{}: already mirrored as {}", post, local)
+                }
+                mirror::Outcome::Failed(e) => {
+                    failed += 1;
+                    eprintln!("{}: {}", post, e);
+                }
+            }
+        }
+        println!("{} posts, {} failed", results.len(), failed);
+
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "import-html" {
+        if args.len() < 3 {
+            eprintln!("Usage: nonograph import-html <file-or-dir>");
+            std::process::exit(1);
+        }
+
+        let results =
+            match import::import_html(std::path::Path::new(&args[2]), std::path::Path::new(".")) {
+                Ok(results) => results,
+                Err(e) => {
+                    eprintln!("Error importing {}: {}", args[2], e);
+                    std::process::exit(1);
+                }
+            };
+
+        let mut failed = 0;
+        for (file, outcome) in &results {
+            match outcome {
+                import::Outcome::Imported(post) => {
+                    println!("{}: imported as {}", file.display(), post)
+                }
+                import::Outcome::Skipped(post) => {
+                    println!("{}: already imported as {}", file.display(), post)
+                }
+                import::Outcome::Failed(e) => {
+                    failed += 1;
+                    eprintln!("{}: {}", file.display(), e);
+                }
+            }
+        }
+        println!("{} files, {} failed", results.len(), failed);
+
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "import-docx" {
+        if args.len() < 3 {
+            eprintln!("Usage: nonograph import-docx <file>");
+            std::process::exit(1);
+        }
+
+        match import::import_docx(std::path::Path::new(&args[2]), std::path::Path::new(".")) {
+            Ok((import::Outcome::Imported(post), warnings)) => {
+                println!("{}: imported as {}", args[2], post);
+                for warning in warnings {
+                    eprintln!("warning: {}", warning);
+                }
+            }
+            Ok((import::Outcome::Skipped(post), _)) => {
+                println!("{}: already imported as {}", args[2], post)
+            }
+            Ok((import::Outcome::Failed(e), _)) | Err(e) => {
+                eprintln!("Error importing {}: {}", args[2], e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "import" {
+        let mut format = None;
+        let mut site_url = None;
+        let mut map_file = None;
+        let mut file = None;
+        let mut rest = args[2..].iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--format" => {
+                    format = rest
+                        .next()
+                        .and_then(|name| import::ExportFormat::parse(name))
+                }
+                "--url" => site_url = rest.next(),
+                "--map" => map_file = rest.next(),
+                _ => file = Some(arg),
+            }
+        }
+
+        let (Some(format), Some(file)) = (format, file) else {
+            eprintln!("Usage: nonograph import --format wxr|ghost [--url <old site>] [--map <redirects.csv>] <file>");
+            std::process::exit(1);
+        };
+        let site_url = site_url.map(|url| {
+            url::Url::parse(url).unwrap_or_else(|e| {
+                eprintln!("Invalid --url {}: {}", url, e);
+                std::process::exit(1);
+            })
+        });
+        let export = match std::fs::read_to_string(file) {
+            Ok(export) => export,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", file, e);
+                std::process::exit(1);
+            }
+        };
+
+        let results = match import::import_export(
+            format,
+            &export,
+            site_url.as_ref(),
+            std::path::Path::new("."),
+        ) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("Error importing {}: {}", file, e);
+                std::process::exit(1);
+            }
+        };
+        print!("{}", import::mapping_report(&results));
+
+        if let Some(map_file) = map_file {
+            if let Err(e) = std::fs::write(map_file, import::mapping_csv(&results)) {
+                eprintln!("Error writing {}: {}", map_file, e);
+                std::process::exit(1);
+            }
+        }
+        if results
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, import::Outcome::Failed(_)))
+        {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "export-static" {
+        if args.len() < 3 {
+            eprintln!("Usage: nonograph export-static <outdir>");
+            std::process::exit(1);
+        }
+
+        let config = Config::load_with_logging();
+        let outdir = std::path::Path::new(&args[2]);
+
+        match export::export_static(std::path::Path::new("content"), outdir, &config) {
+            Ok(summary) => {
+                println!(
+                    "Exported to {}: {} rendered, {} unchanged, {} skipped, {} removed",
+                    outdir.display(),
+                    summary.rendered,
+                    summary.unchanged,
+                    summary.skipped,
+                    summary.removed
+                );
+            }
+            Err(e) => {
+                eprintln!("Error exporting site: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "backup" {
+        if args.len() < 3 {
+            eprintln!("Usage: nonograph backup <file>");
+            std::process::exit(1);
+        }
+
+        let config = Config::load_with_logging();
+        let file = std::path::Path::new(&args[2]);
+
+        match backup::backup(std::path::Path::new("."), &config, file) {
+            Ok(posts) => println!("Backed up {} posts to {}", posts, file.display()),
+            Err(e) => {
+                eprintln!("Error writing backup: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "restore" {
+        if args.len() < 3 {
+            eprintln!("Usage: nonograph restore <file>");
+            std::process::exit(1);
+        }
+
+        match backup::restore(std::path::Path::new(&args[2]), std::path::Path::new(".")) {
+            Ok(report) => {
+                println!(
+                    "Restored {} files, {} already up to date",
+                    report.restored.len(),
+                    report.identical.len()
+                );
+                if !report.conflicts.is_empty() {
+                    eprintln!("Not overwritten, these differ from the backup:");
+                    for path in &report.conflicts {
+                        eprintln!("  {}", path);
+                    }
+                    std::process::exit(2);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error restoring backup: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        return Ok(());
+    }
+
+    if args.len() > 1 && args[1] == "fsck" {
+        let fix = args[2..].iter().any(|arg| arg == "--fix");
+        let json = args[2..].iter().any(|arg| arg == "--json");
+
+        let report = match fsck::fsck(std::path::Path::new("."), fix) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error checking content: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+        } else {
+            for issue in &report.issues {
+                let status = if issue.fixed {
+                    "fixed"
+                } else if issue.fixable {
+                    "fixable with --fix"
+                } else {
+                    "needs manual attention"
+                };
+                println!("{}: {:?} ({})", issue.file, issue.problem, status);
+            }
+            println!(
+                "Scanned {} files, {} problems, {} unresolved",
+                report.scanned,
+                report.issues.len(),
+                report.unresolved()
+            );
+        }
+
+        if report.unresolved() > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Default behavior - launch web server
     let _rocket = rocket().launch().await?;
     Ok(())
@@ -969,12 +2256,23 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
 
     let limits = Limits::default()
         .limit("form", config.form_data_limit_bytes().bytes())
-        .limit("data-form", config.form_data_limit_bytes().bytes())
-        .limit("string", config.form_data_limit_bytes().bytes());
+        // Uploads arrive as multipart forms, with a few fields beside the file
+        .limit(
+            "data-form",
+            (config.form_data_limit_bytes() + config.upload_limit_bytes()).bytes(),
+        )
+        .limit("file", config.upload_limit_bytes().bytes())
+        .limit("string", config.form_data_limit_bytes().bytes())
+        .limit("json", config.form_data_limit_bytes().bytes());
 
     let storage = Arc::new(Mutex::new(PostCache::new(config.cache.max_cache_size_mb)));
     start_cache_purge_worker(Arc::clone(&storage), config.cache.cache_purge_interval_mins);
     let file_save_sender = start_file_save_worker();
+    let gemini_config = config.clone();
+    let gemini_storage = Arc::clone(&storage);
+    let gopher_config = config.clone();
+    let gopher_storage = Arc::clone(&storage);
+    let mirror_config = config.mirror.clone();
 
     let onion_url = config.resolve_onion_url();
     match &onion_url {
@@ -995,6 +2293,7 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
         })
         .manage(storage)
         .manage(FileSaveQueue::new(file_save_sender))
+        .manage(Arc::new(jobs::ArchiveJobs::default()))
         .manage(config)
         .mount(
             "/",
@@ -1002,13 +2301,26 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
                 index,
                 create_post,
                 view_post,
+                unlock_post,
                 markup_page,
                 legal_page,
                 about_page,
                 api_page,
                 robots_txt,
+                writemark_js,
+                markup_wasm,
+                markup_wasm_config,
+                archived_media,
+                create_encrypted_post,
+                encrypted_post_data,
+                convert_html,
+                import_docx,
+                archive_page,
+                start_archive,
+                archive_job,
                 nojs_index,
                 nojs_view_post,
+                nojs_unlock_post,
                 nojs_create_post
             ],
         );
@@ -1017,6 +2329,47 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
         rocket = rocket.attach(OnionLocationFairing { onion_url: url });
     }
 
+    if gemini_config.gemini.enabled {
+        rocket = rocket.attach(rocket::fairing::AdHoc::on_liftoff(
+            "Gemini listener",
+            move |_| {
+                Box::pin(async move {
+                    rocket::tokio::spawn(async move {
+                        if let Err(e) = gemini::serve(gemini_config, gemini_storage).await {
+                            eprintln!("Gemini listener stopped: {}", e);
+                        }
+                    });
+                })
+            },
+        ));
+    }
+
+    if gopher_config.gopher.enabled {
+        rocket = rocket.attach(rocket::fairing::AdHoc::on_liftoff(
+            "Gopher listener",
+            move |_| {
+                Box::pin(async move {
+                    rocket::tokio::spawn(async move {
+                        if let Err(e) = gopher::serve(gopher_config, gopher_storage).await {
+                            eprintln!("Gopher listener stopped: {}", e);
+                        }
+                    });
+                })
+            },
+        ));
+    }
+
+    if mirror_config.sync_interval_mins > 0 && !mirror_config.peers.is_empty() {
+        rocket = rocket.attach(rocket::fairing::AdHoc::on_liftoff(
+            "Mirror sync",
+            move |_| {
+                Box::pin(async move {
+                    rocket::tokio::spawn(mirror::sync_periodically(mirror_config));
+                })
+            },
+        ));
+    }
+
     rocket
 }
 
@@ -1024,6 +2377,57 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_frontmatter_password_field() {
+        let file = "---\ntitle: Locked\ndate: 2024-01-01\npassword: $argon2id$v=19$abc\ngenerator: nonograph v0.4.2\n---\n\nBody text\n";
+
+        assert_eq!(
+            frontmatter_field(file, "password"),
+            Some("$argon2id$v=19$abc".to_string())
+        );
+        assert_eq!(frontmatter_field(file, "author"), None);
+        assert_eq!(frontmatter_field("No frontmatter", "password"), None);
+
+        let stripped = strip_frontmatter_field(file, "password");
+        assert!(!stripped.contains("argon2"));
+        assert!(stripped.starts_with("---\ntitle: Locked\ndate: 2024-01-01\ngenerator:"));
+        assert!(stripped.ends_with("---\n\nBody text\n"));
+        assert_eq!(parse_yaml_frontmatter(&stripped).unwrap().3, "Body text\n");
+    }
+
+    #[rocket::async_test]
+    #[serial_test::serial(argon2)]
+    async fn test_is_unlocked() {
+        let hash = password::hash_password("open sesame").unwrap();
+
+        assert_eq!(is_unlocked(None, None).await, Ok(true));
+        assert_eq!(is_unlocked(None, Some("anything")).await, Ok(true));
+        assert_eq!(is_unlocked(Some(&hash), None).await, Ok(false));
+        assert_eq!(is_unlocked(Some(&hash), Some("wrong")).await, Ok(false));
+        assert_eq!(
+            is_unlocked(Some(&hash), Some("open sesame")).await,
+            Ok(true)
+        );
+        let long = "x".repeat(password::PASSWORD_MAX_LENGTH + 1);
+        assert_eq!(is_unlocked(Some(&hash), Some(&long)).await, Ok(false));
+    }
+
+    #[rocket::async_test]
+    #[serial_test::serial(argon2)]
+    async fn test_hash_form_password() {
+        assert_eq!(hash_form_password(&None).await, Ok(None));
+        assert_eq!(hash_form_password(&Some(String::new())).await, Ok(None));
+        assert!(hash_form_password(&Some("secret".to_string()))
+            .await
+            .unwrap()
+            .unwrap()
+            .starts_with("$argon2id$"));
+        assert_eq!(
+            hash_form_password(&Some("x".repeat(200))).await,
+            Err("password_too_long".to_string())
+        );
+    }
+
     #[test]
     fn test_post_id_generation() {
         let storage = Arc::new(Mutex::new(PostCache::new(128)));
@@ -1040,7 +2444,7 @@ mod tests {
     #[test]
     fn test_markdown_rendering_basic() {
         let input = "This is *bold* text and **italic** text.";
-        let output = parser::render_markdown(input);
+        let output = nonograph_markup::render_markdown(input);
         // Basic test - the actual implementation needs proper regex
         assert!(output.contains("bold"));
         assert!(output.contains("italic"));
@@ -1110,7 +2514,7 @@ mod tests {
     #[test]
     fn test_markdown_bold_formatting() {
         let input = "This is **bold** text and more **bold text**.";
-        let output = parser::render_markdown(input);
+        let output = nonograph_markup::render_markdown(input);
         assert!(output.contains("<strong>bold</strong>"));
         assert!(output.contains("<strong>bold text</strong>"));
     }
@@ -1118,7 +2522,7 @@ mod tests {
     #[test]
     fn test_markdown_code_formatting() {
         let input = "Here is `inline code` and more `code`.";
-        let output = parser::render_markdown(input);
+        let output = nonograph_markup::render_markdown(input);
         // Note: Our current simple implementation doesn't handle this yet
         // This test documents expected behavior
         assert!(output.contains("inline code"));
@@ -1135,19 +2539,19 @@ mod tests {
     #[test]
     fn test_title_and_author_sanitization() {
         let malicious_title = "<script>alert('xss')</script>Safe Title";
-        let sanitized_title = parser::sanitize_text(&malicious_title);
+        let sanitized_title = nonograph_markup::sanitize_text(&malicious_title);
         assert_eq!(sanitized_title, "Safe Title");
 
         let malicious_author = "<b>Bold</b><script>alert('xss')</script>John Doe";
-        let sanitized_author = parser::sanitize_text(&malicious_author);
+        let sanitized_author = nonograph_markup::sanitize_text(&malicious_author);
         assert_eq!(sanitized_author, "BoldJohn Doe");
 
         let clean_text = "Normal Title";
-        let sanitized_clean = parser::sanitize_text(&clean_text);
+        let sanitized_clean = nonograph_markup::sanitize_text(&clean_text);
         assert_eq!(sanitized_clean, "Normal Title");
 
         let various_tags = "<h1>Title</h1><p>Content</p><script>alert('xss')</script>";
-        let sanitized_various = parser::sanitize_text(&various_tags);
+        let sanitized_various = nonograph_markup::sanitize_text(&various_tags);
         assert_eq!(sanitized_various, "TitleContent");
     }
 
@@ -1207,7 +2611,7 @@ mod tests {
         ];
 
         for (i, xss_payload) in xss_test_cases.iter().enumerate() {
-            let sanitized = parser::sanitize_text(xss_payload);
+            let sanitized = nonograph_markup::sanitize_text(xss_payload);
             let lower = sanitized.to_lowercase();
             assert!(
                 !lower.contains("<script"),
@@ -1266,11 +2670,11 @@ mod tests {
         }
 
         let mixed_payload = "Hello <script>alert('XSS')</script> World";
-        let sanitized_mixed = parser::sanitize_text(&mixed_payload);
+        let sanitized_mixed = nonograph_markup::sanitize_text(&mixed_payload);
         assert_eq!(sanitized_mixed, "Hello  World");
 
         let title_with_xss = "My Blog Post <img src=x onerror=alert(1)>";
-        let sanitized_title = parser::sanitize_text(&title_with_xss);
+        let sanitized_title = nonograph_markup::sanitize_text(&title_with_xss);
         assert_eq!(sanitized_title, "My Blog Post ");
 
         let dangerous_payloads = [
@@ -1286,7 +2690,7 @@ mod tests {
         ];
 
         for (payload, expected) in dangerous_payloads {
-            let result = parser::sanitize_text(payload);
+            let result = nonograph_markup::sanitize_text(payload);
             assert_eq!(result, expected, "Failed for payload: {}", payload);
         }
     }
@@ -1299,15 +2703,17 @@ mod tests {
         let clean_content = "This is safe content";
 
         let post_id = generate_post_id("clean-fallback", &storage).unwrap();
-        let rendered_content = parser::render_markdown(clean_content);
+        let rendered_content = nonograph_markup::render_markdown(clean_content);
 
         let post = Post {
             id: post_id.clone(),
-            title: parser::sanitize_text(&malicious_title),
-            author: parser::sanitize_text(&malicious_author),
+            title: nonograph_markup::sanitize_text(&malicious_title),
+            author: nonograph_markup::sanitize_text(&malicious_author),
             content: rendered_content,
             raw_content: clean_content.to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         assert_eq!(post.title, "Clean Title");
@@ -1324,6 +2730,8 @@ mod tests {
             content: "Test content".to_string(),
             raw_content: "Test content".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         {
@@ -1364,6 +2772,8 @@ mod tests {
                     content: "Content".to_string(),
                     raw_content: "Content".to_string(),
                     created_at: now,
+                    password_hash: None,
+                    kind: PostKind::Markdown,
                 };
                 posts.insert(id, post);
             }
@@ -1443,9 +2853,11 @@ mod tests {
             id: post_id.clone(),
             title: emoji_title.to_string(),
             author: "🍆".to_string(),
-            content: parser::render_markdown(&emoji_content),
+            content: nonograph_markup::render_markdown(&emoji_content),
             raw_content: emoji_content.clone(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let description = if post.raw_content.chars().count() > 160 {
@@ -1467,17 +2879,17 @@ mod tests {
     #[test]
     fn test_emoji_parsing_edge_cases() {
         let emoji_content = "🎯";
-        let _result = parser::render_markdown(emoji_content);
+        let _result = nonograph_markup::render_markdown(emoji_content);
 
         let empty_content = "";
-        let _empty_result = parser::render_markdown(empty_content);
+        let _empty_result = nonograph_markup::render_markdown(empty_content);
 
         let single_char = "A";
-        let single_result = parser::render_markdown(single_char);
+        let single_result = nonograph_markup::render_markdown(single_char);
         assert!(single_result.contains("A"));
 
         let boundary_content = "AB";
-        let boundary_result = parser::render_markdown(boundary_content);
+        let boundary_result = nonograph_markup::render_markdown(boundary_content);
         assert!(boundary_result.contains("AB"));
 
         let storage = Arc::new(Mutex::new(PostCache::new(128)));
@@ -2012,11 +3424,13 @@ mod tests {
         for (id, title, author) in &cases {
             let post = Post {
                 id: id.to_string(),
-                title: parser::sanitize_text(title),
-                author: parser::sanitize_text(author),
+                title: nonograph_markup::sanitize_text(title),
+                author: nonograph_markup::sanitize_text(author),
                 content: String::new(),
                 raw_content: "Content".to_string(),
                 created_at: Utc::now(),
+                password_hash: None,
+                kind: PostKind::Markdown,
             };
 
             save::save_post_to_file_in_dir(&post, temp_path).unwrap();
@@ -2039,13 +3453,13 @@ mod tests {
                 parse_yaml_frontmatter(&file_content).unwrap();
             assert_eq!(
                 parsed_title,
-                parser::sanitize_text(title),
+                nonograph_markup::sanitize_text(title),
                 "title round-trip failed for {}",
                 id
             );
             assert_eq!(
                 parsed_author,
-                parser::sanitize_text(author),
+                nonograph_markup::sanitize_text(author),
                 "author round-trip failed for {}",
                 id
             );
@@ -2064,9 +3478,11 @@ mod tests {
             id: post_id.clone(),
             title: emoji_title.to_string(),
             author: "test".to_string(),
-            content: parser::render_markdown(&long_emoji_content),
+            content: nonograph_markup::render_markdown(&long_emoji_content),
             raw_content: long_emoji_content.clone(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let description = if post.raw_content.chars().count() > 160 {
@@ -2089,9 +3505,11 @@ mod tests {
             id: post_id2.clone(),
             title: ascii_title.to_string(),
             author: "test".to_string(),
-            content: parser::render_markdown(&long_ascii_content),
+            content: nonograph_markup::render_markdown(&long_ascii_content),
             raw_content: long_ascii_content.clone(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let description2 = if post2.raw_content.chars().count() > 160 {
@@ -2213,6 +3631,8 @@ mod tests {
             content: "<p>Content</p>".to_string(),
             raw_content: "Content".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let alias_display = if post_with_alias.author.is_empty() {
@@ -2230,6 +3650,8 @@ mod tests {
             content: "<p>Content</p>".to_string(),
             raw_content: "Content".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let alias_display_empty = if post_without_alias.author.is_empty() {
@@ -2480,4 +3902,55 @@ mod tests {
             r#"href="https://github.com/du82/nonograph" target="_blank">source code</a>"#
         ));
     }
+
+    #[test]
+    fn test_post_plain_text_and_json() {
+        let mut post = Post {
+            id: "terminal-01-01-2025".to_string(),
+            title: "Terminal".to_string(),
+            author: String::new(),
+            content: "<p>Read <a href=\"https://example.com\">this</a></p>".to_string(),
+            raw_content: "Read [this](https://example.com)".to_string(),
+            created_at: chrono::TimeZone::with_ymd_and_hms(&Utc, 2025, 1, 1, 0, 0, 0).unwrap(),
+            password_hash: None,
+            kind: PostKind::Markdown,
+        };
+
+        assert_eq!(
+            post_plain_text(&post),
+            "Terminal\n========\nAnonymous · January 01, 2025\n\nRead this [1]\n\nReferences\n----------\n[1] https://example.com\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&post_json(&post)).unwrap();
+        assert_eq!(json["id"], "terminal-01-01-2025");
+        assert_eq!(json["created_at"], "2025-01-01T00:00:00+00:00");
+        assert_eq!(json["content"], "Read [this](https://example.com)");
+        assert!(json["html"].as_str().unwrap().contains("<a href"));
+
+        post.kind = PostKind::Encrypted;
+        assert!(post_plain_text(&post).ends_with(ENCRYPTED_NOTICE_TEXT));
+        let json: serde_json::Value = serde_json::from_str(&post_json(&post)).unwrap();
+        assert_eq!(json["encrypted"], true);
+        assert!(json["html"].is_null());
+    }
+
+    #[test]
+    fn test_negotiated_responses_vary() {
+        use rocket::local::blocking::Client;
+
+        let client = Client::untracked(rocket()).unwrap();
+        let response = client
+            .get("/missing-01-01-2026")
+            .header(Header::new("Accept", "text/plain"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.headers().get_one("Vary"),
+            Some("Accept, User-Agent")
+        );
+
+        let response = client.get("/missing-01-01-2026.txt").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.headers().get_one("Vary"), None);
+    }
 }
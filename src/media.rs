@@ -0,0 +1,354 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::Request;
+use sha2::{Digest, Sha256};
+
+use crate::config::Archiver;
+use crate::fetch::{SafeFetcher, ARCHIVABLE_MEDIA_TYPES};
+
+/// File extension each archivable media type is stored under
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+];
+
+/// Local copies of images and videos from archived pages. Files are named
+/// after the SHA-256 of their contents, so media shared between pages is
+/// stored once and a name never points at different bytes.
+pub struct MediaStore {
+    dir: PathBuf,
+    max_bytes: usize,
+}
+
+impl MediaStore {
+    pub fn new(config: &Archiver) -> Self {
+        Self {
+            dir: PathBuf::from(&config.media_dir),
+            max_bytes: config.max_media_size_kb * 1024,
+        }
+    }
+
+    /// Downloads `url` into the store and returns the path it's served at.
+    pub async fn localise(&self, fetcher: &SafeFetcher, url: &str) -> Result<String, String> {
+        let (media_type, data) = fetcher
+            .get_media(url, ARCHIVABLE_MEDIA_TYPES, self.max_bytes)
+            .await?;
+        let file_name = self.store(&media_type, &data)?;
+        Ok(format!("/media/{}", file_name))
+    }
+
+    fn store(&self, media_type: &str, data: &[u8]) -> Result<String, String> {
+        let (_, extension) = EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == media_type)
+            .ok_or_else(|| format!("Cannot store media of type {}", media_type))?;
+        let file_name = format!("{:x}.{}", Sha256::digest(data), extension);
+
+        let path = self.dir.join(&file_name);
+        if !path.exists() {
+            fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+            // Renamed into place so a reader never sees half a file
+            let partial = self.dir.join(format!("{}.partial", file_name));
+            fs::write(&partial, data)
+                .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+            fs::rename(&partial, &path)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+
+        Ok(file_name)
+    }
+}
+
+/// Content type for a name the store could have written, `None` for
+/// anything else so arbitrary files in the directory are never served.
+pub fn content_type_for(file_name: &str) -> Option<ContentType> {
+    let (hash, extension) = file_name.split_once('.')?;
+    let is_digest = hash.len() == 64
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if !is_digest {
+        return None;
+    }
+
+    let (media_type, _) = EXTENSIONS.iter().find(|(_, known)| *known == extension)?;
+    ContentType::parse_flexible(media_type)
+}
+
+/// Where the file behind a `/media/<name>` URL is stored, for names the
+/// store could have written.
+pub fn local_path(media_dir: &str, url: &str) -> Option<PathBuf> {
+    let file_name = url.strip_prefix("/media/")?;
+    content_type_for(file_name)?;
+    Some(Path::new(media_dir).join(file_name))
+}
+
+/// Names of the stored files that `html` embeds or links to
+pub fn referenced_files(html: &str) -> BTreeSet<String> {
+    html.split("\"/media/")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .filter(|name| content_type_for(name).is_some())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    Whole,
+    /// First and last byte, inclusive
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+/// Reads a `Range` header for a file of `len` bytes. Multiple ranges and
+/// anything malformed get the whole file, which the spec allows.
+fn byte_range(header: Option<&str>, len: u64) -> ByteRange {
+    let Some((first, last)) = header
+        .and_then(|header| header.trim().strip_prefix("bytes="))
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return ByteRange::Whole;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    let (start, end) = if first.is_empty() {
+        // The last `n` bytes
+        match last.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            Err(_) => return ByteRange::Whole,
+        }
+    } else {
+        let Ok(start) = first.parse::<u64>() else {
+            return ByteRange::Whole;
+        };
+        let end = match last {
+            "" => len.saturating_sub(1),
+            last => match last.parse::<u64>() {
+                Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+                _ => return ByteRange::Whole,
+            },
+        };
+        (start, end)
+    };
+
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Part(start, end)
+    }
+}
+
+/// An archived media file streamed from disk. A single byte range is
+/// honoured so videos can seek.
+pub struct MediaFile {
+    file: NamedFile,
+    len: u64,
+    content_type: ContentType,
+}
+
+impl MediaFile {
+    pub async fn open(path: &Path, content_type: ContentType) -> Option<Self> {
+        let file = NamedFile::open(path).await.ok()?;
+        let len = file.metadata().await.ok()?.len();
+        Some(Self {
+            file,
+            len,
+            content_type,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for MediaFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let range = byte_range(request.headers().get_one("Range"), self.len);
+        let mut response = match range {
+            ByteRange::Whole => self.file.respond_to(request)?,
+            ByteRange::Part(start, end) => {
+                // Nothing has touched the file yet, so it converts back and
+                // forth without waiting
+                let mut file = self
+                    .file
+                    .take_file()
+                    .try_into_std()
+                    .map_err(|_| Status::InternalServerError)?;
+                file.seek(SeekFrom::Start(start))
+                    .map_err(|_| Status::InternalServerError)?;
+                Response::build()
+                    .status(Status::PartialContent)
+                    .raw_header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, self.len),
+                    )
+                    .streamed_body(File::from_std(file).take(end - start + 1))
+                    .finalize()
+            }
+            ByteRange::Unsatisfiable => Response::build()
+                .status(Status::RangeNotSatisfiable)
+                .raw_header("Content-Range", format!("bytes */{}", self.len))
+                .finalize(),
+        };
+        response.set_header(self.content_type);
+        response.set_raw_header("Accept-Ranges", "bytes");
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_store_is_content_addressed() {
+        let dir = tempdir().unwrap();
+        let store = MediaStore::new(&Archiver {
+            media_dir: dir.path().to_string_lossy().into_owned(),
+            ..Archiver::default()
+        });
+
+        let name = store.store("image/png", b"png bytes").unwrap();
+        assert_eq!(name, format!("{:x}.png", Sha256::digest(b"png bytes")));
+        assert_eq!(store.store("image/png", b"png bytes").unwrap(), name);
+        assert_eq!(fs::read(dir.path().join(&name)).unwrap(), b"png bytes");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert!(store.store("image/svg+xml", b"<svg/>").is_err());
+    }
+
+    #[test]
+    fn test_content_type_for() {
+        let hash = format!("{:x}", Sha256::digest(b"x"));
+        assert_eq!(
+            content_type_for(&format!("{}.mp4", hash)),
+            Some(ContentType::new("video", "mp4"))
+        );
+        assert_eq!(
+            content_type_for(&format!("{}.jpg", hash)),
+            Some(ContentType::JPEG)
+        );
+        assert_eq!(content_type_for(&format!("{}.html", hash)), None);
+        assert_eq!(
+            content_type_for(&format!("{}.png", hash.to_uppercase())),
+            None
+        );
+        assert_eq!(content_type_for("config.png"), None);
+        assert_eq!(content_type_for(&format!("{}.png.partial", hash)), None);
+    }
+
+    #[test]
+    fn test_local_path_and_references() {
+        let hash = format!("{:x}", Sha256::digest(b"x"));
+        assert_eq!(
+            local_path("media", &format!("/media/{}.png", hash)),
+            Some(Path::new("media").join(format!("{}.png", hash)))
+        );
+        assert_eq!(local_path("media", "/media/../Config.toml"), None);
+        assert_eq!(
+            local_path("media", &format!("https://example.com/media/{}.png", hash)),
+            None
+        );
+
+        let html = format!(
+            "<img src=\"/media/{0}.png\"><a href=\"/media/{0}.mp4\">v</a><a href=\"/media/notes.txt\">n</a>",
+            hash
+        );
+        assert_eq!(
+            referenced_files(&html),
+            BTreeSet::from([format!("{}.mp4", hash), format!("{}.png", hash)])
+        );
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(None, 100), ByteRange::Whole);
+        assert_eq!(byte_range(Some("bytes=0-"), 100), ByteRange::Part(0, 99));
+        assert_eq!(
+            byte_range(Some("bytes=10-19"), 100),
+            ByteRange::Part(10, 19)
+        );
+        assert_eq!(
+            byte_range(Some("bytes=90-200"), 100),
+            ByteRange::Part(90, 99)
+        );
+        assert_eq!(byte_range(Some("bytes=-10"), 100), ByteRange::Part(90, 99));
+        assert_eq!(byte_range(Some("bytes=-200"), 100), ByteRange::Part(0, 99));
+        assert_eq!(
+            byte_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(byte_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range(Some("bytes=0-1,5-6"), 100), ByteRange::Whole);
+        assert_eq!(byte_range(Some("bytes=5-1"), 100), ByteRange::Whole);
+        assert_eq!(byte_range(Some("items=0-1"), 100), ByteRange::Whole);
+    }
+
+    #[rocket::get("/<path>")]
+    async fn serve(path: &str) -> Option<MediaFile> {
+        MediaFile::open(Path::new(path), ContentType::new("video", "mp4")).await
+    }
+
+    #[rocket::async_test]
+    async fn test_media_file_serves_ranges() {
+        use rocket::http::Header;
+        use rocket::local::asynchronous::Client;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        fs::write(&path, b"0123456789").unwrap();
+        let uri = format!(
+            "/{}",
+            rocket::http::RawStr::new(&path.to_string_lossy()).percent_encode()
+        );
+        let client = Client::untracked(rocket::build().mount("/", rocket::routes![serve]))
+            .await
+            .unwrap();
+
+        let response = client.get(uri.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("video", "mp4"))
+        );
+        assert_eq!(response.into_bytes().await.unwrap(), b"0123456789");
+
+        let response = client
+            .get(uri.clone())
+            .header(Header::new("Range", "bytes=2-5"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(
+            response.headers().get_one("Content-Range"),
+            Some("bytes 2-5/10")
+        );
+        assert_eq!(response.into_bytes().await.unwrap(), b"2345");
+
+        let response = client
+            .get(uri)
+            .header(Header::new("Range", "bytes=10-"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+        assert_eq!(
+            response.headers().get_one("Content-Range"),
+            Some("bytes */10")
+        );
+    }
+}
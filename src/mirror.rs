@@ -0,0 +1,224 @@
+use std::path::Path;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::config::Mirror;
+use crate::fetch::SafeFetcher;
+use crate::{frontmatter_field, parse_yaml_frontmatter, save, Post, PostKind};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Mirrored(String),
+    /// Already mirrored; holds the local post's URL
+    Skipped(String),
+    Failed(String),
+}
+
+/// Copies posts from the instance at `base_url` into `<base_dir>/content`,
+/// keeping their ids and recording where they came from in `mirrored_from`.
+/// Results are in the order of `ids`.
+pub async fn mirror_posts(
+    base_url: &str,
+    ids: &[String],
+    proxy: &str,
+    base_dir: &Path,
+) -> Result<Vec<(String, Outcome)>, String> {
+    // Without the trailing slash, joining an id would replace the last segment
+    let base = Url::parse(&format!("{}/", base_url.trim_end_matches('/')))
+        .map_err(|e| format!("Invalid instance URL {}: {}", base_url, e))?;
+    if !matches!(base.scheme(), "http" | "https") {
+        return Err(format!("{} is not an http(s) URL", base_url));
+    }
+
+    // Peers are chosen by the operator, so they may be local like a
+    // self-hosted Telegraph API
+    let mut fetcher = SafeFetcher::new().trusting(&base);
+    if !proxy.is_empty() {
+        fetcher = fetcher.with_proxy(proxy);
+    }
+
+    let mut results = Vec::new();
+    for id in ids {
+        let outcome = match mirror_post(&fetcher, &base, id, base_dir).await {
+            Ok(outcome) => outcome,
+            Err(e) => Outcome::Failed(e),
+        };
+        results.push((id.clone(), outcome));
+    }
+    Ok(results)
+}
+
+async fn mirror_post(
+    fetcher: &SafeFetcher,
+    base: &Url,
+    id: &str,
+    base_dir: &Path,
+) -> Result<Outcome, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("{} is not a post id", id));
+    }
+    let origin = base
+        .join(id)
+        .map_err(|e| format!("Invalid post URL for {}: {}", id, e))?
+        .to_string();
+
+    let local = base_dir.join("content").join(format!("{}.md", id));
+    if let Ok(existing) = std::fs::read_to_string(&local) {
+        return if frontmatter_field(&existing, "mirrored_from").as_deref() == Some(origin.as_str())
+        {
+            Ok(Outcome::Skipped(format!("/{}", id)))
+        } else {
+            Err(format!("A different post is already saved as /{}", id))
+        };
+    }
+
+    let file_content = fetcher.get_text(&format!("{}.md", origin)).await?;
+    let (title, author, created_at, raw_content) = parse_yaml_frontmatter(&file_content)
+        .ok_or_else(|| format!("{}.md has no frontmatter", origin))?;
+    let kind = match frontmatter_field(&file_content, "kind").as_deref() {
+        Some("encrypted") => PostKind::Encrypted,
+        _ => PostKind::Markdown,
+    };
+
+    let post = Post {
+        id: id.to_string(),
+        title,
+        author,
+        content: String::new(),
+        raw_content,
+        created_at,
+        password_hash: None,
+        kind,
+    };
+    save::save_post_with_fields_in_dir(
+        &post,
+        &[("mirrored_from", origin)],
+        &base_dir.to_string_lossy(),
+    )?;
+
+    Ok(Outcome::Mirrored(format!("/{}", id)))
+}
+
+/// Mirrors every post listed for every peer. Results are keyed by the
+/// post's URL on the peer.
+pub async fn sync(config: &Mirror, base_dir: &Path) -> Vec<(String, Outcome)> {
+    let mut results = Vec::new();
+    for peer in &config.peers {
+        let peer_url = peer.url.trim_end_matches('/');
+        match mirror_posts(peer_url, &peer.posts, &config.proxy, base_dir).await {
+            Ok(outcomes) => results.extend(
+                outcomes
+                    .into_iter()
+                    .map(|(id, outcome)| (format!("{}/{}", peer_url, id), outcome)),
+            ),
+            Err(e) => results.push((peer_url.to_string(), Outcome::Failed(e))),
+        }
+    }
+    results
+}
+
+/// Runs `sync` every `sync_interval_mins` for as long as the server runs.
+pub async fn sync_periodically(config: Mirror) {
+    let interval = Duration::from_secs(config.sync_interval_mins.max(1) * 60);
+    loop {
+        for (url, outcome) in sync(&config, Path::new(".")).await {
+            match outcome {
+                Outcome::Mirrored(post) => println!("Mirrored {} as {}", url, post),
+                Outcome::Failed(e) => eprintln!("Failed to mirror {}: {}", url, e),
+                Outcome::Skipped(_) => {}
+            }
+        }
+        rocket::tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Peer;
+    use tempfile::tempdir;
+
+    /// Starts a second instance on a free port, serving the repo's content.
+    async fn second_instance() -> String {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let rocket = crate::rocket();
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("port", port))
+            .merge(("log_level", "off"));
+        rocket::tokio::spawn(rocket.configure(figment).launch());
+
+        let base = format!("http://127.0.0.1:{}", port);
+        let fetcher = SafeFetcher::new().trusting(&Url::parse(&base).unwrap());
+        for _ in 0..50 {
+            if fetcher.get(&format!("{}/about.md", base)).await.is_ok() {
+                return base;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("second instance didn't start");
+    }
+
+    #[rocket::async_test]
+    async fn test_mirror_from_second_instance() {
+        let base = second_instance().await;
+        let dir = tempdir().unwrap();
+        let ids = vec![
+            "about".to_string(),
+            "missing-01-01-2026".to_string(),
+            "../about".to_string(),
+        ];
+
+        let results = mirror_posts(&format!("{}/", base), &ids, "", dir.path())
+            .await
+            .unwrap();
+        assert_eq!(results[0].1, Outcome::Mirrored("/about".to_string()));
+        assert!(matches!(&results[1].1, Outcome::Failed(e) if e.contains("404")));
+        assert_eq!(
+            results[2].1,
+            Outcome::Failed("../about is not a post id".to_string())
+        );
+
+        let saved = std::fs::read_to_string(dir.path().join("content/about.md")).unwrap();
+        let original = std::fs::read_to_string("content/about.md").unwrap();
+        assert_eq!(
+            parse_yaml_frontmatter(&saved),
+            parse_yaml_frontmatter(&original)
+        );
+        assert_eq!(
+            frontmatter_field(&saved, "mirrored_from"),
+            Some(format!("{}/about", base))
+        );
+
+        // Rerunning leaves the copy alone, as does syncing it from a peer list
+        let config = Mirror {
+            peers: vec![Peer {
+                url: base.clone(),
+                posts: vec!["about".to_string()],
+            }],
+            ..Mirror::default()
+        };
+        assert_eq!(
+            sync(&config, dir.path()).await,
+            vec![(
+                format!("{}/about", base),
+                Outcome::Skipped("/about".to_string())
+            )]
+        );
+
+        // A local post with the same id is never overwritten
+        let other = tempdir().unwrap();
+        std::fs::create_dir(other.path().join("content")).unwrap();
+        std::fs::write(other.path().join("content/about.md"), &original).unwrap();
+        let results = mirror_posts(&base, &ids[..1], "", other.path())
+            .await
+            .unwrap();
+        assert!(matches!(&results[0].1, Outcome::Failed(e) if e.contains("different post")));
+    }
+}
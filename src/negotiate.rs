@@ -0,0 +1,171 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::Request;
+
+/// Representation of a post picked from the URL suffix, the `Accept` header
+/// or, for clients that accept anything, the `User-Agent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostFormat {
+    Html,
+    /// Wrapped plain text with numbered link references
+    Text,
+    Json,
+    /// The stored markdown file
+    Markdown,
+}
+
+/// Command-line clients that get plain text when they don't ask for anything
+const TERMINAL_AGENTS: &[&str] = &["curl/", "wget/", "httpie/", "xh/", "fetch libfetch"];
+
+impl PostFormat {
+    /// Splits an explicit `.html`, `.txt`, `.json` or `.md` suffix off a
+    /// post id.
+    pub fn from_suffix(post_id: &str) -> (&str, Option<PostFormat>) {
+        for (suffix, format) in [
+            (".html", PostFormat::Html),
+            (".txt", PostFormat::Text),
+            (".json", PostFormat::Json),
+            (".md", PostFormat::Markdown),
+        ] {
+            if let Some(id) = post_id.strip_suffix(suffix) {
+                return (id, Some(format));
+            }
+        }
+        (post_id, None)
+    }
+
+    /// Picks the format with the highest quality in `Accept`. Wildcards and
+    /// a missing header fall back to plain text for terminal clients and
+    /// HTML for everyone else.
+    pub fn negotiate(accept: Option<&str>, user_agent: Option<&str>) -> PostFormat {
+        let mut best: Option<(PostFormat, f32)> = None;
+
+        for range in accept.unwrap_or_default().split(',') {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let format = match media_type.as_str() {
+                "text/html" | "application/xhtml+xml" => PostFormat::Html,
+                "text/plain" => PostFormat::Text,
+                "application/json" => PostFormat::Json,
+                "text/markdown" | "text/x-markdown" => PostFormat::Markdown,
+                _ => continue,
+            };
+            // Earlier entries win ties
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((format, quality));
+            }
+        }
+
+        if let Some((format, _)) = best {
+            return format;
+        }
+
+        let user_agent = user_agent.unwrap_or_default().to_ascii_lowercase();
+        if TERMINAL_AGENTS
+            .iter()
+            .any(|agent| user_agent.starts_with(agent))
+        {
+            PostFormat::Text
+        } else {
+            PostFormat::Html
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PostFormat {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        Outcome::Success(PostFormat::negotiate(
+            headers.get_one("Accept"),
+            headers.get_one("User-Agent"),
+        ))
+    }
+}
+
+/// A response whose body may depend on the request's `Accept` and
+/// `User-Agent`. Caches are told so with `Vary`, unless the URL picked the
+/// format itself.
+pub struct Negotiated<R> {
+    pub response: R,
+    pub negotiated: bool,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Negotiated<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.response.respond_to(request)?;
+        if self.negotiated {
+            response.set_raw_header("Vary", "Accept, User-Agent");
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_suffix() {
+        assert_eq!(
+            PostFormat::from_suffix("post-01-01-2025.txt"),
+            ("post-01-01-2025", Some(PostFormat::Text))
+        );
+        assert_eq!(
+            PostFormat::from_suffix("post-01-01-2025.json"),
+            ("post-01-01-2025", Some(PostFormat::Json))
+        );
+        assert_eq!(
+            PostFormat::from_suffix("post-01-01-2025.md"),
+            ("post-01-01-2025", Some(PostFormat::Markdown))
+        );
+        assert_eq!(
+            PostFormat::from_suffix("post-01-01-2025.html"),
+            ("post-01-01-2025", Some(PostFormat::Html))
+        );
+        assert_eq!(
+            PostFormat::from_suffix("post-01-01-2025"),
+            ("post-01-01-2025", None)
+        );
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        assert_eq!(
+            PostFormat::negotiate(Some(browser), Some("Mozilla/5.0")),
+            PostFormat::Html
+        );
+        assert_eq!(
+            PostFormat::negotiate(Some("*/*"), Some("curl/8.5.0")),
+            PostFormat::Text
+        );
+        assert_eq!(
+            PostFormat::negotiate(None, Some("Wget/1.21")),
+            PostFormat::Text
+        );
+        assert_eq!(
+            PostFormat::negotiate(Some("*/*"), Some("Mozilla/5.0")),
+            PostFormat::Html
+        );
+        assert_eq!(
+            PostFormat::negotiate(Some("application/json"), Some("curl/8.5.0")),
+            PostFormat::Json
+        );
+        assert_eq!(
+            PostFormat::negotiate(Some("text/html;q=0.5, text/markdown"), None),
+            PostFormat::Markdown
+        );
+        assert_eq!(
+            PostFormat::negotiate(Some("text/plain;q=0, text/html"), Some("curl/8")),
+            PostFormat::Html
+        );
+    }
+}
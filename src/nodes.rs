@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// An HTML-like tree in the shape of the Telegraph API's `Node`. Telegraph
+/// pages arrive like this and other HTML is parsed into it, so everything
+/// imported goes through the same conversion to nonograph markup.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Node {
+    Text(String),
+    Element(NodeElement),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NodeElement {
+    pub tag: String,
+    pub attrs: Option<HashMap<String, String>>,
+    pub children: Option<Vec<Node>>,
+}
+
+/// Converts `nodes` to nonograph markup. Link targets and media sources go
+/// through `resolve_url`, e.g. to make relative ones absolute.
+pub fn to_markdown(nodes: &[Node], resolve_url: &dyn Fn(&str) -> String) -> String {
+    let converter = Converter { resolve_url };
+    let mut markdown = String::new();
+    for node in nodes {
+        converter.convert(node, &mut markdown, 0, None);
+    }
+
+    clean_excessive_newlines(&markdown)
+}
+
+struct Converter<'a> {
+    resolve_url: &'a dyn Fn(&str) -> String,
+}
+
+impl Converter<'_> {
+    fn convert(&self, node: &Node, output: &mut String, depth: usize, image_caption: Option<&str>) {
+        match node {
+            Node::Text(text) => {
+                output.push_str(text);
+            }
+            Node::Element(element) => {
+                match element.tag.as_str() {
+                    "p" => {
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("\n\n");
+                    }
+                    "br" => {
+                        output.push('\n');
+                    }
+                    "strong" | "b" => {
+                        output.push_str("**");
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("**");
+                    }
+                    "em" | "i" => {
+                        output.push('*');
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push('*');
+                    }
+                    "u" => {
+                        output.push('_');
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push('_');
+                    }
+                    "s" => {
+                        output.push('~');
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push('~');
+                    }
+                    "code" => {
+                        output.push('`');
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push('`');
+                    }
+                    "pre" => {
+                        output.push_str("```\n");
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("\n```\n\n");
+                    }
+                    "a" => {
+                        let href = element.attrs.as_ref().and_then(|attrs| attrs.get("href"));
+                        if let Some(href) = href {
+                            output.push('[');
+                            if let Some(children) = &element.children {
+                                for child in children {
+                                    self.convert(child, output, depth, image_caption);
+                                }
+                            } else {
+                                output.push_str(href);
+                            }
+                            output.push_str(&format!("]({})", (self.resolve_url)(href)));
+                        } else if let Some(children) = &element.children {
+                            // Anchors without a target, or whose unsafe one was removed, keep their text
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                    }
+                    "img" => {
+                        if let Some(attrs) = &element.attrs {
+                            if let Some(src) = attrs.get("src") {
+                                let full_url = (self.resolve_url)(src);
+                                let caption = image_caption.unwrap_or("");
+                                output.push_str(&format!("![{}]({})", caption, full_url));
+                            }
+                        }
+                    }
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                        output.push_str(heading_marker(&element.tag));
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("\n\n");
+                    }
+                    "blockquote" => {
+                        output.push_str("> ");
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("\n\n");
+                    }
+                    "ul" => {
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push('\n');
+                    }
+                    "ol" => {
+                        if let Some(children) = &element.children {
+                            for (i, child) in children.iter().enumerate() {
+                                if let Node::Element(li) = child {
+                                    if li.tag == "li" {
+                                        output.push_str(&format!("{}. ", i + 1));
+                                        if let Some(li_children) = &li.children {
+                                            for li_child in li_children {
+                                                self.convert(
+                                                    li_child,
+                                                    output,
+                                                    depth,
+                                                    image_caption,
+                                                );
+                                            }
+                                        }
+                                        output.push('\n');
+                                    }
+                                }
+                            }
+                        }
+                        output.push('\n');
+                    }
+                    "li" => {
+                        // Handle unordered list items
+                        if depth == 0 {
+                            output.push_str("- ");
+                        }
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth + 1, image_caption);
+                            }
+                        }
+                        if depth == 0 {
+                            output.push('\n');
+                        }
+                    }
+                    "hr" => {
+                        output.push_str("---\n\n");
+                    }
+                    "figure" => {
+                        // Extract caption from figcaption first
+                        let mut caption = String::new();
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                if let Node::Element(elem) = child {
+                                    if elem.tag == "figcaption" {
+                                        if let Some(caption_children) = &elem.children {
+                                            for caption_child in caption_children {
+                                                if let Node::Text(text) = caption_child {
+                                                    caption.push_str(text);
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        // Now process all children with the caption context
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                if let Node::Element(elem) = child {
+                                    if elem.tag != "figcaption" {
+                                        let caption_ref = if caption.is_empty() {
+                                            None
+                                        } else {
+                                            Some(caption.as_str())
+                                        };
+                                        self.convert(child, output, depth, caption_ref);
+                                    }
+                                }
+                            }
+                        }
+                        output.push('\n');
+                    }
+                    "figcaption" => {
+                        // Figcaptions are now handled by the figure element above
+                        // Skip processing them directly to avoid duplication
+                    }
+                    "iframe" | "video" => {
+                        if let Some(attrs) = &element.attrs {
+                            if let Some(src) = attrs.get("src") {
+                                let full_url = (self.resolve_url)(src);
+                                let caption = image_caption.unwrap_or("Video");
+                                output.push_str(&format!("![{}]({})\n\n", caption, full_url));
+                            }
+                        }
+                    }
+                    "aside" => {
+                        // Convert aside to blockquote
+                        output.push_str("> ");
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                        output.push_str("\n\n");
+                    }
+                    _ => {
+                        // For unhandled tags, just process children
+                        if let Some(children) = &element.children {
+                            for child in children {
+                                self.convert(child, output, depth, image_caption);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Telegraph only has h3 and h4; markup headings go down to `####`.
+fn heading_marker(tag: &str) -> &'static str {
+    match tag {
+        "h1" => "# ",
+        "h2" => "## ",
+        "h3" => "### ",
+        _ => "#### ",
+    }
+}
+
+fn clean_excessive_newlines(content: &str) -> String {
+    // Replace 3+ consecutive newlines with just 2
+    let mut result = content.to_string();
+    while result.contains("\n\n\n") {
+        result = result.replace("\n\n\n", "\n\n");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown_headings_and_links() {
+        let nodes: Vec<Node> = serde_json::from_str(
+            r#"[
+                {"tag": "h2", "children": ["Part one"]},
+                {"tag": "h6", "children": ["Aside"]},
+                {"tag": "p", "children": [{"tag": "a", "attrs": {"href": "/next"}, "children": ["Next"]}]}
+            ]"#,
+        )
+        .unwrap();
+
+        let markdown = to_markdown(&nodes, &|url| format!("https://example.com{}", url));
+        assert_eq!(
+            markdown,
+            "## Part one\n\n#### Aside\n\n[Next](https://example.com/next)\n\n"
+        );
+    }
+}
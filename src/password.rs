@@ -0,0 +1,104 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::Rng;
+use rocket::tokio::sync::Semaphore;
+
+pub const PASSWORD_MAX_LENGTH: usize = 128;
+
+/// Argon2 runs allowed at once. Each one holds about 19 MiB, so anonymous
+/// unlock attempts can't run the server out of memory.
+const MAX_CONCURRENT_HASHES: usize = 8;
+
+/// Error code for when every Argon2 slot is taken
+pub const PASSWORD_BUSY: &str = "password_busy";
+
+static HASH_PERMITS: Semaphore = Semaphore::const_new(MAX_CONCURRENT_HASHES);
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt_bytes: [u8; 16] = rand::thread_rng().gen();
+    let salt = SaltString::encode_b64(&salt_bytes)
+        .map_err(|e| format!("Failed to encode password salt: {}", e))?;
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Runs an Argon2 job on the blocking pool. Fails with `PASSWORD_BUSY`
+/// straight away when too many are already running, rather than queueing.
+async fn bounded<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    let _permit = HASH_PERMITS
+        .try_acquire()
+        .map_err(|_| PASSWORD_BUSY.to_string())?;
+    rocket::tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| format!("Password job failed: {}", e))
+}
+
+/// `hash_password` off the async workers, within the Argon2 limit.
+pub async fn hash_password_bounded(password: &str) -> Result<String, String> {
+    let password = password.to_string();
+    bounded(move || hash_password(&password)).await?
+}
+
+/// `verify_password` off the async workers, within the Argon2 limit.
+pub async fn verify_password_bounded(password: &str, hash: &str) -> Result<bool, String> {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    bounded(move || verify_password(&password, &hash)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+
+        // Same password, fresh salt
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+    }
+
+    #[rocket::async_test]
+    #[serial_test::serial(argon2)]
+    async fn test_bounded_jobs_fail_fast_when_busy() {
+        let hash = hash_password_bounded("correct horse").await.unwrap();
+        assert_eq!(
+            verify_password_bounded("correct horse", &hash).await,
+            Ok(true)
+        );
+
+        let held = HASH_PERMITS
+            .acquire_many(MAX_CONCURRENT_HASHES as u32)
+            .await
+            .unwrap();
+        assert_eq!(
+            verify_password_bounded("correct horse", &hash).await,
+            Err(PASSWORD_BUSY.to_string())
+        );
+        drop(held);
+        assert_eq!(
+            verify_password_bounded("wrong horse", &hash).await,
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_malformed_hash() {
+        assert!(!verify_password("anything", ""));
+        assert!(!verify_password("anything", "not-a-hash"));
+    }
+}
@@ -1,13 +1,27 @@
 use std::fs;
 use std::path::Path;
 
-use crate::Post;
+use crate::{Post, PostKind};
 
 pub fn save_post_to_file(post: &Post) -> Result<(), String> {
     save_post_to_file_in_dir(post, ".")
 }
 
 pub fn save_post_to_file_in_dir(post: &Post, base_dir: &str) -> Result<(), String> {
+    save_post_with_fields_in_dir(post, &[], base_dir)
+}
+
+/// Saves a post with extra frontmatter fields (such as an archived page's
+/// `source`) written after the standard ones.
+pub fn save_post_with_fields(post: &Post, fields: &[(&str, String)]) -> Result<(), String> {
+    save_post_with_fields_in_dir(post, fields, ".")
+}
+
+pub fn save_post_with_fields_in_dir(
+    post: &Post,
+    fields: &[(&str, String)],
+    base_dir: &str,
+) -> Result<(), String> {
     let content_dir = Path::new(base_dir).join("content");
     if !content_dir.exists() {
         fs::create_dir_all(&content_dir)
@@ -23,6 +37,16 @@ pub fn save_post_to_file_in_dir(post: &Post, base_dir: &str) -> Result<(), Strin
     if !post.author.is_empty() {
         frontmatter.push_str(&format!("author: {}\n", post.author));
     }
+    if let Some(password_hash) = &post.password_hash {
+        frontmatter.push_str(&format!("password: {}\n", password_hash));
+    }
+    if post.kind == PostKind::Encrypted {
+        frontmatter.push_str("kind: encrypted\n");
+    }
+    for (key, value) in fields {
+        // Frontmatter values are single lines
+        frontmatter.push_str(&format!("{}: {}\n", key, value.replace(['\r', '\n'], " ")));
+    }
     frontmatter.push_str(&format!(
         "generator: {} v{}\n",
         env!("CARGO_PKG_NAME"),
@@ -83,6 +107,8 @@ mod tests {
             content: "<p>Rendered content</p>".to_string(),
             raw_content: "Raw content here".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         let temp_path = temp_dir.path().to_str().unwrap();
@@ -94,6 +120,35 @@ mod tests {
         assert!(post_file_exists_in_dir("test-post-01-01-2024", temp_path));
     }
 
+    #[test]
+    #[serial]
+    fn test_save_post_with_fields() {
+        let (temp_dir, content_dir) = setup_test_env();
+
+        let post = Post {
+            id: "archived-01-01-2024".to_string(),
+            title: "Archived".to_string(),
+            author: String::new(),
+            content: String::new(),
+            raw_content: "Body".to_string(),
+            created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
+        };
+        let fields = [
+            ("source", "https://telegra.ph/Archived-01-01".to_string()),
+            ("archived", "2024-01-02\nkind: encrypted".to_string()),
+        ];
+
+        let temp_path = temp_dir.path().to_str().unwrap();
+        assert!(save_post_with_fields_in_dir(&post, &fields, temp_path).is_ok());
+
+        let saved = std::fs::read_to_string(content_dir.join("archived-01-01-2024.md")).unwrap();
+        assert!(saved.contains("\nsource: https://telegra.ph/Archived-01-01\n"));
+        assert!(saved.contains("\narchived: 2024-01-02 kind: encrypted\n"));
+        assert!(saved.ends_with("---\n\nBody"));
+    }
+
     #[test]
     #[serial]
     fn test_load_nonexistent_post() {
@@ -119,6 +174,8 @@ mod tests {
             content: "<p>Content</p>".to_string(),
             raw_content: "Content".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         // Save and verify exists
@@ -151,6 +208,8 @@ mod tests {
             content: "<p>Rendered</p>".to_string(),
             raw_content: "This is the user content\nWith multiple lines".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         assert!(save_post_to_file_in_dir(&post, temp_path).is_ok());
@@ -198,6 +257,8 @@ mod tests {
             content: "<p>Rendered</p>".to_string(),
             raw_content: "Content without author".to_string(),
             created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Markdown,
         };
 
         assert!(save_post_to_file_in_dir(&post, temp_path).is_ok());
@@ -227,4 +288,75 @@ mod tests {
         assert_eq!(lines[5], "");
         assert_eq!(lines[6], "Content without author");
     }
+
+    #[test]
+    #[serial]
+    fn test_file_format_with_password() {
+        let (temp_dir, _content_dir) = setup_test_env();
+
+        let temp_path = temp_dir.path().to_str().unwrap();
+
+        let post = Post {
+            id: "locked-test-01-01-2024".to_string(),
+            title: "Locked Test".to_string(),
+            author: "".to_string(),
+            content: "<p>Rendered</p>".to_string(),
+            raw_content: "Secret content".to_string(),
+            created_at: Utc::now(),
+            password_hash: Some("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string()),
+            kind: PostKind::Markdown,
+        };
+
+        assert!(save_post_to_file_in_dir(&post, temp_path).is_ok());
+
+        let file_path = temp_dir
+            .path()
+            .join("content")
+            .join("locked-test-01-01-2024.md");
+        let raw_file = fs::read_to_string(file_path).unwrap();
+        let lines: Vec<&str> = raw_file.lines().collect();
+
+        assert_eq!(lines[1], "title: Locked Test");
+        assert_eq!(
+            lines[3],
+            "password: $argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA"
+        );
+        assert!(lines[4].starts_with("generator: "));
+    }
+
+    #[test]
+    #[serial]
+    fn test_file_format_encrypted() {
+        let (temp_dir, _content_dir) = setup_test_env();
+
+        let temp_path = temp_dir.path().to_str().unwrap();
+
+        let post = Post {
+            id: "na-ab12-01-01-2024".to_string(),
+            title: "Encrypted post".to_string(),
+            author: "".to_string(),
+            content: String::new(),
+            raw_content: r#"{"v":1,"iv":"AAECAwQFBgcICQoL","ct":"Y2lwaGVy"}"#.to_string(),
+            created_at: Utc::now(),
+            password_hash: None,
+            kind: PostKind::Encrypted,
+        };
+
+        assert!(save_post_to_file_in_dir(&post, temp_path).is_ok());
+
+        let file_path = temp_dir
+            .path()
+            .join("content")
+            .join("na-ab12-01-01-2024.md");
+        let raw_file = fs::read_to_string(file_path).unwrap();
+        let lines: Vec<&str> = raw_file.lines().collect();
+
+        assert_eq!(lines[1], "title: Encrypted post");
+        assert_eq!(lines[3], "kind: encrypted");
+        assert!(lines[4].starts_with("generator: "));
+        assert_eq!(
+            lines[7],
+            r#"{"v":1,"iv":"AAECAwQFBgcICQoL","ct":"Y2lwaGVy"}"#
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::parser::html_attr_escape;
+use nonograph_markup::html_attr_escape;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
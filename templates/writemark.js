@@ -611,6 +611,15 @@ function getLines(value) {
   return lines;
 }
 
+// Where `offset` in `before` ended up after a single edit turned it into `after`; offsets inside the edited span move to its end.
+function mapOffsetThroughEdit(before, after, offset) {
+  let prefix = 0; const shortest = Math.min(before.length, after.length);
+  while (prefix < shortest && before[prefix] === after[prefix]) prefix++;
+  if (offset <= prefix) return offset;
+  let suffix = 0; while (suffix < shortest - prefix && before[before.length - 1 - suffix] === after[after.length - 1 - suffix]) suffix++;
+  if (offset >= before.length - suffix) return offset + after.length - before.length;
+  return after.length - suffix;
+}
 function getLineRange(value, offset) {
   const source = normalizeLineEndings(value);
   const safe = clamp(offset, 0, source.length);
@@ -1293,6 +1302,7 @@ class WritemarkEditorElement extends HTMLElement {
     this._shadow = this.attachShadow({ mode: "open", delegatesFocus: true });
     this._value = "";
     this._defaultValue = "";
+    this._renderer = null;
     this._selection = { start: 0, end: 0, direction: "none" };
     this._dirty = false;
     this._formDisabled = false;
@@ -1452,7 +1462,11 @@ class WritemarkEditorElement extends HTMLElement {
     this._providers.set(provider.id, { priority: 0, triggers: [], ...provider });
   }
   unregisterCompletionProvider(providerId) { this._providers.delete(providerId); if (this._completion.providerId === providerId) this._closeCompletion(); }
-  getHTML() { return renderMarkdown(this._value, this._rendererOptions()); }
+  // An external renderer (markdown => HTML string) replaces the built-in one
+  // for getHTML() and rich copies, e.g. the host's own parser compiled to WASM.
+  get renderer() { return this._renderer; }
+  set renderer(fn) { this._renderer = typeof fn === "function" ? fn : null; }
+  getHTML() { return this._renderHTML(this._value); }
   getText() { return textFromMarkdown(this._value, this._rendererOptions()); }
   getMarkdown() { return this._value; }
   setMarkdown(markdown) { this.value = markdown; }
@@ -3754,7 +3768,13 @@ class WritemarkEditorElement extends HTMLElement {
     event.clipboardData?.setData("text/plain", markdown);
     event.clipboardData?.setData("text/markdown", markdown);
     event.clipboardData?.setData("text/x-markdown", markdown);
-    event.clipboardData?.setData("text/html", renderMarkdown(markdown, this._rendererOptions()));
+    event.clipboardData?.setData("text/html", this._renderHTML(markdown));
+  }
+  _renderHTML(markdown) {
+    if (this._renderer) {
+      try { return String(this._renderer(markdown)); } catch (_) { /* fall back to the built-in renderer */ }
+    }
+    return renderMarkdown(markdown, this._rendererOptions());
   }
   _onLiveCopy(event) {
     if (this._isSourceActive()) return;
@@ -3874,8 +3894,23 @@ class WritemarkEditorElement extends HTMLElement {
     const { markdown, kind } = markdownFromClipboardData(clipboard);
     if (!markdown) return;
     event.preventDefault();
+    const convertUrl = this.getAttribute("html-convert-url");
+    if (kind === "html" && convertUrl) { this._convertPastedHtml(convertUrl, safeClipboardGet(clipboard, "text/html"), markdown); return; }
     this._insertPastedMarkdown(markdown, kind);
   }
+  // Lets the server convert pasted rich text; the in-browser conversion is the fallback.
+  async _convertPastedHtml(url, html, fallback) {
+    const valueBefore = this._value; const selection = this._getCurrentSelection();
+    let markdown = fallback;
+    try {
+      const response = await fetch(url, { method: "POST", headers: { "Content-Type": "application/json" }, body: JSON.stringify({ html }) });
+      if (response.ok) { const { markup } = await response.json(); if (typeof markup === "string" && markup.trim()) markdown = normalizeLineEndings(markup); }
+    } catch (error) { this._emitError("paste", error, true); }
+    // Paste where the user pasted, not wherever typing moved the cursor while waiting
+    const pasteAt = mapOffsetThroughEdit(valueBefore, this._value, selection.start); const pasteEnd = mapOffsetThroughEdit(valueBefore, this._value, selection.end);
+    this.setSelectionRange(pasteAt, Math.max(pasteAt, pasteEnd));
+    this._insertPastedMarkdown(markdown, "html");
+  }
   _onDrop(event) { if (this.disabled || this.readonly) return; const files = Array.from(event.dataTransfer?.files || []); if (!files.length) return; event.preventDefault(); const insertionPoint = this.selectionStart; this._dispatch("md-file-drop", { files, insertionPoint, insertMarkdown: markdown => this._insertPastedMarkdown(markdown, "file") }); }
 
   _getContext() {
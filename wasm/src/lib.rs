@@ -0,0 +1,54 @@
+//! WebAssembly build of `nonograph-markup`, so the editor can preview a post
+//! exactly as the server will render it without a round trip. Build with
+//! `make wasm`.
+
+use nonograph_markup::RenderOptions;
+use wasm_bindgen::prelude::*;
+
+/// Renders markup with the default options.
+#[wasm_bindgen]
+pub fn render_markdown(content: &str) -> String {
+    nonograph_markup::render_markdown(content)
+}
+
+/// Renders markup with `options`, a JSON `RenderOptions` object such as the
+/// one served at `/wasm/config.json`. Missing fields keep their defaults.
+#[wasm_bindgen]
+pub fn render_markdown_with_options(content: &str, options: &str) -> Result<String, JsError> {
+    let options = options_from_json(options).map_err(|e| JsError::new(&e))?;
+    Ok(nonograph_markup::render_markdown_with_options(
+        content, &options,
+    ))
+}
+
+/// The default options as JSON, for callers that want to see which keys
+/// `render_markdown_with_options` accepts.
+#[wasm_bindgen]
+pub fn default_options() -> String {
+    serde_json::to_string(&RenderOptions::default()).unwrap_or_default()
+}
+
+fn options_from_json(options: &str) -> Result<RenderOptions, String> {
+    if options.trim().is_empty() {
+        return Ok(RenderOptions::default());
+    }
+    serde_json::from_str(options).map_err(|e| format!("Invalid render options: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_from_json() {
+        let options =
+            options_from_json(r#"{"max_url_length": 10, "media": {"mode": "link"}}"#).unwrap();
+        assert_eq!(options.max_url_length, 10);
+        assert!(options.external_link_security);
+        assert_eq!(options.media.mode, nonograph_markup::MediaMode::Link);
+
+        assert!(options_from_json("").is_ok());
+        assert!(options_from_json("not json").is_err());
+        assert!(options_from_json(r#"{"media": {"mode": "sideways"}}"#).is_err());
+    }
+}